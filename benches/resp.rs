@@ -0,0 +1,82 @@
+use bytes::{Buf, Bytes};
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+
+use serir::resp::{OutputBuffer, Parser, Protocol, Resp};
+
+const VALUE_SIZES: [usize; 3] = [16, 1024, 64 * 1024];
+const PIPELINE: usize = 100;
+
+/// A pipeline of `SET key:<i> <value>` requests, as a client would send it.
+fn set_requests(value_size: usize) -> Bytes {
+    let value = vec![b'x'; value_size];
+    let mut requests = vec![];
+    for i in 0..PIPELINE {
+        let key = format!("key:{}", i);
+        requests.extend_from_slice(format!("*3\r\n$3\r\nSET\r\n${}\r\n", key.len()).as_bytes());
+        requests.extend_from_slice(key.as_bytes());
+        requests.extend_from_slice(format!("\r\n${}\r\n", value.len()).as_bytes());
+        requests.extend_from_slice(&value);
+        requests.extend_from_slice(b"\r\n");
+    }
+    requests.into()
+}
+
+/// Parses a pipeline of requests. Values are slices of the read buffer, so the cost
+/// shouldn't grow with their size.
+fn decode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("decode");
+    for size in VALUE_SIZES {
+        let requests = set_requests(size);
+        group.throughput(Throughput::Bytes(requests.len() as u64));
+        group.bench_with_input(
+            BenchmarkId::from_parameter(size),
+            &requests,
+            |b, requests| {
+                b.iter(|| {
+                    let mut parser = Parser::new(requests.clone());
+                    while let Some(request) = parser.parse_single_resp_object().unwrap() {
+                        criterion::black_box(request);
+                    }
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+/// Encodes the replies to a pipeline of `MGET`s of 10 values each, by copying them into a
+/// fresh `Vec` per reply like `Resp::serialize` does, and by writing them into the
+/// connection's output buffer, which shares large values instead.
+fn encode(c: &mut Criterion) {
+    let mut group = c.benchmark_group("encode");
+    for size in VALUE_SIZES {
+        let value = Bytes::from(vec![b'x'; size]);
+        let reply = Resp::Array(Some(vec![Resp::BulkString(Some(value)); 10]));
+        group.throughput(Throughput::Bytes(
+            (reply.serialize().unwrap().len() * PIPELINE) as u64,
+        ));
+        group.bench_with_input(BenchmarkId::new("serialize", size), &reply, |b, reply| {
+            b.iter(|| {
+                let mut output = vec![];
+                for _ in 0..PIPELINE {
+                    output.extend_from_slice(&reply.serialize().unwrap());
+                }
+                criterion::black_box(output)
+            })
+        });
+        let mut output = OutputBuffer::new();
+        group.bench_with_input(BenchmarkId::new("write_to", size), &reply, |b, reply| {
+            b.iter(|| {
+                for _ in 0..PIPELINE {
+                    reply.write_to(&mut output, Protocol::Resp2);
+                }
+                // what writing to the socket does once done
+                output.advance(output.remaining());
+            })
+        });
+    }
+    group.finish();
+}
+
+criterion_group!(benches, decode, encode);
+criterion_main!(benches);
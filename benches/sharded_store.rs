@@ -0,0 +1,43 @@
+use criterion::{criterion_group, criterion_main, BenchmarkId, Criterion, Throughput};
+use rayon::prelude::*;
+
+use serir::commands::Command;
+use serir::sharded::ShardedStore;
+
+const NUM_KEYS: usize = 10_000;
+
+fn keys() -> Vec<Vec<u8>> {
+    (0..NUM_KEYS)
+        .map(|i| format!("key:{}", i).into_bytes())
+        .collect()
+}
+
+/// Hammers the store with SETs and GETs from every rayon thread at once. With a
+/// single shard all threads serialize on one lock, more shards let them proceed
+/// in parallel.
+fn parallel_get_set(c: &mut Criterion) {
+    let keys = keys();
+    let mut group = c.benchmark_group("parallel_get_set");
+    group.throughput(Throughput::Elements(2 * NUM_KEYS as u64));
+    for num_shards in [1, 4, 16, 64] {
+        let store = ShardedStore::new(num_shards);
+        group.bench_with_input(
+            BenchmarkId::from_parameter(num_shards),
+            &store,
+            |b, store| {
+                b.iter(|| {
+                    keys.par_iter().for_each(|key| {
+                        store
+                            .exec(Command::Set((key.clone(), key.clone().into())))
+                            .unwrap();
+                        store.exec(Command::Get(key.clone())).unwrap();
+                    })
+                })
+            },
+        );
+    }
+    group.finish();
+}
+
+criterion_group!(benches, parallel_get_set);
+criterion_main!(benches);
@@ -0,0 +1,966 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fs;
+use std::path::Path;
+use std::sync::{Mutex, RwLock};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use sha2::{Digest, Sha256};
+
+use crate::commands::{Command, CommandSpec, COMMAND_TABLE};
+use crate::glob::glob_match;
+use crate::resp::Resp;
+
+pub const DEFAULT_USER: &str = "default";
+
+/// Every ACL category Redis defines, including the ones no serir command belongs to yet.
+pub const CATEGORIES: &[&str] = &[
+    "keyspace",
+    "read",
+    "write",
+    "set",
+    "sortedset",
+    "list",
+    "hash",
+    "string",
+    "bitmap",
+    "hyperloglog",
+    "geo",
+    "stream",
+    "pubsub",
+    "admin",
+    "fast",
+    "slow",
+    "blocking",
+    "dangerous",
+    "connection",
+    "transaction",
+    "scripting",
+];
+
+/// Failed authentications and denied commands within this many milliseconds of a similar
+/// one are merged into the same `ACL LOG` entry.
+const LOG_ENTRY_GROUPING_MS: u64 = 60_000;
+
+/// Hashes a password as the bytes it was sent as, which don't have to be UTF-8.
+fn hash_password(password: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(password))
+}
+
+fn is_password_hash(hash: &str) -> bool {
+    hash.len() == 64
+        && hash
+            .bytes()
+            .all(|byte| byte.is_ascii_digit() || (b'a'..=b'f').contains(&byte))
+}
+
+fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|now| now.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Which subcommands of a command a user may run.
+#[derive(Debug, Clone, PartialEq)]
+enum CommandPermission {
+    Allowed,
+    AllowedExcept(BTreeSet<String>),
+    Only(BTreeSet<String>),
+}
+
+#[derive(Debug, Clone, PartialEq)]
+struct KeyPattern {
+    pattern: String,
+    read: bool,
+    write: bool,
+}
+
+impl KeyPattern {
+    fn describe(&self) -> String {
+        match (self.read, self.write) {
+            (true, false) => format!("%R~{}", self.pattern),
+            (false, true) => format!("%W~{}", self.pattern),
+            _ => format!("~{}", self.pattern),
+        }
+    }
+}
+
+/// Why a command was refused.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Denial {
+    /// The user may not run the command, named like `client|kill` for subcommands.
+    Command(String),
+    Key(Vec<u8>),
+}
+
+impl Denial {
+    fn reason(&self) -> &'static str {
+        match self {
+            Denial::Command(_) => "command",
+            Denial::Key(_) => "key",
+        }
+    }
+
+    fn object(&self) -> String {
+        match self {
+            Denial::Command(command) => command.clone(),
+            Denial::Key(key) => String::from_utf8_lossy(key).to_string(),
+        }
+    }
+
+    /// The error sent to the client.
+    pub fn message(&self, username: &str) -> String {
+        match self {
+            Denial::Command(command) => format!(
+                "NOPERM User {} has no permissions to run the '{}' command",
+                username, command
+            ),
+            Denial::Key(_) => "NOPERM No permissions to access a key".to_string(),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct User {
+    pub name: String,
+    enabled: bool,
+    nopass: bool,
+    /// SHA-256 of the passwords, hex encoded.
+    passwords: BTreeSet<String>,
+    commands: HashMap<&'static str, CommandPermission>,
+    /// Whether the user may run every command, including the ones modules add later. Set
+    /// by `+@all` and cleared by any rule taking commands away.
+    all_commands: bool,
+    /// Command rules applied since the last `+@all` or `-@all`, to describe the user.
+    command_rules: Vec<String>,
+    keys: Vec<KeyPattern>,
+    /// Pub/sub channel patterns. Recorded and reported only, serir has no pub/sub.
+    channels: Vec<String>,
+}
+
+impl User {
+    /// A new user is disabled and can't do anything until rules are applied to it.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_string(),
+            enabled: false,
+            nopass: false,
+            passwords: BTreeSet::new(),
+            commands: HashMap::new(),
+            all_commands: false,
+            command_rules: vec!["-@all".to_string()],
+            keys: vec![],
+            channels: vec![],
+        }
+    }
+
+    fn new_default(commands: &[&'static CommandSpec]) -> Self {
+        let mut user = Self::new(DEFAULT_USER);
+        for rule in ["on", "nopass", "~*", "&*", "+@all"] {
+            user.apply(rule, commands).unwrap();
+        }
+        user
+    }
+
+    /// Applies a single ACL rule such as `on`, `>password`, `~cache:*` or `+@read`.
+    /// Command names and categories are resolved among `commands`.
+    pub fn apply(
+        &mut self,
+        rule: impl AsRef<[u8]>,
+        commands: &[&'static CommandSpec],
+    ) -> Result<(), String> {
+        // passwords are taken byte for byte, other rules are text
+        match rule.as_ref().split_first() {
+            Some((b'>', password)) => {
+                self.passwords.insert(hash_password(password));
+                self.nopass = false;
+                return Ok(());
+            }
+            Some((b'<', password)) => {
+                if !self.passwords.remove(&hash_password(password)) {
+                    return Err("no such password".to_string());
+                }
+                return Ok(());
+            }
+            _ => {}
+        }
+        let rule = String::from_utf8_lossy(rule.as_ref());
+        let rule: &str = &rule;
+        let lower = rule.to_lowercase();
+        match lower.as_str() {
+            "on" => self.enabled = true,
+            "off" => self.enabled = false,
+            "nopass" => {
+                self.nopass = true;
+                self.passwords.clear();
+            }
+            "resetpass" => {
+                self.nopass = false;
+                self.passwords.clear();
+            }
+            "allkeys" => self.keys = vec![Self::key_pattern("*", true, true)],
+            "resetkeys" => self.keys.clear(),
+            "allchannels" => self.channels = vec!["*".to_string()],
+            "resetchannels" => self.channels.clear(),
+            "allcommands" | "+@all" => self.set_all_commands(true, commands),
+            "nocommands" | "-@all" => self.set_all_commands(false, commands),
+            "reset" => *self = Self::new(&self.name),
+            _ => return self.apply_with_argument(rule, commands),
+        }
+        Ok(())
+    }
+
+    fn apply_with_argument(
+        &mut self,
+        rule: &str,
+        commands: &[&'static CommandSpec],
+    ) -> Result<(), String> {
+        let (prefix, argument) = rule.split_at(rule.chars().next().map_or(0, char::len_utf8));
+        match prefix {
+            "#" if is_password_hash(argument) => {
+                self.passwords.insert(argument.to_string());
+                self.nopass = false;
+            }
+            "!" if is_password_hash(argument) => {
+                if !self.passwords.remove(argument) {
+                    return Err("no such password".to_string());
+                }
+            }
+            "#" | "!" => return Err("The password hash must be exactly 64 characters and contain only lowercase hexadecimal characters".to_string()),
+            "~" => self.keys.push(Self::key_pattern(argument, true, true)),
+            "%" => {
+                let (access, pattern) = argument
+                    .split_once('~')
+                    .ok_or_else(|| "Syntax error".to_string())?;
+                let access = access.to_uppercase();
+                let read = access.contains('R');
+                let write = access.contains('W');
+                if access.is_empty() || access.chars().any(|c| c != 'R' && c != 'W') {
+                    return Err("Syntax error".to_string());
+                }
+                self.keys.push(Self::key_pattern(pattern, read, write));
+            }
+            "&" => self.channels.push(argument.to_string()),
+            "+" | "-" => {
+                self.apply_command_rule(prefix == "+", &argument.to_lowercase(), commands)?;
+                self.command_rules.push(rule.to_lowercase());
+            }
+            _ => return Err("Syntax error".to_string()),
+        }
+        Ok(())
+    }
+
+    fn key_pattern(pattern: &str, read: bool, write: bool) -> KeyPattern {
+        KeyPattern {
+            pattern: pattern.to_string(),
+            read,
+            write,
+        }
+    }
+
+    fn set_all_commands(&mut self, allowed: bool, commands: &[&'static CommandSpec]) {
+        self.commands.clear();
+        self.all_commands = allowed;
+        if allowed {
+            for spec in commands {
+                self.commands.insert(spec.name, CommandPermission::Allowed);
+            }
+        }
+        self.command_rules = vec![if allowed { "+@all" } else { "-@all" }.to_string()];
+    }
+
+    fn apply_command_rule(
+        &mut self,
+        allow: bool,
+        target: &str,
+        commands: &[&'static CommandSpec],
+    ) -> Result<(), String> {
+        const UNKNOWN: &str = "Unknown command or category name in ACL";
+        if !allow {
+            self.all_commands = false;
+        }
+        if let Some(category) = target.strip_prefix('@') {
+            if !CATEGORIES.contains(&category) {
+                return Err(UNKNOWN.to_string());
+            }
+            let in_category = |spec: &CommandSpec| spec.categories.contains(&category);
+            for spec in commands {
+                if spec.subcommands.is_empty() {
+                    if in_category(spec) {
+                        self.set_command(spec.name, allow);
+                    }
+                } else if spec.subcommands.iter().all(in_category) {
+                    // containers whose subcommands all belong to the category are handled
+                    // as a whole
+                    self.set_command(spec.name, allow);
+                } else {
+                    for subcommand in spec.subcommands {
+                        if subcommand.categories.contains(&category) {
+                            self.set_subcommand(spec.name, subcommand.name, allow);
+                        }
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let (name, subcommand) = match target.split_once('|') {
+            Some((name, subcommand)) => (name, Some(subcommand)),
+            None => (target, None),
+        };
+        let spec = commands
+            .iter()
+            .find(|spec| spec.name == name)
+            .ok_or_else(|| UNKNOWN.to_string())?;
+        match subcommand {
+            None => self.set_command(spec.name, allow),
+            Some(subcommand) if !subcommand.is_empty() && !subcommand.contains('|') => {
+                self.set_subcommand(spec.name, subcommand, allow)
+            }
+            Some(_) => return Err(UNKNOWN.to_string()),
+        }
+        Ok(())
+    }
+
+    fn set_command(&mut self, name: &'static str, allow: bool) {
+        if allow {
+            self.commands.insert(name, CommandPermission::Allowed);
+        } else {
+            self.commands.remove(name);
+        }
+    }
+
+    fn set_subcommand(&mut self, name: &'static str, subcommand: &str, allow: bool) {
+        let subcommand = subcommand.to_string();
+        match (self.commands.get_mut(name), allow) {
+            (Some(CommandPermission::Allowed), true) => {}
+            (Some(CommandPermission::Allowed), false) => {
+                let denied = BTreeSet::from([subcommand]);
+                self.commands
+                    .insert(name, CommandPermission::AllowedExcept(denied));
+            }
+            (Some(CommandPermission::AllowedExcept(denied)), true) => {
+                denied.remove(&subcommand);
+                if denied.is_empty() {
+                    self.commands.insert(name, CommandPermission::Allowed);
+                }
+            }
+            (Some(CommandPermission::AllowedExcept(denied)), false) => {
+                denied.insert(subcommand);
+            }
+            (Some(CommandPermission::Only(allowed)), true) => {
+                allowed.insert(subcommand);
+            }
+            (Some(CommandPermission::Only(allowed)), false) => {
+                allowed.remove(&subcommand);
+                if allowed.is_empty() {
+                    self.commands.remove(name);
+                }
+            }
+            (None, true) => {
+                let allowed = BTreeSet::from([subcommand]);
+                self.commands.insert(name, CommandPermission::Only(allowed));
+            }
+            (None, false) => {}
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn check_password(&self, password: &[u8]) -> bool {
+        self.enabled && (self.nopass || self.passwords.contains(&hash_password(password)))
+    }
+
+    /// Checks that the user may run `command` and access the keys it touches.
+    pub fn check(&self, command: &Command) -> Result<(), Denial> {
+        let name = command.name();
+        let subcommand = command.subcommand().map(str::to_lowercase);
+        let allowed = match (self.commands.get(name), &subcommand) {
+            (Some(CommandPermission::Allowed), _) => true,
+            (Some(CommandPermission::AllowedExcept(denied)), Some(subcommand)) => {
+                !denied.contains(subcommand)
+            }
+            (Some(CommandPermission::AllowedExcept(_)), None) => true,
+            (Some(CommandPermission::Only(allowed)), Some(subcommand)) => {
+                allowed.contains(subcommand)
+            }
+            (None, _) => self.all_commands,
+            _ => false,
+        };
+        if !allowed {
+            return Err(Denial::Command(match subcommand {
+                Some(subcommand) if !subcommand.is_empty() => format!("{}|{}", name, subcommand),
+                _ => name.to_string(),
+            }));
+        }
+
+        let write = command.is_write();
+        for key in command.keys() {
+            let accessible = self.keys.iter().any(|pattern| {
+                (if write { pattern.write } else { pattern.read })
+                    && glob_match(pattern.pattern.as_bytes(), key)
+            });
+            if !accessible {
+                return Err(Denial::Key(key.to_vec()));
+            }
+        }
+        Ok(())
+    }
+
+    /// The rules that recreate the user, as shown by `ACL LIST` and saved to the ACL file.
+    pub fn describe(&self) -> String {
+        let mut rules = vec![format!("user {}", self.name)];
+        rules.push(if self.enabled { "on" } else { "off" }.to_string());
+        if self.nopass {
+            rules.push("nopass".to_string());
+        }
+        rules.extend(self.passwords.iter().map(|hash| format!("#{}", hash)));
+        rules.extend(self.keys.iter().map(KeyPattern::describe));
+        rules.push(self.describe_channels());
+        rules.push(self.command_rules.join(" "));
+        rules.join(" ")
+    }
+
+    fn describe_keys(&self) -> String {
+        self.keys
+            .iter()
+            .map(KeyPattern::describe)
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    fn describe_channels(&self) -> String {
+        if self.channels.is_empty() {
+            return "resetchannels".to_string();
+        }
+        self.channels
+            .iter()
+            .map(|channel| format!("&{}", channel))
+            .collect::<Vec<_>>()
+            .join(" ")
+    }
+
+    /// Reply to `ACL GETUSER`.
+    fn to_resp(&self) -> Resp {
+        let bulk = |value: &str| Resp::BulkString(Some(Bytes::copy_from_slice(value.as_bytes())));
+        let mut flags = vec![bulk(if self.enabled { "on" } else { "off" })];
+        if self.nopass {
+            flags.push(bulk("nopass"));
+        }
+        let channels = if self.channels.is_empty() {
+            String::new()
+        } else {
+            self.describe_channels()
+        };
+        Resp::Map(vec![
+            (bulk("flags"), Resp::Array(Some(flags))),
+            (
+                bulk("passwords"),
+                Resp::Array(Some(self.passwords.iter().map(|hash| bulk(hash)).collect())),
+            ),
+            (bulk("commands"), bulk(&self.command_rules.join(" "))),
+            (bulk("keys"), bulk(&self.describe_keys())),
+            (bulk("channels"), bulk(&channels)),
+            (bulk("selectors"), Resp::Array(Some(vec![]))),
+        ])
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct AclLogEntry {
+    pub entry_id: u64,
+    pub count: u64,
+    /// `auth`, `command` or `key`.
+    pub reason: &'static str,
+    pub object: String,
+    pub username: String,
+    pub client_info: String,
+    pub created_ms: u64,
+    pub updated_ms: u64,
+}
+
+impl AclLogEntry {
+    fn to_resp(&self, now_ms: u64) -> Resp {
+        let bulk = |value: &str| Resp::BulkString(Some(Bytes::copy_from_slice(value.as_bytes())));
+        Resp::Map(vec![
+            (bulk("count"), Resp::Integer(self.count as i64)),
+            (bulk("reason"), bulk(self.reason)),
+            (bulk("context"), bulk("toplevel")),
+            (bulk("object"), bulk(&self.object)),
+            (bulk("username"), bulk(&self.username)),
+            (
+                bulk("age-seconds"),
+                bulk(&format!(
+                    "{:.3}",
+                    now_ms.saturating_sub(self.created_ms) as f64 / 1000.0
+                )),
+            ),
+            (bulk("client-info"), bulk(self.client_info.trim_end())),
+            (bulk("entry-id"), Resp::Integer(self.entry_id as i64)),
+            (
+                bulk("timestamp-created"),
+                Resp::Integer(self.created_ms as i64),
+            ),
+            (
+                bulk("timestamp-last-updated"),
+                Resp::Integer(self.updated_ms as i64),
+            ),
+        ])
+    }
+}
+
+#[derive(Debug, Default)]
+struct AclLog {
+    entries: VecDeque<AclLogEntry>,
+    next_id: u64,
+    max_len: usize,
+}
+
+/// Users, their permissions and the log of what they were denied.
+#[derive(Debug)]
+pub struct Acl {
+    users: RwLock<BTreeMap<String, User>>,
+    log: Mutex<AclLog>,
+    /// Commands that rules may name, directly or through their categories.
+    commands: RwLock<Vec<&'static CommandSpec>>,
+}
+
+impl Acl {
+    /// ACLs for the built-in commands.
+    pub fn new() -> Self {
+        Self::with_commands(COMMAND_TABLE.iter().collect())
+    }
+
+    /// ACLs for `commands`, the ones a server knows.
+    pub fn with_commands(commands: Vec<&'static CommandSpec>) -> Self {
+        let default = User::new_default(&commands);
+        let users = BTreeMap::from([(DEFAULT_USER.to_string(), default)]);
+        Self {
+            commands: RwLock::new(commands),
+            users: RwLock::new(users),
+            log: Mutex::new(AclLog {
+                max_len: 128,
+                ..AclLog::default()
+            }),
+        }
+    }
+
+    /// Applies `requirepass`: the default user gets `password` as its only password, or
+    /// none at all.
+    pub fn set_default_password(&self, password: Option<&str>) {
+        let commands = self.commands.read().unwrap();
+        let mut users = self.users.write().unwrap();
+        let default = users
+            .entry(DEFAULT_USER.to_string())
+            .or_insert_with(|| User::new_default(&commands));
+        match password {
+            Some(password) => {
+                default.apply("resetpass", &commands).unwrap();
+                default
+                    .apply([b">", password.as_bytes()].concat(), &commands)
+                    .unwrap();
+            }
+            None => default.apply("nopass", &commands).unwrap(),
+        }
+    }
+
+    /// Replaces the commands rules may name, when modules add or remove some. Users keep
+    /// their permissions.
+    pub fn set_commands(&self, commands: Vec<&'static CommandSpec>) {
+        *self.commands.write().unwrap() = commands;
+    }
+
+    pub fn set_log_max_len(&self, max_len: usize) {
+        let mut log = self.log.lock().unwrap();
+        log.max_len = max_len;
+        log.entries.truncate(max_len);
+    }
+
+    /// Whether clients that didn't authenticate may run commands as the default user.
+    pub fn default_user_is_open(&self) -> bool {
+        self.users
+            .read()
+            .unwrap()
+            .get(DEFAULT_USER)
+            .is_some_and(|user| user.enabled && user.nopass)
+    }
+
+    pub fn authenticate(&self, username: &str, password: &[u8]) -> bool {
+        self.users
+            .read()
+            .unwrap()
+            .get(username)
+            .is_some_and(|user| user.check_password(password))
+    }
+
+    pub fn check(&self, username: &str, command: &Command) -> Result<(), Denial> {
+        match self.users.read().unwrap().get(username) {
+            Some(user) => user.check(command),
+            // the user was deleted while the client was connected
+            None => Err(Denial::Command(command.name().to_string())),
+        }
+    }
+
+    /// Creates or modifies a user. Either every rule applies or the user is left untouched.
+    pub fn set_user(&self, name: &str, rules: &[impl AsRef<[u8]>]) -> Result<(), String> {
+        let commands = self.commands.read().unwrap();
+        let mut users = self.users.write().unwrap();
+        let mut user = users.get(name).cloned().unwrap_or_else(|| User::new(name));
+        for rule in rules {
+            user.apply(rule, &commands).map_err(|e| {
+                format!(
+                    "Error in ACL SETUSER modifier '{}': {}",
+                    String::from_utf8_lossy(rule.as_ref()),
+                    e
+                )
+            })?;
+        }
+        users.insert(name.to_string(), user);
+        Ok(())
+    }
+
+    pub fn get_user(&self, name: &str) -> Resp {
+        match self.users.read().unwrap().get(name) {
+            Some(user) => user.to_resp(),
+            None => Resp::BulkString(None),
+        }
+    }
+
+    /// Deletes users, returning how many existed.
+    pub fn delete_users(&self, names: &[String]) -> Result<usize, String> {
+        if names.iter().any(|name| name == DEFAULT_USER) {
+            return Err("The 'default' user cannot be removed".to_string());
+        }
+        let mut users = self.users.write().unwrap();
+        Ok(names
+            .iter()
+            .filter(|name| users.remove(name.as_str()).is_some())
+            .count())
+    }
+
+    pub fn exists(&self, name: &str) -> bool {
+        self.users.read().unwrap().contains_key(name)
+    }
+
+    pub fn list(&self) -> Vec<String> {
+        self.users
+            .read()
+            .unwrap()
+            .values()
+            .map(User::describe)
+            .collect()
+    }
+
+    pub fn usernames(&self) -> Vec<String> {
+        self.users.read().unwrap().keys().cloned().collect()
+    }
+
+    /// Writes every user to `path`, in the format `load` reads.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let mut contents = self.list().join("\n");
+        contents.push('\n');
+        let tmp = path.with_extension("tmp");
+        fs::write(&tmp, contents)
+            .and_then(|()| fs::rename(&tmp, path))
+            .map_err(|e| e.to_string())
+    }
+
+    /// Replaces all users with the ones defined in `path`, one `user <name> <rules...>`
+    /// per line. Nothing changes if any line is invalid.
+    pub fn load(&self, path: &Path) -> Result<(), String> {
+        let contents = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        let commands = self.commands.read().unwrap();
+        let mut users = BTreeMap::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            if line.is_empty() || line.starts_with('#') {
+                continue;
+            }
+            let error = |e: &str| format!("{}:{}: {}", path.display(), number + 1, e);
+            let mut words = line.split_whitespace();
+            let name = match (words.next(), words.next()) {
+                (Some("user"), Some(name)) => name,
+                _ => return Err(error("line should start with user keyword")),
+            };
+            if users.contains_key(name) {
+                return Err(error(&format!("duplicate user '{}' found", name)));
+            }
+            let mut user = User::new(name);
+            for rule in words {
+                user.apply(rule, &commands)
+                    .map_err(|e| error(&format!("Error in user rule '{}': {}", rule, e)))?;
+            }
+            users.insert(name.to_string(), user);
+        }
+        users
+            .entry(DEFAULT_USER.to_string())
+            .or_insert_with(|| User::new_default(&commands));
+        *self.users.write().unwrap() = users;
+        Ok(())
+    }
+
+    /// Records a failed authentication (`denial` is `None`) or a denied command, merging
+    /// it with a recent identical entry.
+    pub fn log(&self, denial: Option<&Denial>, username: &str, client_info: String) {
+        let (reason, object) = match denial {
+            Some(denial) => (denial.reason(), denial.object()),
+            None => ("auth", "AUTH".to_string()),
+        };
+        let now = now_ms();
+        let mut log = self.log.lock().unwrap();
+        if let Some(entry) = log.entries.iter_mut().find(|entry| {
+            entry.reason == reason
+                && entry.object == object
+                && entry.username == username
+                && now.saturating_sub(entry.updated_ms) < LOG_ENTRY_GROUPING_MS
+        }) {
+            entry.count += 1;
+            entry.updated_ms = now;
+            entry.client_info = client_info;
+            return;
+        }
+        let entry = AclLogEntry {
+            entry_id: log.next_id,
+            count: 1,
+            reason,
+            object,
+            username: username.to_string(),
+            client_info,
+            created_ms: now,
+            updated_ms: now,
+        };
+        log.next_id += 1;
+        log.entries.push_front(entry);
+        let max_len = log.max_len;
+        log.entries.truncate(max_len);
+    }
+
+    /// Reply to `ACL LOG [count]`, newest entries first.
+    pub fn log_reply(&self, count: Option<usize>) -> Resp {
+        let log = self.log.lock().unwrap();
+        let now = now_ms();
+        Resp::Array(Some(
+            log.entries
+                .iter()
+                .take(count.unwrap_or(log.entries.len()))
+                .map(|entry| entry.to_resp(now))
+                .collect(),
+        ))
+    }
+
+    pub fn reset_log(&self) {
+        self.log.lock().unwrap().entries.clear();
+    }
+
+    /// Commands in `category`, with subcommands named like `client|kill`.
+    pub fn category_commands(&self, category: &str) -> Vec<String> {
+        let mut commands = vec![];
+        for spec in self.commands.read().unwrap().iter() {
+            if spec.categories.contains(&category) {
+                commands.push(spec.name.to_string());
+            }
+            for subcommand in spec.subcommands {
+                if subcommand.categories.contains(&category) {
+                    commands.push(format!("{}|{}", spec.name, subcommand.name));
+                }
+            }
+        }
+        commands
+    }
+}
+
+impl Default for Acl {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+
+    fn builtins() -> Vec<&'static CommandSpec> {
+        COMMAND_TABLE.iter().collect()
+    }
+
+    fn user(rules: &str) -> User {
+        let mut user = User::new("alice");
+        for rule in rules.split_whitespace() {
+            user.apply(rule, &builtins()).unwrap();
+        }
+        user
+    }
+
+    fn get(key: &str) -> Command {
+        Command::Get(key.as_bytes().to_vec())
+    }
+
+    fn set(key: &str) -> Command {
+        Command::Set((key.as_bytes().to_vec(), Bytes::from_static(b"value")))
+    }
+
+    fn client(subcommand: &str) -> Command {
+        Command::Client(vec![subcommand.to_string()])
+    }
+
+    #[test]
+    fn checks_passwords() {
+        let alice = user(&format!("on >secret #{}", hash_password(b"other")));
+        assert!(alice.check_password(b"secret"));
+        assert!(alice.check_password(b"other"));
+        assert!(!alice.check_password(b"wrong"));
+        assert!(!user(">secret").check_password(b"secret"));
+        assert!(user("on nopass").check_password(b"anything"));
+        assert!(User::new("alice").apply("#nothex", &builtins()).is_err());
+        assert!(User::new("alice").apply("<unknown", &builtins()).is_err());
+    }
+
+    #[test]
+    fn checks_commands_and_categories() {
+        let alice = user("on +@read ~* -mget");
+        assert!(alice.check(&get("key")).is_ok());
+        assert_eq!(
+            alice.check(&set("key")),
+            Err(Denial::Command("set".to_string()))
+        );
+        assert!(alice.check(&Command::MGet(vec![b"key".to_vec()])).is_err());
+
+        let operator = user("on +@all -@dangerous");
+        assert!(operator.check(&client("setname")).is_ok());
+        assert_eq!(
+            operator.check(&client("kill")),
+            Err(Denial::Command("client|kill".to_string()))
+        );
+        assert!(operator
+            .check(&Command::Config(vec!["get".to_string()]))
+            .is_err());
+
+        let reader = user("on +config|get");
+        assert!(reader
+            .check(&Command::Config(vec!["GET".to_string()]))
+            .is_ok());
+        assert!(reader
+            .check(&Command::Config(vec!["set".to_string()]))
+            .is_err());
+
+        assert!(User::new("alice")
+            .apply("+nosuchcommand", &builtins())
+            .is_err());
+        assert!(User::new("alice")
+            .apply("+@nosuchcategory", &builtins())
+            .is_err());
+    }
+
+    #[test]
+    fn checks_key_patterns() {
+        let alice = user("on +@all ~cache:* %R~shared:*");
+        assert!(alice.check(&set("cache:1")).is_ok());
+        assert!(alice.check(&get("shared:1")).is_ok());
+        assert_eq!(
+            alice.check(&set("shared:1")),
+            Err(Denial::Key(b"shared:1".to_vec()))
+        );
+        assert!(alice.check(&get("other")).is_err());
+        assert!(alice.check(&client("id")).is_ok());
+    }
+
+    #[test]
+    fn describes_users() {
+        assert_eq!(
+            User::new_default(&builtins()).describe(),
+            "user default on nopass ~* &* +@all"
+        );
+        assert_eq!(
+            user("on >secret %W~w:* +@read -get").describe(),
+            format!(
+                "user alice on #{} %W~w:* resetchannels -@all +@read -get",
+                hash_password(b"secret")
+            )
+        );
+    }
+
+    #[test]
+    fn sets_users_atomically() {
+        let acl = Acl::new();
+        acl.set_user("alice", &["on".to_string(), "+get".to_string()])
+            .unwrap();
+        let error = acl
+            .set_user("alice", &["off".to_string(), "+nope".to_string()])
+            .unwrap_err();
+        assert_eq!(
+            error,
+            "Error in ACL SETUSER modifier '+nope': Unknown command or category name in ACL"
+        );
+        assert!(acl.users.read().unwrap()["alice"].enabled);
+
+        assert!(acl.delete_users(&["default".to_string()]).is_err());
+        assert_eq!(
+            acl.delete_users(&["alice".to_string(), "bob".to_string()]),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn hashes_passwords_as_raw_bytes() {
+        let acl = Acl::new();
+        acl.set_user("alice", &[&b"on"[..], b">\xfe"]).unwrap();
+        assert!(acl.authenticate("alice", b"\xfe"));
+        assert!(!acl.authenticate("alice", b"\xff"));
+        assert!(acl.set_user("alice", &[&b"<\xff"[..]]).is_err());
+        acl.set_user("alice", &[&b"<\xfe"[..]]).unwrap();
+        assert!(!acl.authenticate("alice", b"\xfe"));
+    }
+
+    #[test]
+    fn requirepass_protects_the_default_user() {
+        let acl = Acl::new();
+        assert!(acl.default_user_is_open());
+        acl.set_default_password(Some("secret"));
+        assert!(!acl.default_user_is_open());
+        assert!(acl.authenticate("default", b"secret"));
+        assert!(!acl.authenticate("default", b"wrong"));
+        acl.set_default_password(None);
+        assert!(acl.default_user_is_open());
+    }
+
+    #[test]
+    fn saves_and_loads_acl_files() {
+        let path = env::temp_dir().join(format!("serir-acl-{}.acl", std::process::id()));
+        let acl = Acl::new();
+        acl.set_user(
+            "alice",
+            &["on", ">secret", "~cache:*", "+@read"].map(String::from),
+        )
+        .unwrap();
+        acl.save(&path).unwrap();
+
+        let loaded = Acl::new();
+        loaded.load(&path).unwrap();
+        assert_eq!(loaded.list(), acl.list());
+        assert!(loaded.authenticate("alice", b"secret"));
+
+        fs::write(&path, "user alice on\nuser bob +nope\n").unwrap();
+        assert!(loaded.load(&path).is_err());
+        assert_eq!(loaded.list(), acl.list());
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn groups_similar_log_entries() {
+        let acl = Acl::new();
+        let denial = Denial::Command("get".to_string());
+        acl.log(Some(&denial), "alice", "id=1".to_string());
+        acl.log(Some(&denial), "alice", "id=2".to_string());
+        acl.log(None, "alice", "id=2".to_string());
+
+        let log = acl.log.lock().unwrap();
+        assert_eq!(log.entries.len(), 2);
+        assert_eq!(log.entries[0].reason, "auth");
+        assert_eq!(log.entries[1].count, 2);
+        assert_eq!(log.entries[1].client_info, "id=2");
+    }
+}
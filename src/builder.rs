@@ -0,0 +1,230 @@
+use std::net::{Ipv4Addr, SocketAddr};
+use std::panic;
+use std::sync::Arc;
+
+use bytes::Bytes;
+use tokio::task::JoinHandle;
+
+use crate::config::Config;
+use crate::error::SerirError;
+use crate::registry::CommandRegistry;
+use crate::shutdown::ShutdownOptions;
+use crate::state::ServerState;
+use crate::{bind_listener, remove_unix_socket, Listeners};
+
+/// Starts a server on the current tokio runtime, for programs and tests embedding serir.
+///
+/// Unlike [`crate::run`], port 0 means any free port rather than not listening on TCP, and
+/// the server runs in the background until [`ServerHandle::shutdown`] or `SHUTDOWN`.
+#[derive(Debug)]
+pub struct ServerBuilder {
+    config: Config,
+    commands: CommandRegistry,
+    addr: SocketAddr,
+    data: Vec<(Vec<u8>, Bytes)>,
+}
+
+impl ServerBuilder {
+    /// A builder for a server with the default configuration and commands, listening on
+    /// a free port of 127.0.0.1.
+    pub fn new() -> Self {
+        Self {
+            config: Config::default(),
+            commands: CommandRegistry::default(),
+            addr: (Ipv4Addr::LOCALHOST, 0).into(),
+            data: vec![],
+        }
+    }
+
+    /// Address to listen on. Port 0 picks a free port, see [`ServerHandle::addr`].
+    pub fn bind(mut self, addr: SocketAddr) -> Self {
+        self.addr = addr;
+        self
+    }
+
+    pub fn port(mut self, port: u16) -> Self {
+        self.addr.set_port(port);
+        self
+    }
+
+    /// Configuration of the server. Its `bind` and `port` are replaced by the address of
+    /// the builder, other listeners like `metrics-port`, `tls-port` or `unixsocket` are
+    /// kept and listen on the same IP address.
+    pub fn config(mut self, config: Config) -> Self {
+        self.config = config;
+        self
+    }
+
+    pub fn commands(mut self, commands: CommandRegistry) -> Self {
+        self.commands = commands;
+        self
+    }
+
+    /// Sets `key` to `value` before the server accepts clients.
+    pub fn data(mut self, key: impl Into<Vec<u8>>, value: impl Into<Bytes>) -> Self {
+        self.data.push((key.into(), value.into()));
+        self
+    }
+
+    /// Binds the listeners, loads modules, ACLs and certificates, stores the initial data
+    /// and starts accepting clients.
+    pub async fn start(self) -> Result<ServerHandle, SerirError> {
+        let mut config = self.config;
+        let listener = bind_listener(self.addr, false, config.tcp_backlog as i32).map_err(|e| {
+            SerirError::ConfigError(format!("could not listen on {}: {}", self.addr, e))
+        })?;
+        let addr = listener.local_addr()?;
+        config.bind = vec![addr.ip().to_string()];
+        config.port = 0;
+        let mut listeners = Listeners::bind(&config)?;
+        listeners.tcp.push(listener);
+        config.port = addr.port();
+
+        let unixsocket = config.unixsocket.clone();
+        let state = Arc::new(ServerState::with_commands(config, self.commands));
+        state.load_modules()?;
+        state.load_acl_file()?;
+        state.load_tls()?;
+        for (key, value) in self.data {
+            state.store.set(key, value)?;
+        }
+        let cron = tokio::spawn(state.clone().cron());
+        let listeners = listeners.serve(&state)?;
+
+        let task = tokio::spawn({
+            let state = state.clone();
+            async move {
+                let options = state.shutdown.requested().await;
+                state.shut_down(options).await;
+                cron.abort();
+                // listeners stop on their own once the shutdown is requested, and free
+                // their ports when their tasks end
+                for listener in listeners {
+                    let _ = listener.await;
+                }
+                remove_unix_socket(unixsocket);
+            }
+        });
+        Ok(ServerHandle { addr, state, task })
+    }
+}
+
+impl Default for ServerBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A server started by a [`ServerBuilder`].
+#[derive(Debug)]
+pub struct ServerHandle {
+    addr: SocketAddr,
+    state: Arc<ServerState>,
+    task: JoinHandle<()>,
+}
+
+impl ServerHandle {
+    /// Address the server accepts TCP connections on, with the port it picked if asked
+    /// for port 0.
+    pub fn addr(&self) -> SocketAddr {
+        self.addr
+    }
+
+    /// State of the server, to reach its store or configuration without a connection.
+    pub fn state(&self) -> &Arc<ServerState> {
+        &self.state
+    }
+
+    /// Requests a graceful shutdown, like `SHUTDOWN` does. [`ServerHandle::join`] waits
+    /// for it to complete.
+    pub fn shutdown(&self) {
+        self.state.shutdown.request(ShutdownOptions::default());
+    }
+
+    /// Waits until the server has shut down, after [`ServerHandle::shutdown`] or
+    /// `SHUTDOWN`: its listeners are closed and its clients were asked to disconnect.
+    /// Connections still busy with a command once `shutdown-timeout` has passed aren't
+    /// waited for, they close once their command completes.
+    pub async fn join(self) {
+        if let Err(e) = self.task.await {
+            if e.is_panic() {
+                panic::resume_unwind(e.into_panic());
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpStream;
+
+    #[tokio::test]
+    async fn serves_initial_data_on_a_free_port() {
+        let server = ServerBuilder::new()
+            .data("greeting", "hello")
+            .start()
+            .await
+            .unwrap();
+        assert_ne!(server.addr().port(), 0);
+        assert_eq!(
+            server.state().config.read().unwrap().port,
+            server.addr().port()
+        );
+
+        let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+        stream
+            .write_all(b"*2\r\n$3\r\nGET\r\n$8\r\ngreeting\r\n")
+            .await
+            .unwrap();
+        let mut reply = [0; 11];
+        stream.read_exact(&mut reply).await.unwrap();
+        assert_eq!(&reply, b"$5\r\nhello\r\n");
+
+        server.shutdown();
+        server.join().await;
+        assert!(TcpStream::connect(stream.peer_addr().unwrap())
+            .await
+            .is_err());
+    }
+
+    #[tokio::test]
+    async fn stops_on_shutdown_command() {
+        let server = ServerBuilder::new().start().await.unwrap();
+        let mut stream = TcpStream::connect(server.addr()).await.unwrap();
+        stream
+            .write_all(b"*2\r\n$8\r\nSHUTDOWN\r\n$6\r\nNOSAVE\r\n")
+            .await
+            .unwrap();
+        server.join().await;
+    }
+
+    #[tokio::test]
+    async fn closes_the_metrics_listener_on_shutdown() {
+        let metrics_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let config = Config {
+            metrics_port,
+            ..Config::default()
+        };
+        let server = ServerBuilder::new().config(config).start().await.unwrap();
+        TcpStream::connect(("127.0.0.1", metrics_port))
+            .await
+            .unwrap();
+
+        server.shutdown();
+        server.join().await;
+        std::net::TcpListener::bind(("127.0.0.1", metrics_port)).unwrap();
+    }
+
+    #[tokio::test]
+    async fn refuses_ports_in_use() {
+        let server = ServerBuilder::new().start().await.unwrap();
+        let result = ServerBuilder::new().bind(server.addr()).start().await;
+        assert!(matches!(result, Err(SerirError::ConfigError(_))));
+    }
+}
@@ -0,0 +1,729 @@
+use std::collections::BTreeMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicU8, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use tokio::sync::{watch, Notify};
+use tokio::time;
+
+use crate::acl::DEFAULT_USER;
+use crate::resp::{Protocol, Resp};
+use crate::tracking::TrackingOptions;
+
+/// A connection as seen by the `CLIENT` commands, shared between the task serving it and
+/// the registry of all connections.
+#[derive(Debug)]
+pub struct Client {
+    pub id: u64,
+    /// Address of the peer, e.g. `127.0.0.1:5555`.
+    pub addr: String,
+    /// Local address the peer connected to.
+    pub laddr: String,
+    created: Instant,
+    /// When the client last ran a command, and which one.
+    last_command: Mutex<(Instant, &'static str)>,
+    name: RwLock<String>,
+    /// ACL user the client runs commands as.
+    user: RwLock<String>,
+    authenticated: AtomicBool,
+    monitor: AtomicBool,
+    no_evict: AtomicBool,
+    reply_off: AtomicBool,
+    /// Number of upcoming replies to drop, see `CLIENT REPLY SKIP`.
+    skip_replies: AtomicU8,
+    /// Whether the client switched to RESP3 with `HELLO 3`.
+    resp3: AtomicBool,
+    /// Set by `CLIENT TRACKING ON`.
+    tracking: Mutex<Option<TrackingOptions>>,
+    /// Set by `CLIENT CACHING` for the next command only.
+    caching: AtomicBool,
+    /// Whether the client tracking redirects to went away.
+    broken_redirect: AtomicBool,
+    /// Messages sent out of band from replies, like key invalidations.
+    pushes: Mutex<Vec<Resp>>,
+    pushed: Notify,
+    killed: AtomicBool,
+    kill: Notify,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplyMode {
+    On,
+    Off,
+    /// Drops the reply to the next command only.
+    Skip,
+}
+
+impl Client {
+    pub fn new(id: u64, addr: String, laddr: String) -> Self {
+        let now = Instant::now();
+        Self {
+            id,
+            addr,
+            laddr,
+            created: now,
+            last_command: Mutex::new((now, "NULL")),
+            name: RwLock::new(String::new()),
+            user: RwLock::new(DEFAULT_USER.to_string()),
+            authenticated: AtomicBool::new(false),
+            monitor: AtomicBool::new(false),
+            no_evict: AtomicBool::new(false),
+            reply_off: AtomicBool::new(false),
+            skip_replies: AtomicU8::new(0),
+            resp3: AtomicBool::new(false),
+            tracking: Mutex::new(None),
+            caching: AtomicBool::new(false),
+            broken_redirect: AtomicBool::new(false),
+            pushes: Mutex::new(vec![]),
+            pushed: Notify::new(),
+            killed: AtomicBool::new(false),
+            kill: Notify::new(),
+        }
+    }
+
+    pub fn name(&self) -> String {
+        self.name.read().unwrap().clone()
+    }
+
+    /// Names the connection, names can't contain spaces or special characters so that
+    /// `CLIENT LIST` stays parsable. An empty name removes the current one.
+    pub fn set_name(&self, name: &str) -> Result<(), String> {
+        if !name.bytes().all(|byte| (b'!'..=b'~').contains(&byte)) {
+            return Err(
+                "Client names cannot contain spaces, newlines or special characters.".to_string(),
+            );
+        }
+        *self.name.write().unwrap() = name.to_string();
+        Ok(())
+    }
+
+    pub fn user(&self) -> String {
+        self.user.read().unwrap().clone()
+    }
+
+    /// Whether the client went through `AUTH`. Clients that didn't authenticate run as the
+    /// default user, as long as it doesn't require a password.
+    pub fn is_authenticated(&self) -> bool {
+        self.authenticated.load(Ordering::Relaxed)
+    }
+
+    pub fn authenticate(&self, user: &str) {
+        *self.user.write().unwrap() = user.to_string();
+        self.authenticated.store(true, Ordering::Relaxed);
+    }
+
+    /// Records that the client is running `command`, for `idle` and `cmd` in `CLIENT LIST`.
+    pub fn command_started(&self, command: &'static str) {
+        *self.last_command.lock().unwrap() = (Instant::now(), command);
+    }
+
+    pub fn age(&self) -> Duration {
+        self.created.elapsed()
+    }
+
+    pub fn idle(&self) -> Duration {
+        self.last_command.lock().unwrap().0.elapsed()
+    }
+
+    /// Set by `MONITOR`: from now on the connection only receives the feed of executed
+    /// commands.
+    pub fn is_monitor(&self) -> bool {
+        self.monitor.load(Ordering::Relaxed)
+    }
+
+    pub fn set_monitor(&self) {
+        self.monitor.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_no_evict(&self) -> bool {
+        self.no_evict.load(Ordering::Relaxed)
+    }
+
+    /// Excludes the client from client eviction. Only recorded for now: clients are never
+    /// evicted since their memory isn't limited.
+    pub fn set_no_evict(&self, no_evict: bool) {
+        self.no_evict.store(no_evict, Ordering::Relaxed);
+    }
+
+    pub fn set_reply_mode(&self, mode: ReplyMode) {
+        match mode {
+            ReplyMode::On => {
+                self.reply_off.store(false, Ordering::Relaxed);
+                self.skip_replies.store(0, Ordering::Relaxed);
+            }
+            ReplyMode::Off => self.reply_off.store(true, Ordering::Relaxed),
+            // the reply to CLIENT REPLY SKIP itself is dropped too
+            ReplyMode::Skip => self.skip_replies.store(2, Ordering::Relaxed),
+        }
+    }
+
+    /// Whether the reply to the command that just ran should be sent. Must be called once
+    /// per command, as it consumes skipped replies.
+    pub fn reply_allowed(&self) -> bool {
+        let skipped = self
+            .skip_replies
+            .fetch_update(Ordering::Relaxed, Ordering::Relaxed, |skip| {
+                skip.checked_sub(1)
+            })
+            .is_ok();
+        !skipped && !self.reply_off.load(Ordering::Relaxed)
+    }
+
+    pub fn protocol(&self) -> Protocol {
+        match self.resp3.load(Ordering::Relaxed) {
+            true => Protocol::Resp3,
+            false => Protocol::Resp2,
+        }
+    }
+
+    pub fn set_protocol(&self, protocol: Protocol) {
+        self.resp3
+            .store(protocol == Protocol::Resp3, Ordering::Relaxed);
+    }
+
+    /// Options of `CLIENT TRACKING`, `None` while tracking is off.
+    pub fn tracking(&self) -> Option<TrackingOptions> {
+        self.tracking.lock().unwrap().clone()
+    }
+
+    pub fn is_tracking(&self) -> bool {
+        self.tracking.lock().unwrap().is_some()
+    }
+
+    pub fn set_tracking(&self, options: Option<TrackingOptions>) {
+        *self.tracking.lock().unwrap() = options;
+        self.caching.store(false, Ordering::Relaxed);
+        self.broken_redirect.store(false, Ordering::Relaxed);
+    }
+
+    /// Set by `CLIENT CACHING YES` in `OPTIN` mode and `CLIENT CACHING NO` in `OPTOUT`
+    /// mode, reset after the next command.
+    pub fn set_caching(&self, caching: bool) {
+        self.caching.store(caching, Ordering::Relaxed);
+    }
+
+    pub fn is_caching(&self) -> bool {
+        self.caching.load(Ordering::Relaxed)
+    }
+
+    /// Whether the keys read by the command running must be remembered, to invalidate
+    /// them once they change. Broadcasting clients are told about keys whether they read
+    /// them or not.
+    pub fn tracks_reads(&self) -> bool {
+        match &*self.tracking.lock().unwrap() {
+            Some(options) if options.bcast => false,
+            Some(options) if options.optin => self.is_caching(),
+            Some(options) if options.optout => !self.is_caching(),
+            Some(_) => true,
+            None => false,
+        }
+    }
+
+    /// `CLIENT GETREDIR`: the client invalidations are redirected to, 0 when sent to the
+    /// client itself and -1 when tracking is off.
+    pub fn tracking_redirect(&self) -> i64 {
+        match &*self.tracking.lock().unwrap() {
+            Some(options) => options.redirect.map_or(0, |id| id as i64),
+            None => -1,
+        }
+    }
+
+    pub fn set_broken_redirect(&self) {
+        self.broken_redirect.store(true, Ordering::Relaxed);
+    }
+
+    pub fn has_broken_redirect(&self) -> bool {
+        self.broken_redirect.load(Ordering::Relaxed)
+    }
+
+    /// Queues a message for the task serving the connection to send, after the replies
+    /// it is writing or right away if the client is idle.
+    pub fn push(&self, message: Resp) {
+        self.pushes.lock().unwrap().push(message);
+        self.pushed.notify_one();
+    }
+
+    pub fn take_pushes(&self) -> Vec<Resp> {
+        std::mem::take(&mut self.pushes.lock().unwrap())
+    }
+
+    fn has_pushes(&self) -> bool {
+        !self.pushes.lock().unwrap().is_empty()
+    }
+
+    /// Resolves once messages were pushed.
+    pub async fn pushed(&self) {
+        while !self.has_pushes() {
+            self.pushed.notified().await;
+        }
+    }
+
+    /// Asks the task serving the connection to close it.
+    pub fn kill(&self) {
+        self.killed.store(true, Ordering::Relaxed);
+        self.kill.notify_one();
+    }
+
+    pub fn is_killed(&self) -> bool {
+        self.killed.load(Ordering::Relaxed)
+    }
+
+    /// Resolves once the client got killed.
+    pub async fn killed(&self) {
+        while !self.is_killed() {
+            self.kill.notified().await;
+        }
+    }
+
+    fn flags(&self) -> String {
+        let mut flags = String::new();
+        if self.is_monitor() {
+            flags.push('O');
+        }
+        if let Some(options) = &*self.tracking.lock().unwrap() {
+            flags.push('t');
+            if self.has_broken_redirect() {
+                flags.push('R');
+            }
+            if options.bcast {
+                flags.push('B');
+            }
+        }
+        if self.is_no_evict() {
+            flags.push('e');
+        }
+        if flags.is_empty() {
+            flags.push('N');
+        }
+        flags
+    }
+
+    /// The line describing the client in `CLIENT LIST` and `CLIENT INFO`.
+    pub fn info_line(&self) -> String {
+        let command = self.last_command.lock().unwrap().1;
+        format!(
+            "id={} addr={} laddr={} name={} age={} idle={} flags={} db=0 sub=0 psub=0 \
+             multi=-1 cmd={} user={} redir={} resp={}\n",
+            self.id,
+            self.addr,
+            self.laddr,
+            self.name(),
+            self.age().as_secs(),
+            self.idle().as_secs(),
+            self.flags(),
+            command,
+            self.user(),
+            self.tracking_redirect(),
+            self.protocol().version()
+        )
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseMode {
+    /// Only commands that may change the keyspace wait.
+    Write,
+    All,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Pause {
+    until: Instant,
+    mode: PauseMode,
+}
+
+/// Classes of clients with their own `client-output-buffer-limit`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ClientClass {
+    Normal,
+    Replica,
+    Pubsub,
+}
+
+impl ClientClass {
+    pub const ALL: [ClientClass; 3] = [
+        ClientClass::Normal,
+        ClientClass::Replica,
+        ClientClass::Pubsub,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClientClass::Normal => "normal",
+            ClientClass::Replica => "replica",
+            ClientClass::Pubsub => "pubsub",
+        }
+    }
+
+    /// Parses a class name, accepting `slave` for replicas like Redis does.
+    pub fn parse(name: &str) -> Option<Self> {
+        match name.to_lowercase().as_str() {
+            "slave" => Some(ClientClass::Replica),
+            name => Self::ALL.into_iter().find(|class| class.as_str() == name),
+        }
+    }
+}
+
+/// How many bytes of replies a client may have pending. Reaching `hard` disconnects it
+/// right away, staying at or above `soft` for more than `soft_seconds` too. Limits of 0
+/// are disabled.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct OutputBufferLimit {
+    pub hard: u64,
+    pub soft: u64,
+    pub soft_seconds: u64,
+}
+
+impl OutputBufferLimit {
+    pub fn exceeds_hard(&self, pending: usize) -> bool {
+        self.hard != 0 && pending as u64 >= self.hard
+    }
+
+    pub fn exceeds_soft(&self, pending: usize) -> bool {
+        self.soft != 0 && pending as u64 >= self.soft
+    }
+
+    /// When a client over the soft limit since `since` must be disconnected.
+    pub fn soft_deadline(&self, since: Instant) -> Instant {
+        // Redis counts whole seconds, so the client gets up to a second more
+        since + Duration::from_secs(self.soft_seconds + 1)
+    }
+}
+
+/// `client-output-buffer-limit` of every client class.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct OutputBufferLimits {
+    pub normal: OutputBufferLimit,
+    pub replica: OutputBufferLimit,
+    pub pubsub: OutputBufferLimit,
+}
+
+impl OutputBufferLimits {
+    pub fn get(&self, class: ClientClass) -> &OutputBufferLimit {
+        match class {
+            ClientClass::Normal => &self.normal,
+            ClientClass::Replica => &self.replica,
+            ClientClass::Pubsub => &self.pubsub,
+        }
+    }
+
+    pub fn get_mut(&mut self, class: ClientClass) -> &mut OutputBufferLimit {
+        match class {
+            ClientClass::Normal => &mut self.normal,
+            ClientClass::Replica => &mut self.replica,
+            ClientClass::Pubsub => &mut self.pubsub,
+        }
+    }
+}
+
+impl Default for OutputBufferLimits {
+    fn default() -> Self {
+        const MB: u64 = 1024 * 1024;
+        Self {
+            normal: OutputBufferLimit::default(),
+            replica: OutputBufferLimit {
+                hard: 256 * MB,
+                soft: 64 * MB,
+                soft_seconds: 60,
+            },
+            pubsub: OutputBufferLimit {
+                hard: 32 * MB,
+                soft: 8 * MB,
+                soft_seconds: 60,
+            },
+        }
+    }
+}
+
+/// Limits protecting the server from clients sending or receiving too much at once.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BufferLimits {
+    /// Bytes of a request that may be buffered before it is complete.
+    pub query_buffer: usize,
+    /// Longest bulk string accepted in a request.
+    pub max_bulk_len: usize,
+    pub output: OutputBufferLimits,
+}
+
+impl Default for BufferLimits {
+    fn default() -> Self {
+        Self {
+            query_buffer: usize::MAX,
+            max_bulk_len: usize::MAX,
+            output: OutputBufferLimits::default(),
+        }
+    }
+}
+
+/// Registry of the open connections.
+#[derive(Debug)]
+pub struct Clients {
+    next_id: AtomicU64,
+    clients: Mutex<BTreeMap<u64, Arc<Client>>>,
+    maxclients: AtomicUsize,
+    /// Idle timeout in seconds, 0 for none.
+    timeout: AtomicU64,
+    /// TCP keepalive interval in seconds, 0 for none.
+    tcp_keepalive: AtomicU64,
+    buffer_limits: RwLock<BufferLimits>,
+    pause: watch::Sender<Option<Pause>>,
+    /// Keeps the channel open and is cloned by clients waiting for the pause to end.
+    pause_rx: watch::Receiver<Option<Pause>>,
+}
+
+impl Clients {
+    pub fn new() -> Self {
+        let (pause, pause_rx) = watch::channel(None);
+        Self {
+            next_id: AtomicU64::new(1),
+            clients: Mutex::new(BTreeMap::new()),
+            maxclients: AtomicUsize::new(usize::MAX),
+            timeout: AtomicU64::new(0),
+            tcp_keepalive: AtomicU64::new(0),
+            buffer_limits: RwLock::new(BufferLimits::default()),
+            pause,
+            pause_rx,
+        }
+    }
+
+    /// Applies `maxclients`, `timeout` and `tcp-keepalive`.
+    pub fn configure(&self, maxclients: usize, timeout: u64, tcp_keepalive: u64) {
+        self.maxclients.store(maxclients, Ordering::Relaxed);
+        self.timeout.store(timeout, Ordering::Relaxed);
+        self.tcp_keepalive.store(tcp_keepalive, Ordering::Relaxed);
+    }
+
+    pub fn set_buffer_limits(&self, limits: BufferLimits) {
+        *self.buffer_limits.write().unwrap() = limits;
+    }
+
+    pub fn buffer_limits(&self) -> BufferLimits {
+        *self.buffer_limits.read().unwrap()
+    }
+
+    pub fn maxclients(&self) -> usize {
+        self.maxclients.load(Ordering::Relaxed)
+    }
+
+    pub fn tcp_keepalive(&self) -> Option<Duration> {
+        match self.tcp_keepalive.load(Ordering::Relaxed) {
+            0 => None,
+            seconds => Some(Duration::from_secs(seconds)),
+        }
+    }
+
+    pub fn register(&self, addr: String, laddr: String) -> Arc<Client> {
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let client = Arc::new(Client::new(id, addr, laddr));
+        self.clients.lock().unwrap().insert(id, client.clone());
+        client
+    }
+
+    /// Registers a client unless `maxclients` clients are already connected.
+    pub fn try_register(&self, addr: String, laddr: String) -> Option<Arc<Client>> {
+        let mut clients = self.clients.lock().unwrap();
+        if clients.len() >= self.maxclients() {
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let client = Arc::new(Client::new(id, addr, laddr));
+        clients.insert(id, client.clone());
+        Some(client)
+    }
+
+    pub fn get(&self, id: u64) -> Option<Arc<Client>> {
+        self.clients.lock().unwrap().get(&id).cloned()
+    }
+
+    pub fn unregister(&self, id: u64) {
+        self.clients.lock().unwrap().remove(&id);
+    }
+
+    /// All registered clients, ordered by id.
+    pub fn list(&self) -> Vec<Arc<Client>> {
+        self.clients.lock().unwrap().values().cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.clients.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Kills the clients that didn't send a command for longer than `timeout`. Monitors
+    /// only receive data and are never considered idle.
+    pub fn kill_idle(&self) {
+        let timeout = match self.timeout.load(Ordering::Relaxed) {
+            0 => return,
+            seconds => Duration::from_secs(seconds),
+        };
+        for client in self.clients.lock().unwrap().values() {
+            if !client.is_monitor() && client.idle() > timeout {
+                client.kill();
+            }
+        }
+    }
+
+    /// Pauses clients for `timeout`. A pause already in progress is only ever extended,
+    /// both in time and in the commands it applies to.
+    pub fn pause(&self, timeout: Duration, mode: PauseMode) {
+        let mut pause = Pause {
+            until: Instant::now() + timeout,
+            mode,
+        };
+        if let Some(current) = *self.pause_rx.borrow() {
+            if current.until > Instant::now() {
+                pause.until = pause.until.max(current.until);
+                if current.mode == PauseMode::All {
+                    pause.mode = PauseMode::All;
+                }
+            }
+        }
+        // cannot fail, `pause_rx` keeps the channel open
+        let _ = self.pause.send(Some(pause));
+    }
+
+    pub fn unpause(&self) {
+        let _ = self.pause.send(None);
+    }
+
+    /// Whether a pause is in progress. Background jobs that change the keyspace, like
+    /// active expiry and eviction, don't run while clients are paused.
+    pub fn is_paused(&self) -> bool {
+        matches!(*self.pause_rx.borrow(), Some(pause) if pause.until > Instant::now())
+    }
+
+    /// Waits until a command, which `is_write` or not, is allowed to run.
+    pub async fn wait_for_unpause(&self, is_write: bool) {
+        let mut pause_rx = self.pause_rx.clone();
+        loop {
+            let pause = *pause_rx.borrow();
+            let until = match pause {
+                Some(pause) if pause.mode == PauseMode::All || is_write => pause.until,
+                _ => return,
+            };
+            if until <= Instant::now() {
+                return;
+            }
+            tokio::select! {
+                _ = time::sleep_until(until.into()) => {},
+                _ = pause_rx.changed() => {},
+            }
+        }
+    }
+}
+
+impl Default for Clients {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn registers_clients_with_increasing_ids() {
+        let clients = Clients::new();
+        let first = clients.register("127.0.0.1:1".to_string(), "127.0.0.1:6379".to_string());
+        let second = clients.register("127.0.0.1:2".to_string(), "127.0.0.1:6379".to_string());
+        assert_eq!((first.id, second.id), (1, 2));
+        assert_eq!(clients.len(), 2);
+
+        clients.unregister(first.id);
+        let ids: Vec<_> = clients.list().iter().map(|client| client.id).collect();
+        assert_eq!(ids, vec![2]);
+    }
+
+    #[test]
+    fn limits_clients_and_kills_idle_ones() {
+        let clients = Clients::new();
+        clients.configure(1, 1, 0);
+        let first = clients.try_register(String::new(), String::new()).unwrap();
+        assert!(clients.try_register(String::new(), String::new()).is_none());
+
+        clients.kill_idle();
+        assert!(!first.is_killed());
+        *first.last_command.lock().unwrap() = (Instant::now() - Duration::from_secs(2), "get");
+        clients.kill_idle();
+        assert!(first.is_killed());
+    }
+
+    #[test]
+    fn describes_clients_like_redis() {
+        let client = Client::new(
+            7,
+            "127.0.0.1:5555".to_string(),
+            "127.0.0.1:6379".to_string(),
+        );
+        client.set_name("worker-1").unwrap();
+        client.command_started("get");
+        client.set_no_evict(true);
+
+        let line = client.info_line();
+        assert!(line.starts_with(
+            "id=7 addr=127.0.0.1:5555 laddr=127.0.0.1:6379 name=worker-1 age=0 idle=0 flags=e "
+        ));
+        assert!(line.contains(" cmd=get "));
+        assert!(line.ends_with('\n'));
+        assert!(client.set_name("with space").is_err());
+    }
+
+    #[test]
+    fn skips_and_disables_replies() {
+        let client = Client::new(1, String::new(), String::new());
+        assert!(client.reply_allowed());
+
+        client.set_reply_mode(ReplyMode::Skip);
+        assert!(!client.reply_allowed());
+        assert!(!client.reply_allowed());
+        assert!(client.reply_allowed());
+
+        client.set_reply_mode(ReplyMode::Off);
+        assert!(!client.reply_allowed());
+        client.set_reply_mode(ReplyMode::On);
+        assert!(client.reply_allowed());
+    }
+
+    #[tokio::test]
+    async fn pauses_writes_or_everything() {
+        let clients = Clients::new();
+        clients.pause(Duration::from_millis(50), PauseMode::Write);
+        assert!(clients.is_paused());
+
+        let start = Instant::now();
+        clients.wait_for_unpause(false).await;
+        assert!(start.elapsed() < Duration::from_millis(50));
+        clients.wait_for_unpause(true).await;
+        assert!(start.elapsed() >= Duration::from_millis(50));
+
+        clients.pause(Duration::from_secs(60), PauseMode::All);
+        clients.unpause();
+        clients.wait_for_unpause(false).await;
+        assert!(!clients.is_paused());
+    }
+
+    #[tokio::test]
+    async fn queues_pushed_messages() {
+        let client = Client::new(1, String::new(), String::new());
+        client.push(Resp::Integer(1));
+        client.pushed().await;
+        client.push(Resp::Integer(2));
+        assert_eq!(
+            client.take_pushes(),
+            vec![Resp::Integer(1), Resp::Integer(2)]
+        );
+        assert!(client.take_pushes().is_empty());
+    }
+
+    #[tokio::test]
+    async fn notifies_killed_clients() {
+        let client = Client::new(1, String::new(), String::new());
+        client.kill();
+        assert!(client.is_killed());
+        client.killed().await;
+    }
+}
@@ -1,86 +1,1676 @@
+use bytes::Bytes;
+
+use crate::error::{SerirError, SerirResult};
 use crate::resp::Resp;
 
 #[derive(Debug)]
 pub enum Command {
     Get(Vec<u8>),
-    Set((Vec<u8>, Vec<u8>)),
-    Command,
-    Config(String),
+    Set((Vec<u8>, Bytes)),
+    MGet(Vec<Vec<u8>>),
+    MSet(Vec<(Vec<u8>, Bytes)>),
+    Del(Vec<Vec<u8>>),
+    Exists(Vec<Vec<u8>>),
+    Expire((Vec<u8>, i64)),
+    PExpire((Vec<u8>, i64)),
+    Ttl(Vec<u8>),
+    PTtl(Vec<u8>),
+    Persist(Vec<u8>),
+    Object((String, Vec<u8>)),
+    /// `COMMAND [subcommand [argument ...]]`, arguments are kept as bytes since
+    /// `COMMAND GETKEYS` takes keys.
+    Command(Vec<Vec<u8>>),
+    /// `CONFIG subcommand [argument ...]`, arguments must be UTF-8 since configuration is
+    /// text.
+    Config(Vec<String>),
+    Info(Vec<String>),
+    Slowlog(Vec<String>),
+    Latency(Vec<String>),
+    Monitor,
+    Client(Vec<String>),
+    /// `AUTH [username] password`, the username defaults to `default`. The password is
+    /// kept as bytes, like every password argument.
+    Auth((Option<String>, Vec<u8>)),
+    /// `ACL subcommand [argument ...]`, arguments are kept as bytes since `ACL SETUSER`
+    /// takes passwords.
+    Acl(Vec<Vec<u8>>),
+    Shutdown(Vec<String>),
+    /// `HELLO [protover [AUTH username password] [SETNAME clientname]]`, arguments are
+    /// kept as bytes since one is a password.
+    Hello(Vec<Vec<u8>>),
+    Module(Vec<String>),
+    /// A command that isn't built into serir, see [`crate::registry`].
+    Custom(CustomCommand),
+}
+
+/// A command registered by a library user, with its arguments as they were received.
+#[derive(Debug)]
+pub struct CustomCommand {
+    pub spec: &'static CommandSpec,
+    /// Arguments following the command name.
+    pub arguments: Vec<Bytes>,
 }
 
-impl From<Resp> for Command {
-    fn from(object: Resp) -> Self {
-        match object {
-            Resp::Array(Some(elements)) => parse_redis_command(elements),
-            _ => panic!("Command can only be created from Array"),
+impl CustomCommand {
+    /// Checks the number of `arguments` against the arity of `spec` and keeps them as
+    /// they are.
+    pub fn parse(spec: &'static CommandSpec, arguments: &[Resp]) -> SerirResult<Self> {
+        if !spec.accepts(arguments.len() + 1) {
+            return Err(wrong_number_of_arguments(spec.name));
+        }
+        Ok(Self {
+            spec,
+            arguments: parse_bulk_strings(arguments)?,
+        })
+    }
+
+    /// The spec of the subcommand being run for container commands, of the command
+    /// otherwise.
+    fn effective_spec(&self) -> &'static CommandSpec {
+        self.subcommand()
+            .and_then(|subcommand| self.spec.subcommand(subcommand))
+            .unwrap_or(self.spec)
+    }
+
+    fn subcommand(&self) -> Option<&str> {
+        if self.spec.subcommands.is_empty() {
+            return None;
         }
+        self.arguments
+            .first()
+            .and_then(|argument| std::str::from_utf8(argument).ok())
+    }
+
+    fn keys(&self) -> Vec<&[u8]> {
+        // key specs count the command name as the first argument
+        let args: Vec<Option<&Bytes>> = std::iter::once(None)
+            .chain(self.arguments.iter().map(Some))
+            .collect();
+        self.effective_spec()
+            .keys(&args)
+            .into_iter()
+            .filter_map(|arg| arg.map(|key| &key[..]))
+            .collect()
+    }
+
+    fn has_flag(&self, flag: &str) -> bool {
+        self.effective_spec().flags.contains(&flag)
     }
 }
 
-fn parse_redis_command(elements: Vec<Resp>) -> Command {
-    let command = match &elements[0] {
-        Resp::BulkString(Some(val)) => String::from_utf8_lossy(val),
-        _ => panic!("First element of a command must be bulk string."),
-    };
+/// Parses the arguments following the name of a built-in command.
+pub type Parser = fn(&[Resp]) -> SerirResult<Command>;
+
+/// Static description of a command: how it is called, the ACL categories it belongs to
+/// and its documentation, as reported by `COMMAND`.
+#[derive(Debug)]
+pub struct CommandSpec {
+    pub name: &'static str,
+    /// Number of arguments including the command name, or minus the minimum number of
+    /// arguments for commands taking a variable number of them.
+    pub arity: i64,
+    /// Flags like `readonly` or `denyoom`, as reported by `COMMAND INFO`.
+    pub flags: &'static [&'static str],
+    /// ACL categories, without the leading `@`.
+    pub categories: &'static [&'static str],
+    /// Where the keys are among the arguments.
+    pub key_specs: &'static [KeySpec],
+    pub docs: CommandDocs,
+    /// Subcommands of container commands like `CLIENT`.
+    pub subcommands: &'static [CommandSpec],
+}
+
+/// Where keys are among the arguments of a command, in the key specification format of
+/// Redis 7 restricted to index and range searches.
+#[derive(Debug, Clone, Copy)]
+pub struct KeySpec {
+    /// Access flags like `RO` and `access`.
+    pub flags: &'static [&'static str],
+    /// Index of the first key, the command name being at 0.
+    pub begin_index: usize,
+    /// Index of the last key relative to the first one, -1 for the last argument.
+    pub last_key: i64,
+    /// Number of arguments from a key to the next.
+    pub key_step: usize,
+}
+
+/// Documentation of a command, as reported by `COMMAND DOCS`.
+#[derive(Debug, Clone, Copy)]
+pub struct CommandDocs {
+    pub summary: &'static str,
+    /// Redis version that introduced the command.
+    pub since: &'static str,
+    pub group: &'static str,
+    pub complexity: &'static str,
+}
+
+impl CommandSpec {
+    /// Looks a subcommand up, ignoring case.
+    pub fn subcommand(&self, name: &str) -> Option<&CommandSpec> {
+        self.subcommands
+            .iter()
+            .find(|spec| spec.name.eq_ignore_ascii_case(name))
+    }
 
-    match command.to_lowercase().as_str() {
-        "get" => parse_get(&elements[1..]),
-        "set" => parse_set(&elements[1..]),
-        "command" => parse_command(&elements[1..]),
-        "config" => parse_config(&elements[1..]),
-        _ => panic!("Unknown command: {}", command),
+    /// Whether `num_args` arguments, the command name included, satisfy the arity.
+    pub fn accepts(&self, num_args: usize) -> bool {
+        match self.arity {
+            arity if arity < 0 => num_args as i64 >= -arity,
+            arity => num_args as i64 == arity,
+        }
+    }
+
+    /// Positions of the first and last keys and the step between keys, the legacy way of
+    /// describing where keys are, all 0 for commands without keys. A negative last key
+    /// counts from the end.
+    pub fn legacy_key_range(&self) -> (i64, i64, i64) {
+        match self.key_specs {
+            [] => (0, 0, 0),
+            [first, ..] => {
+                let last = self.key_specs.last().unwrap_or(first);
+                let last_key = match last.last_key {
+                    last_key if last_key < 0 => last_key,
+                    last_key => last.begin_index as i64 + last_key,
+                };
+                (first.begin_index as i64, last_key, first.key_step as i64)
+            }
+        }
+    }
+
+    /// Extracts the keys from `args`, the command name included, according to the key
+    /// specifications.
+    pub fn keys<'a, T>(&self, args: &'a [T]) -> Vec<&'a T> {
+        let mut keys = vec![];
+        for spec in self.key_specs {
+            if spec.begin_index >= args.len() {
+                continue;
+            }
+            let last = match spec.last_key {
+                last_key if last_key < 0 => args.len() as i64 + last_key,
+                last_key => spec.begin_index as i64 + last_key,
+            };
+            let last = (last.max(0) as usize).min(args.len() - 1);
+            keys.extend(
+                args[spec.begin_index..=last]
+                    .iter()
+                    .step_by(spec.key_step.max(1)),
+            );
+        }
+        keys
     }
 }
 
-fn parse_command(_arguments: &[Resp]) -> Command {
-    Command::Command
+const fn command(
+    name: &'static str,
+    arity: i64,
+    flags: &'static [&'static str],
+    categories: &'static [&'static str],
+    docs: CommandDocs,
+) -> CommandSpec {
+    CommandSpec {
+        name,
+        arity,
+        flags,
+        categories,
+        key_specs: &[],
+        docs,
+        subcommands: &[],
+    }
 }
 
-fn parse_config(arguments: &[Resp]) -> Command {
-    // if arguments.len() != 2 {
-    //     panic!(
-    //         "CONFIG command requires two arguments, {} were given",
-    //         arguments.len()
-    //     );
-    // }
-    let val = match &arguments[1] {
-        Resp::BulkString(Some(val)) => val,
-        _ => panic!("omg"),
-    };
+const fn docs(
+    summary: &'static str,
+    since: &'static str,
+    group: &'static str,
+    complexity: &'static str,
+) -> CommandDocs {
+    CommandDocs {
+        summary,
+        since,
+        group,
+        complexity,
+    }
+}
 
-    Command::Config(String::from_utf8_lossy(val).to_string())
+const fn keys(
+    flags: &'static [&'static str],
+    begin_index: usize,
+    last_key: i64,
+    key_step: usize,
+) -> KeySpec {
+    KeySpec {
+        flags,
+        begin_index,
+        last_key,
+        key_step,
+    }
 }
 
-fn parse_set(arguments: &[Resp]) -> Command {
-    if arguments.len() != 2 {
-        panic!(
-            "SET command requires two arguments, {} were given",
-            arguments.len()
-        );
+const ADMIN: &[&str] = &["admin", "slow", "dangerous"];
+const CONNECTION: &[&str] = &["slow", "connection"];
+const CONNECTION_ADMIN: &[&str] = &["admin", "slow", "dangerous", "connection"];
+
+const ADMIN_FLAGS: &[&str] = &["admin", "noscript", "loading", "stale"];
+const CONNECTION_FLAGS: &[&str] = &["noscript", "loading", "stale"];
+const LOADING_STALE: &[&str] = &["loading", "stale"];
+
+/// The single key right after the command name, read or updated.
+const FIRST_KEY_READ: &[KeySpec] = &[keys(&["RO", "access"], 1, 0, 1)];
+const FIRST_KEY_UPDATE: &[KeySpec] = &[keys(&["RW", "update"], 1, 0, 1)];
+const FIRST_KEY_METADATA: &[KeySpec] = &[keys(&["RO"], 1, 0, 1)];
+
+/// `HELP` subcommand of container commands.
+const fn help(group: &'static str, since: &'static str) -> CommandSpec {
+    command(
+        "help",
+        2,
+        LOADING_STALE,
+        CONNECTION,
+        docs(
+            "Returns helpful text about the different subcommands.",
+            since,
+            group,
+            "O(1)",
+        ),
+    )
+}
+
+/// Every command serir knows about.
+pub const COMMAND_TABLE: &[CommandSpec] = &[
+    CommandSpec {
+        key_specs: FIRST_KEY_READ,
+        ..command(
+            "get",
+            2,
+            &["readonly", "fast"],
+            &["read", "string", "fast"],
+            docs(
+                "Returns the string value of a key.",
+                "1.0.0",
+                "string",
+                "O(1)",
+            ),
+        )
+    },
+    CommandSpec {
+        key_specs: &[keys(&["OW", "update"], 1, 0, 1)],
+        ..command(
+            "set",
+            3,
+            &["write", "denyoom"],
+            &["write", "string", "slow"],
+            docs(
+                "Sets the string value of a key, ignoring its type. The key is created if it \
+                 doesn't exist.",
+                "1.0.0",
+                "string",
+                "O(1)",
+            ),
+        )
+    },
+    CommandSpec {
+        key_specs: &[keys(&["RO", "access"], 1, -1, 1)],
+        ..command(
+            "mget",
+            -2,
+            &["readonly", "fast"],
+            &["read", "string", "fast"],
+            docs(
+                "Atomically returns the string values of one or more keys.",
+                "1.0.0",
+                "string",
+                "O(N) where N is the number of keys to retrieve.",
+            ),
+        )
+    },
+    CommandSpec {
+        key_specs: &[keys(&["OW", "update"], 1, -1, 2)],
+        ..command(
+            "mset",
+            -3,
+            &["write", "denyoom"],
+            &["write", "string", "slow"],
+            docs(
+                "Atomically creates or modifies the string values of one or more keys.",
+                "1.0.1",
+                "string",
+                "O(N) where N is the number of keys to set.",
+            ),
+        )
+    },
+    CommandSpec {
+        key_specs: &[keys(&["RM", "delete"], 1, -1, 1)],
+        ..command(
+            "del",
+            -2,
+            &["write"],
+            &["keyspace", "write", "slow"],
+            docs(
+                "Deletes one or more keys.",
+                "1.0.0",
+                "generic",
+                "O(N) where N is the number of keys that will be removed.",
+            ),
+        )
+    },
+    CommandSpec {
+        key_specs: &[keys(&["RO"], 1, -1, 1)],
+        ..command(
+            "exists",
+            -2,
+            &["readonly", "fast"],
+            &["keyspace", "read", "fast"],
+            docs(
+                "Determines whether one or more keys exist.",
+                "1.0.0",
+                "generic",
+                "O(N) where N is the number of keys to check.",
+            ),
+        )
+    },
+    CommandSpec {
+        key_specs: FIRST_KEY_UPDATE,
+        ..command(
+            "expire",
+            3,
+            &["write", "fast"],
+            &["keyspace", "write", "fast"],
+            docs(
+                "Sets the expiration time of a key in seconds.",
+                "1.0.0",
+                "generic",
+                "O(1)",
+            ),
+        )
+    },
+    CommandSpec {
+        key_specs: FIRST_KEY_UPDATE,
+        ..command(
+            "pexpire",
+            3,
+            &["write", "fast"],
+            &["keyspace", "write", "fast"],
+            docs(
+                "Sets the expiration time of a key in milliseconds.",
+                "2.6.0",
+                "generic",
+                "O(1)",
+            ),
+        )
+    },
+    CommandSpec {
+        key_specs: FIRST_KEY_METADATA,
+        ..command(
+            "ttl",
+            2,
+            &["readonly", "fast"],
+            &["keyspace", "read", "fast"],
+            docs(
+                "Returns the expiration time in seconds of a key.",
+                "1.0.0",
+                "generic",
+                "O(1)",
+            ),
+        )
+    },
+    CommandSpec {
+        key_specs: FIRST_KEY_METADATA,
+        ..command(
+            "pttl",
+            2,
+            &["readonly", "fast"],
+            &["keyspace", "read", "fast"],
+            docs(
+                "Returns the expiration time in milliseconds of a key.",
+                "2.6.0",
+                "generic",
+                "O(1)",
+            ),
+        )
+    },
+    CommandSpec {
+        key_specs: FIRST_KEY_UPDATE,
+        ..command(
+            "persist",
+            2,
+            &["write", "fast"],
+            &["keyspace", "write", "fast"],
+            docs(
+                "Removes the expiration time of a key.",
+                "2.2.0",
+                "generic",
+                "O(1)",
+            ),
+        )
+    },
+    CommandSpec {
+        subcommands: &[
+            CommandSpec {
+                key_specs: &[keys(&["RO"], 2, 0, 1)],
+                ..command(
+                    "freq",
+                    3,
+                    &["readonly"],
+                    &["keyspace", "read", "slow"],
+                    docs(
+                        "Returns the logarithmic access frequency counter of a Redis object.",
+                        "4.0.0",
+                        "generic",
+                        "O(1)",
+                    ),
+                )
+            },
+            CommandSpec {
+                key_specs: &[keys(&["RO"], 2, 0, 1)],
+                ..command(
+                    "idletime",
+                    3,
+                    &["readonly"],
+                    &["keyspace", "read", "slow"],
+                    docs(
+                        "Returns the time since the last access to a Redis object.",
+                        "2.2.3",
+                        "generic",
+                        "O(1)",
+                    ),
+                )
+            },
+        ],
+        ..command(
+            "object",
+            -2,
+            &[],
+            &["keyspace", "read", "slow"],
+            docs(
+                "A container for object introspection commands.",
+                "2.2.3",
+                "generic",
+                "Depends on subcommand.",
+            ),
+        )
+    },
+    CommandSpec {
+        subcommands: &[
+            command(
+                "count",
+                2,
+                LOADING_STALE,
+                CONNECTION,
+                docs("Returns a count of commands.", "2.8.13", "server", "O(1)"),
+            ),
+            command(
+                "docs",
+                -2,
+                LOADING_STALE,
+                CONNECTION,
+                docs(
+                    "Returns documentary information about one, multiple or all commands.",
+                    "7.0.0",
+                    "server",
+                    "O(N) where N is the number of commands to look up",
+                ),
+            ),
+            command(
+                "getkeys",
+                -3,
+                LOADING_STALE,
+                CONNECTION,
+                docs(
+                    "Extracts the key names from an arbitrary command.",
+                    "2.8.13",
+                    "server",
+                    "O(N) where N is the number of arguments to the command",
+                ),
+            ),
+            command(
+                "info",
+                -2,
+                LOADING_STALE,
+                CONNECTION,
+                docs(
+                    "Returns information about one, multiple or all commands.",
+                    "2.8.13",
+                    "server",
+                    "O(N) where N is the number of commands to look up",
+                ),
+            ),
+            command(
+                "list",
+                -2,
+                LOADING_STALE,
+                CONNECTION,
+                docs(
+                    "Returns a list of command names.",
+                    "7.0.0",
+                    "server",
+                    "O(N) where N is the total number of Redis commands",
+                ),
+            ),
+            help("server", "5.0.0"),
+        ],
+        ..command(
+            "command",
+            -1,
+            LOADING_STALE,
+            CONNECTION,
+            docs(
+                "Returns detailed information about all commands.",
+                "2.8.13",
+                "server",
+                "O(N) where N is the total number of Redis commands",
+            ),
+        )
+    },
+    CommandSpec {
+        subcommands: &[
+            command(
+                "get",
+                -3,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Returns the effective values of configuration parameters.",
+                    "2.0.0",
+                    "server",
+                    "O(N) when N is the number of configuration parameters provided",
+                ),
+            ),
+            command(
+                "set",
+                -4,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Sets configuration parameters in-flight.",
+                    "2.0.0",
+                    "server",
+                    "O(N) when N is the number of configuration parameters provided",
+                ),
+            ),
+            command(
+                "rewrite",
+                2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Persists the effective configuration to file.",
+                    "2.8.0",
+                    "server",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "resetstat",
+                2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs("Resets the server's statistics.", "2.0.0", "server", "O(1)"),
+            ),
+        ],
+        ..command(
+            "config",
+            -2,
+            &[],
+            ADMIN,
+            docs(
+                "A container for server configuration commands.",
+                "2.0.0",
+                "server",
+                "Depends on subcommand.",
+            ),
+        )
+    },
+    command(
+        "info",
+        -1,
+        LOADING_STALE,
+        &["slow", "dangerous"],
+        docs(
+            "Returns information and statistics about the server.",
+            "1.0.0",
+            "server",
+            "O(1)",
+        ),
+    ),
+    CommandSpec {
+        subcommands: &[
+            command(
+                "get",
+                -2,
+                &["admin", "loading", "stale"],
+                ADMIN,
+                docs(
+                    "Returns the slow log's entries.",
+                    "2.2.12",
+                    "server",
+                    "O(N) where N is the number of entries returned",
+                ),
+            ),
+            command(
+                "len",
+                2,
+                &["admin", "loading", "stale"],
+                ADMIN,
+                docs(
+                    "Returns the number of entries in the slow log.",
+                    "2.2.12",
+                    "server",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "reset",
+                2,
+                &["admin", "loading", "stale"],
+                ADMIN,
+                docs(
+                    "Clears all entries from the slow log.",
+                    "2.2.12",
+                    "server",
+                    "O(N) where N is the number of entries in the slowlog",
+                ),
+            ),
+            CommandSpec {
+                categories: ADMIN,
+                ..help("server", "6.2.0")
+            },
+        ],
+        ..command(
+            "slowlog",
+            -2,
+            &[],
+            ADMIN,
+            docs(
+                "A container for slow log commands.",
+                "2.2.12",
+                "server",
+                "Depends on subcommand.",
+            ),
+        )
+    },
+    CommandSpec {
+        subcommands: &[
+            command(
+                "latest",
+                2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Returns the latest latency samples for all events.",
+                    "2.8.13",
+                    "server",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "history",
+                3,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Returns timestamp-latency samples for an event.",
+                    "2.8.13",
+                    "server",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "reset",
+                -2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Resets the latency data for one or more events.",
+                    "2.8.13",
+                    "server",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "doctor",
+                2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Returns a human-readable latency analysis report.",
+                    "2.8.13",
+                    "server",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "histogram",
+                -2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Returns the cumulative distribution of latencies of a subset or all \
+                     commands.",
+                    "7.0.0",
+                    "server",
+                    "O(N) where N is the number of commands with latency information being \
+                     retrieved.",
+                ),
+            ),
+            CommandSpec {
+                categories: ADMIN,
+                ..help("server", "2.8.13")
+            },
+        ],
+        ..command(
+            "latency",
+            -2,
+            &[],
+            ADMIN,
+            docs(
+                "A container for latency diagnostics commands.",
+                "2.8.13",
+                "server",
+                "Depends on subcommand.",
+            ),
+        )
+    },
+    command(
+        "monitor",
+        1,
+        ADMIN_FLAGS,
+        ADMIN,
+        docs(
+            "Listens for all requests received by the server in real-time.",
+            "1.0.0",
+            "server",
+            "",
+        ),
+    ),
+    CommandSpec {
+        subcommands: &[
+            command(
+                "id",
+                2,
+                CONNECTION_FLAGS,
+                CONNECTION,
+                docs(
+                    "Returns the unique client ID of the connection.",
+                    "5.0.0",
+                    "connection",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "info",
+                2,
+                CONNECTION_FLAGS,
+                CONNECTION,
+                docs(
+                    "Returns information about the connection.",
+                    "6.2.0",
+                    "connection",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "list",
+                -2,
+                ADMIN_FLAGS,
+                CONNECTION_ADMIN,
+                docs(
+                    "Lists open connections.",
+                    "2.4.0",
+                    "connection",
+                    "O(N) where N is the number of client connections",
+                ),
+            ),
+            command(
+                "setname",
+                3,
+                CONNECTION_FLAGS,
+                CONNECTION,
+                docs("Sets the connection name.", "2.6.9", "connection", "O(1)"),
+            ),
+            command(
+                "getname",
+                2,
+                CONNECTION_FLAGS,
+                CONNECTION,
+                docs(
+                    "Returns the name of the connection.",
+                    "2.6.9",
+                    "connection",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "kill",
+                -3,
+                ADMIN_FLAGS,
+                CONNECTION_ADMIN,
+                docs(
+                    "Terminates open connections.",
+                    "2.4.0",
+                    "connection",
+                    "O(N) where N is the number of client connections",
+                ),
+            ),
+            command(
+                "pause",
+                -3,
+                ADMIN_FLAGS,
+                CONNECTION_ADMIN,
+                docs(
+                    "Suspends commands processing.",
+                    "3.0.0",
+                    "connection",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "unpause",
+                2,
+                ADMIN_FLAGS,
+                CONNECTION_ADMIN,
+                docs(
+                    "Resumes processing commands from paused clients.",
+                    "6.2.0",
+                    "connection",
+                    "O(N) Where N is the number of paused clients",
+                ),
+            ),
+            command(
+                "no-evict",
+                3,
+                ADMIN_FLAGS,
+                CONNECTION_ADMIN,
+                docs(
+                    "Sets the client eviction mode of the connection.",
+                    "7.0.0",
+                    "connection",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "reply",
+                3,
+                CONNECTION_FLAGS,
+                CONNECTION,
+                docs(
+                    "Instructs the server whether to reply to commands.",
+                    "3.2.0",
+                    "connection",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "tracking",
+                -3,
+                CONNECTION_FLAGS,
+                CONNECTION,
+                docs(
+                    "Controls server-assisted client-side caching for the connection.",
+                    "6.0.0",
+                    "connection",
+                    "O(1). Some options may introduce additional complexity.",
+                ),
+            ),
+            command(
+                "caching",
+                3,
+                CONNECTION_FLAGS,
+                CONNECTION,
+                docs(
+                    "Instructs the server whether to track the keys in the next request.",
+                    "6.0.0",
+                    "connection",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "getredir",
+                2,
+                CONNECTION_FLAGS,
+                CONNECTION,
+                docs(
+                    "Returns the client ID to which the connection's tracking notifications \
+                     are redirected.",
+                    "6.0.0",
+                    "connection",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "trackinginfo",
+                2,
+                CONNECTION_FLAGS,
+                CONNECTION,
+                docs(
+                    "Returns information about server-assisted client-side caching for the \
+                     connection.",
+                    "6.2.0",
+                    "connection",
+                    "O(1)",
+                ),
+            ),
+            help("connection", "5.0.0"),
+        ],
+        ..command(
+            "client",
+            -2,
+            &[],
+            CONNECTION,
+            docs(
+                "A container for client connection commands.",
+                "2.4.0",
+                "connection",
+                "Depends on subcommand.",
+            ),
+        )
+    },
+    command(
+        "auth",
+        -2,
+        &[
+            "noscript",
+            "loading",
+            "stale",
+            "fast",
+            "no_auth",
+            "allow_busy",
+        ],
+        &["fast", "connection"],
+        docs(
+            "Authenticates the connection.",
+            "1.0.0",
+            "connection",
+            "O(N) where N is the number of passwords defined for the user",
+        ),
+    ),
+    CommandSpec {
+        subcommands: &[
+            command(
+                "setuser",
+                -3,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Creates and modifies an ACL user and its rules.",
+                    "6.0.0",
+                    "server",
+                    "O(N). Where N is the number of rules provided.",
+                ),
+            ),
+            command(
+                "getuser",
+                3,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Lists the ACL rules of a user.",
+                    "6.0.0",
+                    "server",
+                    "O(N). Where N is the number of password, command and pattern rules \
+                     that the user has.",
+                ),
+            ),
+            command(
+                "deluser",
+                -3,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Deletes ACL users, and terminates their connections.",
+                    "6.0.0",
+                    "server",
+                    "O(1) amortized time considering the typical user.",
+                ),
+            ),
+            command(
+                "list",
+                2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Dumps the effective rules in ACL file format.",
+                    "6.0.0",
+                    "server",
+                    "O(N). Where N is the number of configured users.",
+                ),
+            ),
+            command(
+                "users",
+                2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Lists all ACL users.",
+                    "6.0.0",
+                    "server",
+                    "O(N). Where N is the number of configured users.",
+                ),
+            ),
+            command(
+                "log",
+                -2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Lists recent security events generated due to ACL rules.",
+                    "6.0.0",
+                    "server",
+                    "O(N) with N being the number of entries shown.",
+                ),
+            ),
+            command(
+                "save",
+                2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Saves the effective ACL rules in the configured ACL file.",
+                    "6.0.0",
+                    "server",
+                    "O(N). Where N is the number of configured users.",
+                ),
+            ),
+            command(
+                "load",
+                2,
+                ADMIN_FLAGS,
+                ADMIN,
+                docs(
+                    "Reloads the rules from the configured ACL file.",
+                    "6.0.0",
+                    "server",
+                    "O(N). Where N is the number of configured users.",
+                ),
+            ),
+            command(
+                "whoami",
+                2,
+                CONNECTION_FLAGS,
+                &["slow"],
+                docs(
+                    "Returns the authenticated username of the current connection.",
+                    "6.0.0",
+                    "server",
+                    "O(1)",
+                ),
+            ),
+            command(
+                "cat",
+                -2,
+                CONNECTION_FLAGS,
+                &["slow"],
+                docs(
+                    "Lists the ACL categories, or the commands inside a category.",
+                    "6.0.0",
+                    "server",
+                    "O(1) since the categories and commands are a fixed set.",
+                ),
+            ),
+            CommandSpec {
+                categories: &["slow"],
+                ..help("server", "6.0.0")
+            },
+        ],
+        ..command(
+            "acl",
+            -2,
+            &[],
+            &["slow"],
+            docs(
+                "A container for Access List Control commands.",
+                "6.0.0",
+                "server",
+                "Depends on subcommand.",
+            ),
+        )
+    },
+    command(
+        "shutdown",
+        -1,
+        &[
+            "admin",
+            "noscript",
+            "loading",
+            "stale",
+            "no_multi",
+            "allow_busy",
+        ],
+        ADMIN,
+        docs(
+            "Synchronously saves the database(s) to disk and shuts down the Redis server.",
+            "1.0.0",
+            "server",
+            "O(N) when saving, where N is the total number of keys in all databases when \
+             saving data, otherwise O(1)",
+        ),
+    ),
+    command(
+        "hello",
+        -1,
+        &[
+            "noscript",
+            "loading",
+            "stale",
+            "fast",
+            "no_auth",
+            "allow_busy",
+        ],
+        &["fast", "connection"],
+        docs(
+            "Handshakes with the Redis server.",
+            "6.0.0",
+            "connection",
+            "O(1)",
+        ),
+    ),
+    CommandSpec {
+        subcommands: &[
+            command(
+                "load",
+                -3,
+                &["admin", "noscript", "no_async_loading"],
+                ADMIN,
+                docs("Loads a module.", "4.0.0", "server", "O(1)"),
+            ),
+            command(
+                "unload",
+                3,
+                &["admin", "noscript", "no_async_loading"],
+                ADMIN,
+                docs("Unloads a module.", "4.0.0", "server", "O(1)"),
+            ),
+            command(
+                "list",
+                2,
+                &["admin", "noscript"],
+                ADMIN,
+                docs(
+                    "Returns all loaded modules.",
+                    "4.0.0",
+                    "server",
+                    "O(N) where N is the number of loaded modules.",
+                ),
+            ),
+            CommandSpec {
+                categories: &["slow"],
+                ..help("server", "5.0.0")
+            },
+        ],
+        ..command(
+            "module",
+            -2,
+            &[],
+            &["slow"],
+            docs(
+                "A container for module commands.",
+                "4.0.0",
+                "server",
+                "Depends on subcommand.",
+            ),
+        )
+    },
+];
+
+/// Looks a command up in [`COMMAND_TABLE`], ignoring case.
+pub fn command_spec(name: &str) -> Option<&'static CommandSpec> {
+    COMMAND_TABLE
+        .iter()
+        .find(|spec| spec.name.eq_ignore_ascii_case(name))
+}
+
+/// Looks a command or a subcommand named like `client|kill` up, ignoring case.
+pub fn lookup_command(name: &str) -> Option<&'static CommandSpec> {
+    match name.split_once('|') {
+        Some((name, subcommand)) => command_spec(name)?.subcommand(subcommand),
+        None => command_spec(name),
+    }
+}
+
+fn bulk(value: &str) -> Resp {
+    Resp::BulkString(Some(Bytes::copy_from_slice(value.as_bytes())))
+}
+
+fn status_set<'a>(values: impl Iterator<Item = &'a str>) -> Resp {
+    Resp::Set(
+        values
+            .map(|value| Resp::SimpleString(value.as_bytes().to_vec()))
+            .collect(),
+    )
+}
+
+impl CommandSpec {
+    /// The reply to `COMMAND INFO` describing the command, named `name` which includes
+    /// the container's name for subcommands.
+    pub fn info_reply(&self, name: &str) -> Resp {
+        let (first_key, last_key, key_step) = self.legacy_key_range();
+        let categories: Vec<String> = self
+            .categories
+            .iter()
+            .map(|category| format!("@{}", category))
+            .collect();
+        Resp::Array(Some(vec![
+            bulk(name),
+            Resp::Integer(self.arity),
+            status_set(self.flags.iter().copied()),
+            Resp::Integer(first_key),
+            Resp::Integer(last_key),
+            Resp::Integer(key_step),
+            status_set(categories.iter().map(String::as_str)),
+            // tips
+            Resp::Array(Some(vec![])),
+            Resp::Array(Some(
+                self.key_specs.iter().map(|spec| spec.to_resp()).collect(),
+            )),
+            Resp::Array(Some(
+                self.subcommands
+                    .iter()
+                    .map(|subcommand| {
+                        subcommand.info_reply(&format!("{}|{}", name, subcommand.name))
+                    })
+                    .collect(),
+            )),
+        ]))
+    }
+
+    /// The reply to `COMMAND DOCS` documenting the command, named `name` which includes
+    /// the container's name for subcommands.
+    pub fn docs_reply(&self, name: &str) -> Resp {
+        let docs = &self.docs;
+        let mut fields = vec![
+            (bulk("summary"), bulk(docs.summary)),
+            (bulk("since"), bulk(docs.since)),
+            (bulk("group"), bulk(docs.group)),
+        ];
+        if !docs.complexity.is_empty() {
+            fields.push((bulk("complexity"), bulk(docs.complexity)));
+        }
+        if !self.subcommands.is_empty() {
+            let subcommands = self
+                .subcommands
+                .iter()
+                .map(|subcommand| {
+                    let name = format!("{}|{}", name, subcommand.name);
+                    (bulk(&name), subcommand.docs_reply(&name))
+                })
+                .collect();
+            fields.push((bulk("subcommands"), Resp::Map(subcommands)));
+        }
+        Resp::Map(fields)
+    }
+}
+
+impl KeySpec {
+    fn to_resp(self) -> Resp {
+        Resp::Map(vec![
+            (bulk("flags"), status_set(self.flags.iter().copied())),
+            (
+                bulk("begin_search"),
+                Resp::Map(vec![
+                    (bulk("type"), bulk("index")),
+                    (
+                        bulk("spec"),
+                        Resp::Map(vec![(
+                            bulk("index"),
+                            Resp::Integer(self.begin_index as i64),
+                        )]),
+                    ),
+                ]),
+            ),
+            (
+                bulk("find_keys"),
+                Resp::Map(vec![
+                    (bulk("type"), bulk("range")),
+                    (
+                        bulk("spec"),
+                        Resp::Map(vec![
+                            (bulk("lastkey"), Resp::Integer(self.last_key)),
+                            (bulk("keystep"), Resp::Integer(self.key_step as i64)),
+                            (bulk("limit"), Resp::Integer(0)),
+                        ]),
+                    ),
+                ]),
+            ),
+        ])
+    }
+}
+
+impl Command {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Command::Get(_) => "get",
+            Command::Set(_) => "set",
+            Command::MGet(_) => "mget",
+            Command::MSet(_) => "mset",
+            Command::Del(_) => "del",
+            Command::Exists(_) => "exists",
+            Command::Expire(_) => "expire",
+            Command::PExpire(_) => "pexpire",
+            Command::Ttl(_) => "ttl",
+            Command::PTtl(_) => "pttl",
+            Command::Persist(_) => "persist",
+            Command::Object(_) => "object",
+            Command::Command(_) => "command",
+            Command::Config(_) => "config",
+            Command::Info(_) => "info",
+            Command::Slowlog(_) => "slowlog",
+            Command::Latency(_) => "latency",
+            Command::Monitor => "monitor",
+            Command::Client(_) => "client",
+            Command::Auth(_) => "auth",
+            Command::Acl(_) => "acl",
+            Command::Shutdown(_) => "shutdown",
+            Command::Hello(_) => "hello",
+            Command::Module(_) => "module",
+            Command::Custom(command) => command.spec.name,
+        }
+    }
+
+    /// Keys the command operates on, in the order they appear in its arguments.
+    pub fn keys(&self) -> Vec<&[u8]> {
+        match self {
+            Command::Get(key)
+            | Command::Set((key, _))
+            | Command::Expire((key, _))
+            | Command::PExpire((key, _))
+            | Command::Ttl(key)
+            | Command::PTtl(key)
+            | Command::Persist(key)
+            | Command::Object((_, key)) => vec![key],
+            Command::MGet(keys) | Command::Del(keys) | Command::Exists(keys) => {
+                keys.iter().map(|key| key.as_slice()).collect()
+            }
+            Command::MSet(pairs) => pairs.iter().map(|(key, _)| key.as_slice()).collect(),
+            Command::Custom(command) => command.keys(),
+            Command::Command(_)
+            | Command::Config(_)
+            | Command::Info(_)
+            | Command::Slowlog(_)
+            | Command::Latency(_)
+            | Command::Monitor
+            | Command::Client(_)
+            | Command::Auth(_)
+            | Command::Acl(_)
+            | Command::Shutdown(_)
+            | Command::Hello(_)
+            | Command::Module(_) => vec![],
+        }
+    }
+
+    /// Whether the command may grow memory usage and therefore must be refused when
+    /// `maxmemory` is reached and nothing can be evicted.
+    pub fn is_denyoom(&self) -> bool {
+        match self {
+            Command::Custom(command) => command.has_flag("denyoom"),
+            command => matches!(command, Command::Set(_) | Command::MSet(_)),
+        }
     }
-    let key = match &arguments[0] {
-        Resp::BulkString(Some(val)) => val,
-        _ => panic!("omg"),
+
+    /// First argument of container commands, e.g. `kill` for `CLIENT KILL`.
+    pub fn subcommand(&self) -> Option<&str> {
+        match self {
+            Command::Object((subcommand, _)) => Some(subcommand),
+            Command::Config(arguments)
+            | Command::Slowlog(arguments)
+            | Command::Latency(arguments)
+            | Command::Client(arguments)
+            | Command::Module(arguments) => arguments.first().map(String::as_str),
+            Command::Command(arguments) | Command::Acl(arguments) => arguments
+                .first()
+                .and_then(|argument| std::str::from_utf8(argument).ok()),
+            Command::Custom(command) => command.subcommand(),
+            _ => None,
+        }
+    }
+
+    /// Whether the command may change the keyspace, such commands wait during a
+    /// `CLIENT PAUSE WRITE`.
+    pub fn is_write(&self) -> bool {
+        match self {
+            Command::Custom(command) => command.has_flag("write"),
+            command => matches!(
+                command,
+                Command::Set(_)
+                    | Command::MSet(_)
+                    | Command::Del(_)
+                    | Command::Expire(_)
+                    | Command::PExpire(_)
+                    | Command::Persist(_)
+            ),
+        }
+    }
+}
+
+impl TryFrom<Resp> for Command {
+    type Error = SerirError;
+
+    /// Parses a request for a built-in command. Servers parse requests with their
+    /// [`crate::registry::CommandRegistry`] instead, which may know other commands.
+    fn try_from(object: Resp) -> SerirResult<Self> {
+        let (name, elements) = split_request(object)?;
+        match builtin_parser(&name) {
+            Some(parse) => parse(&elements[1..]),
+            None => Err(unknown_command(&name, &elements[1..])),
+        }
+    }
+}
+
+/// Splits a request into the lowercase name of the command and all of its elements, the
+/// name included.
+pub(crate) fn split_request(object: Resp) -> SerirResult<(String, Vec<Resp>)> {
+    let elements = match object {
+        Resp::Array(Some(elements)) if !elements.is_empty() => elements,
+        _ => {
+            return Err(SerirError::CommandError(
+                "ERR Protocol error: expected a non-empty array of bulk strings".to_string(),
+            ))
+        }
+    };
+    let name = match &elements[0] {
+        Resp::BulkString(Some(val)) => String::from_utf8_lossy(val).to_lowercase(),
+        _ => {
+            return Err(SerirError::CommandError(
+                "ERR Protocol error: command name must be a bulk string".to_string(),
+            ))
+        }
     };
-    let value = match &arguments[1] {
-        Resp::BulkString(Some(val)) => val,
-        _ => panic!("omg"),
+    Ok((name, elements))
+}
+
+/// The parser of the built-in command `name`, given in lowercase.
+pub(crate) fn builtin_parser(name: &str) -> Option<Parser> {
+    let parser: Parser = match name {
+        "get" => parse_get,
+        "set" => parse_set,
+        "mget" => parse_mget,
+        "mset" => parse_mset,
+        "del" => parse_del,
+        "exists" => parse_exists,
+        "expire" => |arguments| Ok(Command::Expire(parse_key_and_integer("expire", arguments)?)),
+        "pexpire" => |arguments| {
+            Ok(Command::PExpire(parse_key_and_integer(
+                "pexpire", arguments,
+            )?))
+        },
+        "ttl" => |arguments| Ok(Command::Ttl(parse_single_key("ttl", arguments)?)),
+        "pttl" => |arguments| Ok(Command::PTtl(parse_single_key("pttl", arguments)?)),
+        "persist" => |arguments| Ok(Command::Persist(parse_single_key("persist", arguments)?)),
+        "object" => parse_object,
+        "command" => parse_command,
+        "config" => |arguments| Ok(Command::Config(parse_utf8_strings("config", arguments)?)),
+        "info" => |arguments| Ok(Command::Info(parse_strings(arguments)?)),
+        "slowlog" => |arguments| Ok(Command::Slowlog(parse_strings(arguments)?)),
+        "latency" => |arguments| Ok(Command::Latency(parse_strings(arguments)?)),
+        "monitor" => |arguments| match arguments {
+            [] => Ok(Command::Monitor),
+            _ => Err(wrong_number_of_arguments("monitor")),
+        },
+        "client" => |arguments| Ok(Command::Client(parse_strings(arguments)?)),
+        "auth" => parse_auth,
+        "acl" => |arguments| Ok(Command::Acl(parse_keys(arguments)?)),
+        "shutdown" => |arguments| Ok(Command::Shutdown(parse_strings(arguments)?)),
+        "hello" => |arguments| Ok(Command::Hello(parse_keys(arguments)?)),
+        "module" => |arguments| Ok(Command::Module(parse_strings(arguments)?)),
+        _ => return None,
     };
-    Command::Set((key.clone(), value.clone()))
+    Some(parser)
 }
 
-fn parse_get(arguments: &[Resp]) -> Command {
+pub(crate) fn unknown_command(command: &str, arguments: &[Resp]) -> SerirError {
+    let arguments: String = arguments
+        .iter()
+        .filter_map(|argument| match argument {
+            Resp::BulkString(Some(val)) => Some(format!("'{}' ", String::from_utf8_lossy(val))),
+            _ => None,
+        })
+        .collect();
+    SerirError::CommandError(format!(
+        "ERR unknown command '{}', with args beginning with: {}",
+        command, arguments
+    ))
+}
+
+fn wrong_number_of_arguments(command: &str) -> SerirError {
+    SerirError::CommandError(format!(
+        "ERR wrong number of arguments for '{}' command",
+        command
+    ))
+}
+
+fn parse_command(arguments: &[Resp]) -> SerirResult<Command> {
+    Ok(Command::Command(parse_keys(arguments)?))
+}
+
+fn parse_set(arguments: &[Resp]) -> SerirResult<Command> {
+    if arguments.len() != 2 {
+        return Err(wrong_number_of_arguments("set"));
+    }
+    let mut arguments = parse_bulk_strings(arguments)?;
+    let value = arguments.remove(1);
+    Ok(Command::Set((arguments.remove(0).to_vec(), value)))
+}
+
+/// Takes the arguments as they were received, sharing the request's buffer.
+fn parse_bulk_strings(arguments: &[Resp]) -> SerirResult<Vec<Bytes>> {
+    arguments
+        .iter()
+        .map(|argument| match argument {
+            Resp::BulkString(Some(val)) => Ok(val.clone()),
+            _ => Err(SerirError::CommandError(
+                "ERR Protocol error: arguments must be bulk strings".to_string(),
+            )),
+        })
+        .collect()
+}
+
+fn parse_keys(arguments: &[Resp]) -> SerirResult<Vec<Vec<u8>>> {
+    Ok(parse_bulk_strings(arguments)?
+        .iter()
+        .map(|key| key.to_vec())
+        .collect())
+}
+
+/// Parses arguments that are validated when the command is executed, so that mistakes are
+/// reported with the context only the executor has.
+fn parse_strings(arguments: &[Resp]) -> SerirResult<Vec<String>> {
+    Ok(parse_bulk_strings(arguments)?
+        .iter()
+        .map(|argument| String::from_utf8_lossy(argument).to_string())
+        .collect())
+}
+
+/// Parses arguments that must be text, like the values of passwords in configuration,
+/// which would be mangled by replacing invalid UTF-8.
+fn parse_utf8_strings(name: &str, arguments: &[Resp]) -> SerirResult<Vec<String>> {
+    parse_bulk_strings(arguments)?
+        .iter()
+        .map(|argument| {
+            String::from_utf8(argument.to_vec()).map_err(|_| {
+                SerirError::CommandError(format!("ERR arguments of '{}' must be valid UTF-8", name))
+            })
+        })
+        .collect()
+}
+
+fn parse_mget(arguments: &[Resp]) -> SerirResult<Command> {
+    if arguments.is_empty() {
+        return Err(wrong_number_of_arguments("mget"));
+    }
+    Ok(Command::MGet(parse_keys(arguments)?))
+}
+
+fn parse_mset(arguments: &[Resp]) -> SerirResult<Command> {
+    if arguments.is_empty() || !arguments.len().is_multiple_of(2) {
+        return Err(wrong_number_of_arguments("mset"));
+    }
+    let mut pairs = parse_bulk_strings(arguments)?.into_iter();
+    let mut result = vec![];
+    while let (Some(key), Some(value)) = (pairs.next(), pairs.next()) {
+        result.push((key.to_vec(), value));
+    }
+    Ok(Command::MSet(result))
+}
+
+fn parse_del(arguments: &[Resp]) -> SerirResult<Command> {
+    if arguments.is_empty() {
+        return Err(wrong_number_of_arguments("del"));
+    }
+    Ok(Command::Del(parse_keys(arguments)?))
+}
+
+fn parse_exists(arguments: &[Resp]) -> SerirResult<Command> {
+    if arguments.is_empty() {
+        return Err(wrong_number_of_arguments("exists"));
+    }
+    Ok(Command::Exists(parse_keys(arguments)?))
+}
+
+fn parse_single_key(name: &str, arguments: &[Resp]) -> SerirResult<Vec<u8>> {
     if arguments.len() != 1 {
-        panic!(
-            "SET command requires two arguments, {} were given",
-            arguments.len()
-        );
+        return Err(wrong_number_of_arguments(name));
     }
-    let key = match &arguments[0] {
-        Resp::BulkString(Some(val)) => val,
-        _ => panic!("omg"),
-    };
-    Command::Get(key.clone())
+    Ok(parse_keys(arguments)?.remove(0))
+}
+
+fn parse_key_and_integer(name: &str, arguments: &[Resp]) -> SerirResult<(Vec<u8>, i64)> {
+    if arguments.len() != 2 {
+        return Err(wrong_number_of_arguments(name));
+    }
+    let mut arguments = parse_keys(arguments)?;
+    let value = String::from_utf8_lossy(&arguments.remove(1))
+        .parse::<i64>()
+        .map_err(|_| {
+            SerirError::CommandError("ERR value is not an integer or out of range".to_string())
+        })?;
+    Ok((arguments.remove(0), value))
+}
+
+fn parse_object(arguments: &[Resp]) -> SerirResult<Command> {
+    if arguments.len() != 2 {
+        return Err(wrong_number_of_arguments("object"));
+    }
+    let mut arguments = parse_keys(arguments)?;
+    let subcommand = String::from_utf8_lossy(&arguments[0]).to_string();
+    Ok(Command::Object((subcommand, arguments.remove(1))))
+}
+
+fn parse_auth(arguments: &[Resp]) -> SerirResult<Command> {
+    let mut arguments = parse_keys(arguments)?;
+    match arguments.len() {
+        1 => Ok(Command::Auth((None, arguments.remove(0)))),
+        2 => {
+            let password = arguments.remove(1);
+            let username = String::from_utf8_lossy(&arguments[0]).to_string();
+            Ok(Command::Auth((Some(username), password)))
+        }
+        _ => Err(SerirError::CommandError("ERR syntax error".to_string())),
+    }
+}
+
+fn parse_get(arguments: &[Resp]) -> SerirResult<Command> {
+    if arguments.len() != 1 {
+        return Err(wrong_number_of_arguments("get"));
+    }
+    Ok(Command::Get(parse_keys(arguments)?.remove(0)))
 }
 
 #[cfg(test)]
@@ -90,10 +1680,10 @@ mod tests {
     #[test]
     fn parses_get_command() {
         let resp = Resp::Array(Some(vec![
-            Resp::BulkString(Some(b"GET".to_vec())),
-            Resp::BulkString(Some(b"key".to_vec())),
+            Resp::BulkString(Some(Bytes::from_static(b"GET"))),
+            Resp::BulkString(Some(Bytes::from_static(b"key"))),
         ]));
-        let command = Command::from(resp);
+        let command = Command::try_from(resp).unwrap();
 
         if let Command::Get(key) = command {
             assert_eq!(key, b"key".to_vec());
@@ -105,10 +1695,10 @@ mod tests {
     #[test]
     fn parses_lowercase_get_command() {
         let resp = Resp::Array(Some(vec![
-            Resp::BulkString(Some(b"get".to_vec())),
-            Resp::BulkString(Some(b"key".to_vec())),
+            Resp::BulkString(Some(Bytes::from_static(b"get"))),
+            Resp::BulkString(Some(Bytes::from_static(b"key"))),
         ]));
-        let command = Command::from(resp);
+        let command = Command::try_from(resp).unwrap();
 
         if let Command::Get(key) = command {
             assert_eq!(key, b"key".to_vec());
@@ -118,24 +1708,26 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    fn panics_get_parsing_with_wrong_num_args() {
+    fn rejects_get_with_wrong_num_args() {
         let resp = Resp::Array(Some(vec![
-            Resp::BulkString(Some(b"get".to_vec())),
-            Resp::BulkString(Some(b"key".to_vec())),
-            Resp::BulkString(Some(b"value".to_vec())),
+            Resp::BulkString(Some(Bytes::from_static(b"get"))),
+            Resp::BulkString(Some(Bytes::from_static(b"key"))),
+            Resp::BulkString(Some(Bytes::from_static(b"value"))),
         ]));
-        Command::from(resp);
+        assert!(matches!(
+            Command::try_from(resp),
+            Err(SerirError::CommandError(msg)) if msg == "ERR wrong number of arguments for 'get' command"
+        ));
     }
 
     #[test]
     fn parses_set_command() {
         let resp = Resp::Array(Some(vec![
-            Resp::BulkString(Some(b"SET".to_vec())),
-            Resp::BulkString(Some(b"key".to_vec())),
-            Resp::BulkString(Some(b"value".to_vec())),
+            Resp::BulkString(Some(Bytes::from_static(b"SET"))),
+            Resp::BulkString(Some(Bytes::from_static(b"key"))),
+            Resp::BulkString(Some(Bytes::from_static(b"value"))),
         ]));
-        let command = Command::from(resp);
+        let command = Command::try_from(resp).unwrap();
 
         if let Command::Set((key, value)) = command {
             assert_eq!(key, b"key".to_vec());
@@ -148,11 +1740,11 @@ mod tests {
     #[test]
     fn parses_lowercase_set_command() {
         let resp = Resp::Array(Some(vec![
-            Resp::BulkString(Some(b"set".to_vec())),
-            Resp::BulkString(Some(b"key".to_vec())),
-            Resp::BulkString(Some(b"value".to_vec())),
+            Resp::BulkString(Some(Bytes::from_static(b"set"))),
+            Resp::BulkString(Some(Bytes::from_static(b"key"))),
+            Resp::BulkString(Some(Bytes::from_static(b"value"))),
         ]));
-        let command = Command::from(resp);
+        let command = Command::try_from(resp).unwrap();
 
         if let Command::Set((key, value)) = command {
             assert_eq!(key, b"key".to_vec());
@@ -163,12 +1755,157 @@ mod tests {
     }
 
     #[test]
-    #[should_panic]
-    fn panics_set_parsing_with_wrong_num_args() {
+    fn parses_mset_command() {
         let resp = Resp::Array(Some(vec![
-            Resp::BulkString(Some(b"set".to_vec())),
-            Resp::BulkString(Some(b"key".to_vec())),
+            Resp::BulkString(Some(Bytes::from_static(b"MSET"))),
+            Resp::BulkString(Some(Bytes::from_static(b"k1"))),
+            Resp::BulkString(Some(Bytes::from_static(b"v1"))),
+            Resp::BulkString(Some(Bytes::from_static(b"k2"))),
+            Resp::BulkString(Some(Bytes::from_static(b"v2"))),
         ]));
-        Command::from(resp);
+        let command = Command::try_from(resp).unwrap();
+
+        if let Command::MSet(pairs) = command {
+            assert_eq!(
+                pairs,
+                vec![
+                    (b"k1".to_vec(), Bytes::from_static(b"v1")),
+                    (b"k2".to_vec(), Bytes::from_static(b"v2"))
+                ]
+            );
+        } else {
+            panic!("Error parsing MSET command.");
+        }
+    }
+
+    #[test]
+    fn rejects_mset_with_odd_num_args() {
+        let resp = Resp::Array(Some(vec![
+            Resp::BulkString(Some(Bytes::from_static(b"mset"))),
+            Resp::BulkString(Some(Bytes::from_static(b"k1"))),
+            Resp::BulkString(Some(Bytes::from_static(b"v1"))),
+            Resp::BulkString(Some(Bytes::from_static(b"k2"))),
+        ]));
+        assert!(matches!(
+            Command::try_from(resp),
+            Err(SerirError::CommandError(msg)) if msg == "ERR wrong number of arguments for 'mset' command"
+        ));
+    }
+
+    #[test]
+    fn parses_expire_command() {
+        let resp = Resp::Array(Some(vec![
+            Resp::BulkString(Some(Bytes::from_static(b"EXPIRE"))),
+            Resp::BulkString(Some(Bytes::from_static(b"key"))),
+            Resp::BulkString(Some(Bytes::from_static(b"-10"))),
+        ]));
+        let command = Command::try_from(resp).unwrap();
+
+        if let Command::Expire((key, seconds)) = command {
+            assert_eq!(key, b"key".to_vec());
+            assert_eq!(seconds, -10);
+        } else {
+            panic!("Error parsing EXPIRE command.");
+        }
+    }
+
+    #[test]
+    fn rejects_expire_with_non_integer_ttl() {
+        let resp = Resp::Array(Some(vec![
+            Resp::BulkString(Some(Bytes::from_static(b"expire"))),
+            Resp::BulkString(Some(Bytes::from_static(b"key"))),
+            Resp::BulkString(Some(Bytes::from_static(b"soon"))),
+        ]));
+        assert!(matches!(
+            Command::try_from(resp),
+            Err(SerirError::CommandError(msg)) if msg == "ERR value is not an integer or out of range"
+        ));
+    }
+
+    #[test]
+    fn reports_unknown_commands() {
+        let resp = Resp::Array(Some(vec![
+            Resp::BulkString(Some(Bytes::from_static(b"FOO"))),
+            Resp::BulkString(Some(Bytes::from_static(b"bar"))),
+        ]));
+
+        match Command::try_from(resp) {
+            Err(SerirError::CommandError(msg)) => assert_eq!(
+                msg,
+                "ERR unknown command 'foo', with args beginning with: 'bar' "
+            ),
+            _ => panic!("Unknown command was parsed."),
+        }
+    }
+
+    #[test]
+    fn rejects_set_with_wrong_num_args() {
+        let resp = Resp::Array(Some(vec![
+            Resp::BulkString(Some(Bytes::from_static(b"set"))),
+            Resp::BulkString(Some(Bytes::from_static(b"key"))),
+        ]));
+        assert!(matches!(
+            Command::try_from(resp),
+            Err(SerirError::CommandError(msg)) if msg == "ERR wrong number of arguments for 'set' command"
+        ));
+    }
+
+    #[test]
+    fn parses_inline_commands() {
+        let resp = Resp::deserialize(b"expire key 100\r\n").unwrap().remove(0);
+        if let Command::Expire((key, seconds)) = Command::try_from(resp).unwrap() {
+            assert_eq!(key, b"key".to_vec());
+            assert_eq!(seconds, 100);
+        } else {
+            panic!("Error parsing inline EXPIRE command.");
+        }
+    }
+
+    #[test]
+    fn describes_where_keys_are() {
+        let args = |args: &[&str]| -> Vec<Resp> {
+            args.iter()
+                .map(|arg| Resp::BulkString(Some(Bytes::copy_from_slice(arg.as_bytes()))))
+                .collect()
+        };
+        for request in [
+            &["GET", "a"][..],
+            &["MSET", "a", "1", "b", "2"],
+            &["DEL", "a", "b", "c"],
+            &["EXPIRE", "a", "10"],
+            &["OBJECT", "FREQ", "a"],
+            &["CLIENT", "ID"],
+        ] {
+            let request = args(request);
+            let command = Command::try_from(Resp::Array(Some(request.clone()))).unwrap();
+            let mut spec = command_spec(command.name()).unwrap();
+            if let Some(subcommand) = command.subcommand() {
+                spec = spec.subcommand(subcommand).unwrap();
+            }
+            assert!(spec.accepts(request.len()));
+            let keys: Vec<&[u8]> = spec
+                .keys(&request)
+                .into_iter()
+                .map(|key| match key {
+                    Resp::BulkString(Some(key)) => &key[..],
+                    _ => unreachable!(),
+                })
+                .collect();
+            assert_eq!(keys, command.keys());
+        }
+
+        assert_eq!(command_spec("mset").unwrap().legacy_key_range(), (1, -1, 2));
+        assert_eq!(command_spec("ttl").unwrap().legacy_key_range(), (1, 1, 1));
+        assert_eq!(
+            lookup_command("object|freq").unwrap().legacy_key_range(),
+            (2, 2, 1)
+        );
+        assert_eq!(
+            command_spec("client").unwrap().legacy_key_range(),
+            (0, 0, 0)
+        );
+        assert!(!command_spec("mset").unwrap().accepts(2));
+        assert!(lookup_command("CLIENT|KILL").is_some());
+        assert!(lookup_command("get|nothing").is_none());
     }
 }
@@ -0,0 +1,1298 @@
+use std::collections::HashSet;
+use std::fs;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+use std::path::{Path, PathBuf};
+
+use crate::client::{BufferLimits, ClientClass, OutputBufferLimit, OutputBufferLimits};
+use crate::error::{SerirError, SerirResult};
+use crate::eviction::{EvictionPolicy, EvictionSettings};
+use crate::glob::glob_match_nocase;
+use crate::tls::TlsAuthClients;
+
+/// Server configuration, loaded from a `redis.conf`-style file and command line flags and
+/// tunable at runtime through `CONFIG SET`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Config {
+    pub port: u16,
+    /// Port of the HTTP listener exporting Prometheus metrics, 0 to disable it.
+    pub metrics_port: u16,
+    /// Port accepting TLS connections, 0 to disable TLS.
+    pub tls_port: u16,
+    pub tls_cert_file: Option<PathBuf>,
+    pub tls_key_file: Option<PathBuf>,
+    /// CA certificates client certificates are verified against.
+    pub tls_ca_cert_file: Option<PathBuf>,
+    pub tls_auth_clients: TlsAuthClients,
+    /// Addresses to listen on, see [`BindAddr`].
+    pub bind: Vec<String>,
+    /// Refuses clients that aren't on the loopback interface or the Unix socket while the
+    /// default user has no password.
+    pub protected_mode: bool,
+    /// Path of a Unix socket to accept connections on as well.
+    pub unixsocket: Option<PathBuf>,
+    /// Permissions of the Unix socket, 0 to leave them to the umask.
+    pub unixsocketperm: u32,
+    /// Length of the queue of connections waiting to be accepted.
+    pub tcp_backlog: u32,
+    /// Seconds between TCP keepalive probes on idle connections, 0 to disable them.
+    pub tcp_keepalive: u64,
+    /// Seconds after which idle clients are disconnected, 0 to never disconnect them.
+    pub timeout: u64,
+    pub maxclients: usize,
+    /// Bytes of an incomplete request a client may send before being disconnected.
+    pub client_query_buffer_limit: u64,
+    /// Longest bulk string accepted in a request.
+    pub proto_max_bulk_len: u64,
+    pub client_output_buffer_limit: OutputBufferLimits,
+    pub io_threads: usize,
+    pub maxmemory: u64,
+    pub maxmemory_policy: EvictionPolicy,
+    pub maxmemory_samples: usize,
+    pub lfu_log_factor: u8,
+    pub lfu_decay_time: u64,
+    /// Commands slower than this many microseconds go to the slow log, negative to
+    /// disable it.
+    pub slowlog_log_slower_than: i64,
+    pub slowlog_max_len: usize,
+    /// Events slower than this many milliseconds are recorded by the latency monitor, 0
+    /// to disable it.
+    pub latency_monitor_threshold: u64,
+    pub save: Vec<(u64, u64)>,
+    pub appendonly: bool,
+    /// Seconds a shutdown waits for connections to finish their requests.
+    pub shutdown_timeout: u64,
+    pub requirepass: Option<String>,
+    /// File ACL users are loaded from at startup and by `ACL LOAD`, and saved to by
+    /// `ACL SAVE`.
+    pub aclfile: Option<PathBuf>,
+    /// Entries kept by `ACL LOG`.
+    pub acllog_max_len: usize,
+    /// Modules loaded at startup, each with its arguments, see [`crate::modules`].
+    pub loadmodule: Vec<(String, Vec<String>)>,
+    /// File the configuration was loaded from, target of `CONFIG REWRITE`.
+    pub config_file: Option<PathBuf>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            port: 6379,
+            metrics_port: 0,
+            tls_port: 0,
+            tls_cert_file: None,
+            tls_key_file: None,
+            tls_ca_cert_file: None,
+            tls_auth_clients: TlsAuthClients::Yes,
+            bind: vec!["*".to_string(), "-::*".to_string()],
+            protected_mode: true,
+            unixsocket: None,
+            unixsocketperm: 0,
+            tcp_backlog: 511,
+            tcp_keepalive: 300,
+            timeout: 0,
+            maxclients: 10000,
+            client_query_buffer_limit: 1024 * 1024 * 1024,
+            proto_max_bulk_len: 512 * 1024 * 1024,
+            client_output_buffer_limit: OutputBufferLimits::default(),
+            io_threads: crate::default_num_workers(),
+            maxmemory: 0,
+            maxmemory_policy: EvictionPolicy::NoEviction,
+            maxmemory_samples: 5,
+            lfu_log_factor: 10,
+            lfu_decay_time: 1,
+            slowlog_log_slower_than: 10000,
+            slowlog_max_len: 128,
+            latency_monitor_threshold: 0,
+            save: vec![(3600, 1), (300, 100), (60, 10000)],
+            appendonly: false,
+            shutdown_timeout: 10,
+            requirepass: None,
+            aclfile: None,
+            acllog_max_len: 128,
+            loadmodule: vec![],
+            config_file: None,
+        }
+    }
+}
+
+/// An address of `bind`: an IPv4 or IPv6 address, `*` for every IPv4 address or `::*`
+/// for every IPv6 one. Addresses prefixed with `-` are optional, the server starts even
+/// if they aren't available, e.g. on hosts without IPv6.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BindAddr {
+    pub ip: IpAddr,
+    pub optional: bool,
+}
+
+impl BindAddr {
+    pub fn parse(addr: &str) -> Result<Self, String> {
+        let (optional, ip) = match addr.strip_prefix('-') {
+            Some(ip) => (true, ip),
+            None => (false, addr),
+        };
+        let ip = match ip {
+            "*" => IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+            "::*" => IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+            ip => ip
+                .parse()
+                .map_err(|_| format!("invalid bind address '{}'", addr))?,
+        };
+        Ok(Self { ip, optional })
+    }
+}
+
+/// A single configuration parameter as seen by the config file, `CONFIG GET` and
+/// `CONFIG SET`.
+struct Param {
+    name: &'static str,
+    /// Whether the parameter can be changed with `CONFIG SET`.
+    mutable: bool,
+    /// Whether the value is a list of whitespace separated arguments rather than a single
+    /// string, e.g. `save 3600 1 300 100`.
+    multi_arg: bool,
+    get: fn(&Config) -> String,
+    set: fn(&mut Config, &[String]) -> Result<(), String>,
+}
+
+const PARAMS: &[Param] = &[
+    Param {
+        name: "port",
+        mutable: false,
+        multi_arg: false,
+        get: |config| config.port.to_string(),
+        set: |config, args| {
+            config.port = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "metrics-port",
+        mutable: false,
+        multi_arg: false,
+        get: |config| config.metrics_port.to_string(),
+        set: |config, args| {
+            config.metrics_port = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-port",
+        mutable: false,
+        multi_arg: false,
+        get: |config| config.tls_port.to_string(),
+        set: |config, args| {
+            config.tls_port = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "bind",
+        mutable: false,
+        multi_arg: true,
+        get: |config| config.bind.join(" "),
+        set: |config, args| {
+            if args.is_empty() {
+                return Err("wrong number of arguments".to_string());
+            }
+            for addr in args {
+                BindAddr::parse(addr)?;
+            }
+            config.bind = args.to_vec();
+            Ok(())
+        },
+    },
+    Param {
+        name: "protected-mode",
+        mutable: true,
+        multi_arg: false,
+        get: |config| format_bool(config.protected_mode),
+        set: |config, args| {
+            config.protected_mode = parse_bool(single(args)?)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "unixsocket",
+        mutable: false,
+        multi_arg: false,
+        get: |config| format_path(&config.unixsocket),
+        set: |config, args| {
+            config.unixsocket = parse_path(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "unixsocketperm",
+        mutable: false,
+        multi_arg: false,
+        get: |config| format!("{:o}", config.unixsocketperm),
+        set: |config, args| {
+            let arg = single(args)?;
+            config.unixsocketperm = match u32::from_str_radix(arg, 8) {
+                Ok(perm) if perm <= 0o777 => perm,
+                _ => return Err(format!("argument must be an octal mode: '{}'", arg)),
+            };
+            Ok(())
+        },
+    },
+    Param {
+        name: "tcp-backlog",
+        mutable: false,
+        multi_arg: false,
+        get: |config| config.tcp_backlog.to_string(),
+        set: |config, args| {
+            let backlog: u32 = parse_single(args)?;
+            if backlog > i32::MAX as u32 {
+                return Err("argument must be at most 2147483647".to_string());
+            }
+            config.tcp_backlog = backlog;
+            Ok(())
+        },
+    },
+    Param {
+        name: "tcp-keepalive",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.tcp_keepalive.to_string(),
+        set: |config, args| {
+            config.tcp_keepalive = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "timeout",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.timeout.to_string(),
+        set: |config, args| {
+            config.timeout = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxclients",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.maxclients.to_string(),
+        set: |config, args| {
+            let maxclients: usize = parse_single(args)?;
+            if maxclients == 0 {
+                return Err("argument must be greater than 0".to_string());
+            }
+            config.maxclients = maxclients;
+            Ok(())
+        },
+    },
+    Param {
+        name: "client-query-buffer-limit",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.client_query_buffer_limit.to_string(),
+        set: |config, args| {
+            config.client_query_buffer_limit = parse_buffer_limit(single(args)?)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "proto-max-bulk-len",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.proto_max_bulk_len.to_string(),
+        set: |config, args| {
+            config.proto_max_bulk_len = parse_buffer_limit(single(args)?)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "client-output-buffer-limit",
+        mutable: true,
+        multi_arg: true,
+        get: |config| {
+            ClientClass::ALL
+                .iter()
+                .map(|&class| {
+                    let limit = config.client_output_buffer_limit.get(class);
+                    format!(
+                        "{} {} {} {}",
+                        class.as_str(),
+                        limit.hard,
+                        limit.soft,
+                        limit.soft_seconds
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        set: |config, args| {
+            parse_output_buffer_limits(&mut config.client_output_buffer_limit, args)
+        },
+    },
+    Param {
+        name: "io-threads",
+        mutable: false,
+        multi_arg: false,
+        get: |config| config.io_threads.to_string(),
+        set: |config, args| {
+            let io_threads: usize = parse_single(args)?;
+            if io_threads == 0 {
+                return Err("argument must be greater than 0".to_string());
+            }
+            config.io_threads = io_threads;
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxmemory",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.maxmemory.to_string(),
+        set: |config, args| {
+            config.maxmemory = parse_memory(single(args)?)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxmemory-policy",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.maxmemory_policy.to_string(),
+        set: |config, args| {
+            config.maxmemory_policy = single(args)?.parse()?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "maxmemory-samples",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.maxmemory_samples.to_string(),
+        set: |config, args| {
+            let samples: usize = parse_single(args)?;
+            if !(1..=64).contains(&samples) {
+                return Err("argument must be between 1 and 64 inclusive".to_string());
+            }
+            config.maxmemory_samples = samples;
+            Ok(())
+        },
+    },
+    Param {
+        name: "lfu-log-factor",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.lfu_log_factor.to_string(),
+        set: |config, args| {
+            config.lfu_log_factor = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "lfu-decay-time",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.lfu_decay_time.to_string(),
+        set: |config, args| {
+            config.lfu_decay_time = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "slowlog-log-slower-than",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.slowlog_log_slower_than.to_string(),
+        set: |config, args| {
+            config.slowlog_log_slower_than = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "slowlog-max-len",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.slowlog_max_len.to_string(),
+        set: |config, args| {
+            config.slowlog_max_len = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "latency-monitor-threshold",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.latency_monitor_threshold.to_string(),
+        set: |config, args| {
+            config.latency_monitor_threshold = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "save",
+        mutable: true,
+        multi_arg: true,
+        get: |config| {
+            config
+                .save
+                .iter()
+                .map(|(seconds, changes)| format!("{} {}", seconds, changes))
+                .collect::<Vec<_>>()
+                .join(" ")
+        },
+        set: |config, args| {
+            config.save = parse_save(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "appendonly",
+        mutable: true,
+        multi_arg: false,
+        get: |config| format_bool(config.appendonly),
+        set: |config, args| {
+            config.appendonly = parse_bool(single(args)?)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "shutdown-timeout",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.shutdown_timeout.to_string(),
+        set: |config, args| {
+            config.shutdown_timeout = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "requirepass",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.requirepass.clone().unwrap_or_default(),
+        set: |config, args| {
+            let password = single(args)?;
+            config.requirepass = if password.is_empty() {
+                None
+            } else {
+                Some(password.to_string())
+            };
+            Ok(())
+        },
+    },
+    Param {
+        name: "aclfile",
+        mutable: false,
+        multi_arg: false,
+        get: |config| format_path(&config.aclfile),
+        set: |config, args| {
+            config.aclfile = parse_path(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "acllog-max-len",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.acllog_max_len.to_string(),
+        set: |config, args| {
+            config.acllog_max_len = parse_single(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-cert-file",
+        mutable: true,
+        multi_arg: false,
+        get: |config| format_path(&config.tls_cert_file),
+        set: |config, args| {
+            config.tls_cert_file = parse_path(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-key-file",
+        mutable: true,
+        multi_arg: false,
+        get: |config| format_path(&config.tls_key_file),
+        set: |config, args| {
+            config.tls_key_file = parse_path(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-ca-cert-file",
+        mutable: true,
+        multi_arg: false,
+        get: |config| format_path(&config.tls_ca_cert_file),
+        set: |config, args| {
+            config.tls_ca_cert_file = parse_path(args)?;
+            Ok(())
+        },
+    },
+    Param {
+        name: "tls-auth-clients",
+        mutable: true,
+        multi_arg: false,
+        get: |config| config.tls_auth_clients.to_string(),
+        set: |config, args| {
+            config.tls_auth_clients = single(args)?.parse()?;
+            Ok(())
+        },
+    },
+];
+
+fn find_param(name: &str) -> Option<&'static Param> {
+    PARAMS
+        .iter()
+        .find(|param| param.name.eq_ignore_ascii_case(name))
+}
+
+fn single(args: &[String]) -> Result<&str, String> {
+    match args {
+        [arg] => Ok(arg),
+        _ => Err("wrong number of arguments".to_string()),
+    }
+}
+
+fn parse_single<T: std::str::FromStr>(args: &[String]) -> Result<T, String> {
+    let arg = single(args)?;
+    arg.parse()
+        .map_err(|_| format!("argument couldn't be parsed: '{}'", arg))
+}
+
+fn parse_bool(arg: &str) -> Result<bool, String> {
+    match arg.to_lowercase().as_str() {
+        "yes" => Ok(true),
+        "no" => Ok(false),
+        _ => Err("argument must be 'yes' or 'no'".to_string()),
+    }
+}
+
+fn format_bool(value: bool) -> String {
+    if value { "yes" } else { "no" }.to_string()
+}
+
+/// An empty path unsets the parameter.
+fn parse_path(args: &[String]) -> Result<Option<PathBuf>, String> {
+    let path = single(args)?;
+    Ok((!path.is_empty()).then(|| PathBuf::from(path)))
+}
+
+fn format_path(path: &Option<PathBuf>) -> String {
+    path.as_ref()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default()
+}
+
+fn parse_save(args: &[String]) -> Result<Vec<(u64, u64)>, String> {
+    if let [arg] = args {
+        if arg.is_empty() {
+            return Ok(vec![]);
+        }
+    }
+    if !args.len().is_multiple_of(2) {
+        return Err("save requires pairs of <seconds> <changes>".to_string());
+    }
+    args.chunks(2)
+        .map(|pair| {
+            let seconds = pair[0].parse().map_err(|_| "invalid save seconds")?;
+            let changes = pair[1].parse().map_err(|_| "invalid save changes")?;
+            Ok((seconds, changes))
+        })
+        .collect()
+}
+
+/// Like Redis, buffer limits can't go below 1mb.
+fn parse_buffer_limit(arg: &str) -> Result<u64, String> {
+    let limit = parse_memory(arg)?;
+    if limit < 1024 * 1024 {
+        return Err("argument must be at least 1mb".to_string());
+    }
+    Ok(limit)
+}
+
+/// Updates the limits of the classes listed in `args`, as groups of
+/// `<class> <hard> <soft> <soft seconds>`.
+fn parse_output_buffer_limits(
+    limits: &mut OutputBufferLimits,
+    args: &[String],
+) -> Result<(), String> {
+    if args.is_empty() || !args.len().is_multiple_of(4) {
+        return Err(
+            "client-output-buffer-limit requires groups of <class> <hard> <soft> <seconds>"
+                .to_string(),
+        );
+    }
+    for group in args.chunks(4) {
+        let class = ClientClass::parse(&group[0])
+            .ok_or_else(|| format!("invalid client class '{}'", group[0]))?;
+        *limits.get_mut(class) = OutputBufferLimit {
+            hard: parse_memory(&group[1])?,
+            soft: parse_memory(&group[2])?,
+            soft_seconds: group[3]
+                .parse()
+                .map_err(|_| format!("invalid soft seconds '{}'", group[3]))?,
+        };
+    }
+    Ok(())
+}
+
+/// Parses a memory amount with an optional unit suffix, following Redis conventions:
+/// `1k` is 1000 bytes while `1kb` is 1024 bytes. Units are case insensitive.
+pub fn parse_memory(value: &str) -> Result<u64, String> {
+    let lower = value.to_lowercase();
+    let digits_end = lower
+        .find(|c: char| !c.is_ascii_digit())
+        .unwrap_or(lower.len());
+    let (number, unit) = lower.split_at(digits_end);
+    let multiplier: u64 = match unit {
+        "" | "b" => 1,
+        "k" => 1000,
+        "kb" => 1024,
+        "m" => 1000 * 1000,
+        "mb" => 1024 * 1024,
+        "g" => 1000 * 1000 * 1000,
+        "gb" => 1024 * 1024 * 1024,
+        _ => return Err(format!("invalid memory unit in '{}'", value)),
+    };
+    let number: u64 = number
+        .parse()
+        .map_err(|_| format!("invalid memory amount '{}'", value))?;
+    number
+        .checked_mul(multiplier)
+        .ok_or_else(|| format!("memory amount '{}' is too large", value))
+}
+
+/// Splits a config line into arguments the way Redis does: arguments are separated by
+/// whitespace and may be double quoted (with `\n`, `\xHH`, ... escapes) or single quoted.
+///
+/// Returns `None` when the quotes are unbalanced.
+pub fn split_args(line: &str) -> Option<Vec<String>> {
+    let mut args = vec![];
+    let mut chars = line.chars().peekable();
+    loop {
+        while chars.peek().is_some_and(|c| c.is_whitespace()) {
+            chars.next();
+        }
+        let Some(&first) = chars.peek() else {
+            return Some(args);
+        };
+
+        let mut arg = String::new();
+        match first {
+            '"' => {
+                chars.next();
+                loop {
+                    match chars.next()? {
+                        '"' => break,
+                        '\\' => {
+                            let escaped = chars.next()?;
+                            match escaped {
+                                'n' => arg.push('\n'),
+                                'r' => arg.push('\r'),
+                                't' => arg.push('\t'),
+                                'b' => arg.push('\u{8}'),
+                                'a' => arg.push('\u{7}'),
+                                'x' => {
+                                    let hex: String = chars.clone().take(2).collect();
+                                    match u8::from_str_radix(&hex, 16) {
+                                        Ok(byte) if hex.len() == 2 => {
+                                            arg.push(byte as char);
+                                            chars.next();
+                                            chars.next();
+                                        }
+                                        _ => arg.push('x'),
+                                    }
+                                }
+                                other => arg.push(other),
+                            }
+                        }
+                        c => arg.push(c),
+                    }
+                }
+                // a closing quote must be followed by whitespace or the end of line
+                if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                    return None;
+                }
+            }
+            '\'' => {
+                chars.next();
+                loop {
+                    match chars.next()? {
+                        '\'' => break,
+                        '\\' if chars.peek() == Some(&'\'') => {
+                            chars.next();
+                            arg.push('\'');
+                        }
+                        c => arg.push(c),
+                    }
+                }
+                if chars.peek().is_some_and(|c| !c.is_whitespace()) {
+                    return None;
+                }
+            }
+            _ => {
+                while let Some(&c) = chars.peek() {
+                    if c.is_whitespace() {
+                        break;
+                    }
+                    arg.push(c);
+                    chars.next();
+                }
+            }
+        }
+        args.push(arg);
+    }
+}
+
+/// Quotes `arg` if it can't be written to a config file verbatim.
+fn quote_arg(arg: &str) -> String {
+    let needs_quotes = arg.is_empty()
+        || arg
+            .chars()
+            .any(|c| c.is_whitespace() || c == '"' || c == '\'' || c == '\\' || c.is_control());
+    if !needs_quotes {
+        return arg.to_string();
+    }
+
+    let mut quoted = String::from("\"");
+    for c in arg.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\x{:02x}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+fn config_error(path: &Path, line_number: usize, line: &str, reason: &str) -> SerirError {
+    SerirError::ConfigError(format!(
+        "{}:{}: '{}': {}",
+        path.display(),
+        line_number,
+        line.trim(),
+        reason
+    ))
+}
+
+impl Config {
+    /// The parsed `bind` addresses, which were validated when set.
+    pub fn bind_addrs(&self) -> Vec<BindAddr> {
+        self.bind
+            .iter()
+            .filter_map(|addr| BindAddr::parse(addr).ok())
+            .collect()
+    }
+
+    pub fn buffer_limits(&self) -> BufferLimits {
+        BufferLimits {
+            query_buffer: self.client_query_buffer_limit as usize,
+            max_bulk_len: self.proto_max_bulk_len as usize,
+            output: self.client_output_buffer_limit,
+        }
+    }
+
+    pub fn eviction_settings(&self) -> EvictionSettings {
+        EvictionSettings {
+            policy: self.maxmemory_policy,
+            samples: self.maxmemory_samples,
+            lfu_log_factor: self.lfu_log_factor,
+            lfu_decay_time: self.lfu_decay_time,
+        }
+    }
+
+    /// Loads the configuration from `path`, starting from the defaults.
+    pub fn from_file(path: impl AsRef<Path>) -> SerirResult<Self> {
+        let path = path.as_ref();
+        let mut config = Config {
+            config_file: Some(path.to_path_buf()),
+            ..Config::default()
+        };
+        let mut loader = Loader::default();
+        loader.load_file(&mut config, path)?;
+        Ok(config)
+    }
+
+    /// Sets the parameter `name` the way a config file line `name args...` would.
+    pub fn apply(&mut self, name: &str, args: &[String]) -> Result<(), String> {
+        let param = find_param(name).ok_or_else(|| format!("unknown parameter '{}'", name))?;
+        (param.set)(self, args)
+    }
+
+    /// Returns the parameters matching any of the glob `patterns` as name-value pairs, in
+    /// the order of the parameter table.
+    pub fn get(&self, patterns: &[String]) -> Vec<(&'static str, String)> {
+        PARAMS
+            .iter()
+            .filter(|param| {
+                patterns
+                    .iter()
+                    .any(|pattern| glob_match_nocase(pattern.as_bytes(), param.name.as_bytes()))
+            })
+            .map(|param| (param.name, (param.get)(self)))
+            .collect()
+    }
+
+    /// Sets the runtime-tunable parameters in `pairs`. Either all of them are applied or,
+    /// if any of them is unknown, immutable or invalid, none are.
+    pub fn set(&mut self, pairs: &[(String, String)]) -> Result<(), String> {
+        let mut updated = self.clone();
+        let mut seen = HashSet::new();
+        for (name, value) in pairs {
+            let fail = |reason: &str| {
+                format!(
+                    "CONFIG SET failed (possibly related to argument '{}') - {}",
+                    name, reason
+                )
+            };
+            let param = find_param(name)
+                .ok_or_else(|| fail("Unknown option or number of arguments for CONFIG SET"))?;
+            if !seen.insert(param.name) {
+                return Err(fail("duplicate parameter"));
+            }
+            if !param.mutable {
+                return Err(fail("can't set immutable config"));
+            }
+            let args = if param.multi_arg {
+                split_args(value).ok_or_else(|| fail("unbalanced quotes"))?
+            } else {
+                vec![value.clone()]
+            };
+            (param.set)(&mut updated, &args).map_err(|reason| fail(&reason))?;
+        }
+        *self = updated;
+        Ok(())
+    }
+
+    /// Lines describing parameter `param` in a config file.
+    fn param_lines(&self, param: &Param) -> Vec<String> {
+        if param.name == "save" {
+            if self.save.is_empty() {
+                return vec!["save \"\"".to_string()];
+            }
+            return self
+                .save
+                .iter()
+                .map(|(seconds, changes)| format!("save {} {}", seconds, changes))
+                .collect();
+        }
+        let value = (param.get)(self);
+        let value = if param.multi_arg {
+            split_args(&value)
+                .unwrap_or_default()
+                .iter()
+                .map(|arg| quote_arg(arg))
+                .collect::<Vec<_>>()
+                .join(" ")
+        } else {
+            quote_arg(&value)
+        };
+        vec![format!("{} {}", param.name, value)]
+    }
+
+    /// Rewrites the config file the configuration was loaded from so that it reflects the
+    /// current configuration. Comments, unknown directives and `include`s are preserved,
+    /// known parameters are updated in place and the ones that differ from the defaults but
+    /// weren't in the file are appended at the end.
+    pub fn rewrite(&self) -> SerirResult<()> {
+        let path = self.config_file.as_ref().ok_or_else(|| {
+            SerirError::ConfigError("The server is running without a config file".to_string())
+        })?;
+        let original = match fs::read_to_string(path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+
+        let mut lines = vec![];
+        let mut rewritten = HashSet::new();
+        for line in original.lines() {
+            let param = split_args(line)
+                .and_then(|args| args.first().cloned())
+                .filter(|_| !line.trim_start().starts_with('#'))
+                .and_then(|directive| find_param(&directive));
+            match param {
+                Some(param) => {
+                    if rewritten.insert(param.name) {
+                        lines.extend(self.param_lines(param));
+                    }
+                }
+                None => lines.push(line.to_string()),
+            }
+        }
+
+        let defaults = Config::default();
+        let mut generated = PARAMS
+            .iter()
+            .filter(|param| !rewritten.contains(param.name))
+            .filter(|param| (param.get)(self) != (param.get)(&defaults))
+            .flat_map(|param| self.param_lines(param))
+            .peekable();
+        if generated.peek().is_some() {
+            lines.push("# Generated by CONFIG REWRITE".to_string());
+            lines.extend(generated);
+        }
+
+        let mut contents = lines.join("\n");
+        contents.push('\n');
+        let tmp_path = path.with_extension("rewrite.tmp");
+        fs::write(&tmp_path, contents)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+}
+
+/// State kept while loading a config file and the files it includes.
+#[derive(Default)]
+struct Loader {
+    /// `save` lines accumulate, but the first one replaces the default save points.
+    save_seen: bool,
+    /// Files currently being loaded, to detect include cycles.
+    loading: Vec<PathBuf>,
+}
+
+impl Loader {
+    fn load_file(&mut self, config: &mut Config, path: &Path) -> SerirResult<()> {
+        let canonical = fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+        if self.loading.contains(&canonical) {
+            return Err(SerirError::ConfigError(format!(
+                "{}: include cycle",
+                path.display()
+            )));
+        }
+        let contents = fs::read_to_string(path)
+            .map_err(|e| SerirError::ConfigError(format!("{}: {}", path.display(), e)))?;
+
+        self.loading.push(canonical);
+        let result = self.load_str(config, path, &contents);
+        self.loading.pop();
+        result
+    }
+
+    fn load_str(&mut self, config: &mut Config, path: &Path, contents: &str) -> SerirResult<()> {
+        for (index, line) in contents.lines().enumerate() {
+            let line_number = index + 1;
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            let args = split_args(line)
+                .ok_or_else(|| config_error(path, line_number, line, "unbalanced quotes"))?;
+            let Some((directive, args)) = args.split_first() else {
+                continue;
+            };
+            let directive = directive.to_lowercase();
+
+            match directive.as_str() {
+                "include" => {
+                    let [included] = args else {
+                        return Err(config_error(
+                            path,
+                            line_number,
+                            line,
+                            "wrong number of arguments",
+                        ));
+                    };
+                    let included = path
+                        .parent()
+                        .map(|dir| dir.join(included))
+                        .unwrap_or_else(|| PathBuf::from(included));
+                    self.load_file(config, &included)?;
+                }
+                "loadmodule" => {
+                    let Some((name, arguments)) = args.split_first() else {
+                        return Err(config_error(
+                            path,
+                            line_number,
+                            line,
+                            "wrong number of arguments",
+                        ));
+                    };
+                    config.loadmodule.push((name.clone(), arguments.to_vec()));
+                }
+                "save" => {
+                    let points = parse_save(args)
+                        .map_err(|reason| config_error(path, line_number, line, &reason))?;
+                    if !self.save_seen {
+                        config.save.clear();
+                        self.save_seen = true;
+                    }
+                    config.save.extend(points);
+                }
+                _ => match find_param(&directive) {
+                    Some(param) => (param.set)(config, args)
+                        .map_err(|reason| config_error(path, line_number, line, &reason))?,
+                    None => eprintln!(
+                        "{}:{}: ignoring unsupported directive '{}'",
+                        path.display(),
+                        line_number,
+                        directive
+                    ),
+                },
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(contents: &str) -> SerirResult<Config> {
+        let mut config = Config::default();
+        Loader::default().load_str(&mut config, Path::new("test.conf"), contents)?;
+        Ok(config)
+    }
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("serir-{}-{}.conf", name, std::process::id()))
+    }
+
+    #[test]
+    fn sets_client_buffer_limits() {
+        let mut config = Config::default();
+        assert_eq!(
+            config.get(&["client-output-buffer-limit".to_string()])[0].1,
+            "normal 0 0 0 replica 268435456 67108864 60 pubsub 33554432 8388608 60"
+        );
+
+        config
+            .set(&[(
+                "client-output-buffer-limit".to_string(),
+                "normal 4mb 1mb 10 slave 1 2 3".to_string(),
+            )])
+            .unwrap();
+        assert_eq!(
+            config.client_output_buffer_limit.normal,
+            OutputBufferLimit {
+                hard: 4 * 1024 * 1024,
+                soft: 1024 * 1024,
+                soft_seconds: 10
+            }
+        );
+        assert_eq!(config.client_output_buffer_limit.replica.soft_seconds, 3);
+        assert_eq!(
+            config.client_output_buffer_limit.pubsub.hard,
+            32 * 1024 * 1024
+        );
+
+        let previous = config.clone();
+        for value in ["normal 1 2", "normal 1 2 3 unknown 1 2 3", "pubsub 1 2 x"] {
+            assert!(config
+                .set(&[("client-output-buffer-limit".to_string(), value.to_string())])
+                .is_err());
+        }
+        assert_eq!(config, previous);
+
+        config
+            .set(&[("proto-max-bulk-len".to_string(), "2mb".to_string())])
+            .unwrap();
+        assert_eq!(config.buffer_limits().max_bulk_len, 2 * 1024 * 1024);
+        assert!(config
+            .set(&[("client-query-buffer-limit".to_string(), "1kb".to_string())])
+            .is_err());
+    }
+
+    #[test]
+    fn parses_bind_addresses() {
+        assert_eq!(
+            Config::default().bind_addrs(),
+            vec![
+                BindAddr {
+                    ip: IpAddr::V4(Ipv4Addr::UNSPECIFIED),
+                    optional: false
+                },
+                BindAddr {
+                    ip: IpAddr::V6(Ipv6Addr::UNSPECIFIED),
+                    optional: true
+                },
+            ]
+        );
+        assert_eq!(
+            BindAddr::parse("-::1").unwrap().ip,
+            IpAddr::V6(Ipv6Addr::LOCALHOST)
+        );
+        assert!(load("bind 127.0.0.1 localhost").is_err());
+    }
+
+    #[test]
+    fn splits_quoted_arguments() {
+        assert_eq!(
+            split_args(r#"requirepass "a b\"c\x41" 'd e' f"#).unwrap(),
+            vec!["requirepass", "a b\"cA", "d e", "f"]
+        );
+        assert_eq!(split_args(r#"save """#).unwrap(), vec!["save", ""]);
+        assert!(split_args(r#"requirepass "abc"#).is_none());
+        assert!(split_args(r#"requirepass "abc"def"#).is_none());
+    }
+
+    #[test]
+    fn parses_memory_units() {
+        assert_eq!(parse_memory("100").unwrap(), 100);
+        assert_eq!(parse_memory("1k").unwrap(), 1000);
+        assert_eq!(parse_memory("1kb").unwrap(), 1024);
+        assert_eq!(parse_memory("2mb").unwrap(), 2 * 1024 * 1024);
+        assert_eq!(parse_memory("1GB").unwrap(), 1024 * 1024 * 1024);
+        assert_eq!(parse_memory("1g").unwrap(), 1_000_000_000);
+        assert!(parse_memory("1tb").is_err());
+        assert!(parse_memory("mb").is_err());
+    }
+
+    #[test]
+    fn loads_redis_conf_syntax() {
+        let config = load(
+            "# a comment\n\
+             port 7000\n\
+             bind 127.0.0.1 ::1\n\
+             maxmemory 1gb\n\
+             \n\
+             save 900 1\n\
+             save 300 10\n\
+             appendonly yes\n\
+             requirepass \"secret password\"\n\
+             loadmodule counters 10 fast\n\
+             daemonize no\n",
+        )
+        .unwrap();
+
+        assert_eq!(config.port, 7000);
+        assert_eq!(config.bind, vec!["127.0.0.1", "::1"]);
+        assert_eq!(config.maxmemory, 1024 * 1024 * 1024);
+        assert_eq!(config.save, vec![(900, 1), (300, 10)]);
+        assert!(config.appendonly);
+        assert_eq!(config.requirepass.as_deref(), Some("secret password"));
+        assert_eq!(
+            config.loadmodule,
+            vec![(
+                "counters".to_string(),
+                vec!["10".to_string(), "fast".to_string()]
+            )]
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_values() {
+        assert!(load("port notaport\n").is_err());
+        assert!(load("appendonly maybe\n").is_err());
+        assert!(load("save 900\n").is_err());
+        assert!(load("loadmodule\n").is_err());
+    }
+
+    #[test]
+    fn loads_included_files() {
+        let included = temp_path("included");
+        fs::write(&included, "maxmemory 10mb\n").unwrap();
+        let main = temp_path("main");
+        fs::write(
+            &main,
+            format!(
+                "port 7001\ninclude {}\n",
+                included.file_name().unwrap().to_str().unwrap()
+            ),
+        )
+        .unwrap();
+
+        let config = Config::from_file(&main).unwrap();
+        assert_eq!(config.port, 7001);
+        assert_eq!(config.maxmemory, 10 * 1024 * 1024);
+
+        fs::remove_file(included).unwrap();
+        fs::remove_file(main).unwrap();
+    }
+
+    #[test]
+    fn gets_parameters_matching_glob_patterns() {
+        let config = Config::default();
+        let names: Vec<_> = config
+            .get(&["*o*".to_string()])
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(
+            names,
+            vec![
+                "port",
+                "metrics-port",
+                "tls-port",
+                "protected-mode",
+                "unixsocket",
+                "unixsocketperm",
+                "tcp-backlog",
+                "timeout",
+                "proto-max-bulk-len",
+                "client-output-buffer-limit",
+                "io-threads",
+                "maxmemory",
+                "maxmemory-policy",
+                "maxmemory-samples",
+                "lfu-log-factor",
+                "slowlog-log-slower-than",
+                "slowlog-max-len",
+                "latency-monitor-threshold",
+                "appendonly",
+                "shutdown-timeout",
+                "acllog-max-len"
+            ]
+        );
+
+        assert_eq!(
+            config.get(&["SAVE".to_string()]),
+            vec![("save", "3600 1 300 100 60 10000".to_string())]
+        );
+    }
+
+    #[test]
+    fn sets_mutable_parameters_atomically() {
+        let mut config = Config::default();
+        config
+            .set(&[
+                ("maxmemory".to_string(), "2mb".to_string()),
+                ("save".to_string(), "60 1".to_string()),
+            ])
+            .unwrap();
+        assert_eq!(config.maxmemory, 2 * 1024 * 1024);
+        assert_eq!(config.save, vec![(60, 1)]);
+
+        let before = config.clone();
+        assert!(config
+            .set(&[
+                ("maxmemory".to_string(), "1mb".to_string()),
+                ("port".to_string(), "7000".to_string()),
+            ])
+            .is_err());
+        assert_eq!(config, before);
+    }
+
+    #[test]
+    fn rewrites_config_file_in_place() {
+        let path = temp_path("rewrite");
+        fs::write(
+            &path,
+            "# keep me\nport 7002\nmaxmemory 1mb\ndaemonize no\nmaxmemory 5mb\n",
+        )
+        .unwrap();
+
+        let mut config = Config::from_file(&path).unwrap();
+        config
+            .set(&[
+                ("maxmemory".to_string(), "3mb".to_string()),
+                ("requirepass".to_string(), "p w".to_string()),
+            ])
+            .unwrap();
+        config.rewrite().unwrap();
+
+        let rewritten = fs::read_to_string(&path).unwrap();
+        assert_eq!(
+            rewritten,
+            "# keep me\nport 7002\nmaxmemory 3145728\ndaemonize no\n\
+             # Generated by CONFIG REWRITE\nrequirepass \"p w\"\n"
+        );
+        assert_eq!(Config::from_file(&path).unwrap(), config);
+
+        fs::remove_file(path).unwrap();
+    }
+}
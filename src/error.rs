@@ -1,9 +1,5 @@
 use std::{error::Error, fmt::Display, num::ParseIntError};
 use tokio::io;
-use tokio::sync::mpsc::error::SendError;
-use tokio::sync::oneshot::error::RecvError;
-
-use crate::server::Request;
 
 pub type SerirResult<T> = Result<T, SerirError>;
 
@@ -12,8 +8,9 @@ pub enum SerirError {
     IoError(io::Error),
     ParseError(ParseIntError),
     RespParseError(String),
-    MpscSendError(SendError<Request>),
-    OneshotRecvError(RecvError),
+    ConfigError(String),
+    /// A command couldn't be parsed. Holds the error reply for the client.
+    CommandError(String),
 }
 
 impl Display for SerirError {
@@ -22,8 +19,8 @@ impl Display for SerirError {
             SerirError::IoError(e) => write!(f, "IO Error: {}", e),
             SerirError::ParseError(e) => write!(f, "Parsing error: {}", e),
             SerirError::RespParseError(msg) => write!(f, "Resp parsing error: {}", msg),
-            SerirError::MpscSendError(e) => write!(f, "Mpsc send error: {}", e),
-            SerirError::OneshotRecvError(e) => write!(f, "Oneshot recv error: {}", e),
+            SerirError::ConfigError(msg) => write!(f, "Config error: {}", msg),
+            SerirError::CommandError(msg) => write!(f, "Command error: {}", msg),
         }
     }
 }
@@ -33,9 +30,9 @@ impl Error for SerirError {
         match self {
             SerirError::IoError(e) => Some(e),
             SerirError::ParseError(e) => Some(e),
-            SerirError::MpscSendError(e) => Some(e),
-            SerirError::OneshotRecvError(e) => Some(e),
             SerirError::RespParseError(_) => None,
+            SerirError::ConfigError(_) => None,
+            SerirError::CommandError(_) => None,
         }
     }
 }
@@ -51,15 +48,3 @@ impl From<ParseIntError> for SerirError {
         SerirError::ParseError(err)
     }
 }
-
-impl From<SendError<Request>> for SerirError {
-    fn from(err: SendError<Request>) -> Self {
-        SerirError::MpscSendError(err)
-    }
-}
-
-impl From<RecvError> for SerirError {
-    fn from(err: RecvError) -> Self {
-        SerirError::OneshotRecvError(err)
-    }
-}
@@ -0,0 +1,168 @@
+use std::fmt::Display;
+use std::str::FromStr;
+
+use rand::prelude::*;
+
+/// What to do when a write would push memory usage over `maxmemory`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EvictionPolicy {
+    NoEviction,
+    AllKeysLru,
+    VolatileLru,
+    AllKeysLfu,
+    VolatileLfu,
+    AllKeysRandom,
+    VolatileRandom,
+    VolatileTtl,
+}
+
+impl EvictionPolicy {
+    pub const ALL: [EvictionPolicy; 8] = [
+        EvictionPolicy::NoEviction,
+        EvictionPolicy::AllKeysLru,
+        EvictionPolicy::VolatileLru,
+        EvictionPolicy::AllKeysLfu,
+        EvictionPolicy::VolatileLfu,
+        EvictionPolicy::AllKeysRandom,
+        EvictionPolicy::VolatileRandom,
+        EvictionPolicy::VolatileTtl,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EvictionPolicy::NoEviction => "noeviction",
+            EvictionPolicy::AllKeysLru => "allkeys-lru",
+            EvictionPolicy::VolatileLru => "volatile-lru",
+            EvictionPolicy::AllKeysLfu => "allkeys-lfu",
+            EvictionPolicy::VolatileLfu => "volatile-lfu",
+            EvictionPolicy::AllKeysRandom => "allkeys-random",
+            EvictionPolicy::VolatileRandom => "volatile-random",
+            EvictionPolicy::VolatileTtl => "volatile-ttl",
+        }
+    }
+
+    /// Whether only keys with an expire set are candidates for eviction.
+    pub fn is_volatile(&self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::VolatileLru
+                | EvictionPolicy::VolatileLfu
+                | EvictionPolicy::VolatileRandom
+                | EvictionPolicy::VolatileTtl
+        )
+    }
+
+    pub fn is_lfu(&self) -> bool {
+        matches!(
+            self,
+            EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu
+        )
+    }
+}
+
+impl Display for EvictionPolicy {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for EvictionPolicy {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        EvictionPolicy::ALL
+            .into_iter()
+            .find(|policy| policy.as_str().eq_ignore_ascii_case(s))
+            .ok_or_else(|| format!("unknown eviction policy '{}'", s))
+    }
+}
+
+/// The part of the configuration the store needs to track access metadata and pick
+/// eviction candidates.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct EvictionSettings {
+    pub policy: EvictionPolicy,
+    /// Number of keys sampled per shard when looking for the best key to evict.
+    pub samples: usize,
+    pub lfu_log_factor: u8,
+    /// Minutes of inactivity after which the LFU counter of a key is decremented.
+    pub lfu_decay_time: u64,
+}
+
+impl Default for EvictionSettings {
+    fn default() -> Self {
+        Self {
+            policy: EvictionPolicy::NoEviction,
+            samples: 5,
+            lfu_log_factor: 10,
+            lfu_decay_time: 1,
+        }
+    }
+}
+
+/// Initial LFU counter of new keys, so that they have a chance to accumulate accesses
+/// before being evicted.
+pub const LFU_INIT_VAL: u8 = 5;
+
+/// Logarithmically increments an LFU counter: the higher the counter, the less likely an
+/// access is to increment it. With the default factor of 10 the counter saturates after
+/// about a million accesses.
+pub fn lfu_log_incr(counter: u8, lfu_log_factor: u8) -> u8 {
+    if counter == u8::MAX {
+        return counter;
+    }
+    let base = counter.saturating_sub(LFU_INIT_VAL) as f64;
+    let p = 1.0 / (base * lfu_log_factor as f64 + 1.0);
+    if thread_rng().gen::<f64>() < p {
+        counter + 1
+    } else {
+        counter
+    }
+}
+
+/// Decrements an LFU counter by one for every `lfu_decay_time` minutes elapsed since it
+/// was last decremented at `last_decr_minutes`.
+pub fn lfu_decr(counter: u8, last_decr_minutes: u64, now_minutes: u64, lfu_decay_time: u64) -> u8 {
+    if lfu_decay_time == 0 {
+        return counter;
+    }
+    let periods = now_minutes.saturating_sub(last_decr_minutes) / lfu_decay_time;
+    counter.saturating_sub(periods.min(u8::MAX as u64) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_policies_by_name() {
+        for policy in EvictionPolicy::ALL {
+            assert_eq!(policy.as_str().parse::<EvictionPolicy>().unwrap(), policy);
+        }
+        assert_eq!(
+            "ALLKEYS-LRU".parse::<EvictionPolicy>().unwrap(),
+            EvictionPolicy::AllKeysLru
+        );
+        assert!("allkeys-fifo".parse::<EvictionPolicy>().is_err());
+    }
+
+    #[test]
+    fn increments_lfu_counter_logarithmically() {
+        let mut counter = LFU_INIT_VAL;
+        for _ in 0..1000 {
+            counter = lfu_log_incr(counter, 10);
+        }
+        assert!(counter > LFU_INIT_VAL);
+        assert!(counter < 50);
+
+        assert_eq!(lfu_log_incr(u8::MAX, 10), u8::MAX);
+    }
+
+    #[test]
+    fn decays_lfu_counter_over_time() {
+        assert_eq!(lfu_decr(10, 0, 3, 1), 7);
+        assert_eq!(lfu_decr(10, 0, 3, 2), 9);
+        assert_eq!(lfu_decr(10, 0, 100, 1), 0);
+        assert_eq!(lfu_decr(10, 0, 100, 0), 10);
+    }
+}
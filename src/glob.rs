@@ -0,0 +1,148 @@
+/// Matches `string` against a glob-style `pattern`, the way Redis does for `KEYS`,
+/// `CONFIG GET` and friends.
+///
+/// Supports `*`, `?`, character classes (`[abc]`, `[^abc]`, `[a-z]`) and `\` to escape
+/// the next character.
+pub fn glob_match(pattern: &[u8], string: &[u8]) -> bool {
+    glob_match_impl(pattern, string, false)
+}
+
+/// Case-insensitive variant of [`glob_match`].
+pub fn glob_match_nocase(pattern: &[u8], string: &[u8]) -> bool {
+    glob_match_impl(pattern, string, true)
+}
+
+fn glob_match_impl(mut pattern: &[u8], mut string: &[u8], nocase: bool) -> bool {
+    let eq = |a: u8, b: u8| {
+        if nocase {
+            a.eq_ignore_ascii_case(&b)
+        } else {
+            a == b
+        }
+    };
+
+    while let Some(&p) = pattern.first() {
+        match p {
+            b'*' => {
+                while pattern.len() > 1 && pattern[1] == b'*' {
+                    pattern = &pattern[1..];
+                }
+                if pattern.len() == 1 {
+                    return true;
+                }
+                return (0..=string.len())
+                    .any(|start| glob_match_impl(&pattern[1..], &string[start..], nocase));
+            }
+            b'?' => {
+                if string.is_empty() {
+                    return false;
+                }
+                string = &string[1..];
+            }
+            b'[' => {
+                let Some(&c) = string.first() else {
+                    return false;
+                };
+                pattern = &pattern[1..];
+                let negate = pattern.first() == Some(&b'^');
+                if negate {
+                    pattern = &pattern[1..];
+                }
+                let mut matched = false;
+                while let Some(&p) = pattern.first() {
+                    if p == b']' {
+                        break;
+                    }
+                    if p == b'\\' && pattern.len() >= 2 {
+                        pattern = &pattern[1..];
+                        matched |= eq(pattern[0], c);
+                    } else if pattern.len() >= 3 && pattern[1] == b'-' {
+                        let (mut start, mut end) = (pattern[0], pattern[2]);
+                        if start > end {
+                            std::mem::swap(&mut start, &mut end);
+                        }
+                        let c = if nocase { c.to_ascii_lowercase() } else { c };
+                        let (start, end) = if nocase {
+                            (start.to_ascii_lowercase(), end.to_ascii_lowercase())
+                        } else {
+                            (start, end)
+                        };
+                        matched |= start <= c && c <= end;
+                        pattern = &pattern[2..];
+                    } else {
+                        matched |= eq(p, c);
+                    }
+                    pattern = &pattern[1..];
+                }
+                if matched == negate {
+                    return false;
+                }
+                string = &string[1..];
+                if pattern.is_empty() {
+                    // unterminated class, treat the end of pattern as its end
+                    return string.is_empty();
+                }
+            }
+            b'\\' if pattern.len() >= 2 => {
+                pattern = &pattern[1..];
+                if string.first().is_none_or(|&c| !eq(pattern[0], c)) {
+                    return false;
+                }
+                string = &string[1..];
+            }
+            _ => {
+                if string.first().is_none_or(|&c| !eq(p, c)) {
+                    return false;
+                }
+                string = &string[1..];
+            }
+        }
+        pattern = &pattern[1..];
+    }
+    string.is_empty()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_literals() {
+        assert!(glob_match(b"maxmemory", b"maxmemory"));
+        assert!(!glob_match(b"maxmemory", b"maxmemory-policy"));
+        assert!(!glob_match(b"maxmemory-policy", b"maxmemory"));
+    }
+
+    #[test]
+    fn matches_wildcards() {
+        assert!(glob_match(b"*", b""));
+        assert!(glob_match(b"max*", b"maxmemory"));
+        assert!(glob_match(b"*memory*", b"maxmemory-policy"));
+        assert!(glob_match(b"p?rt", b"port"));
+        assert!(!glob_match(b"p?rt", b"prt"));
+        assert!(glob_match(b"a*b*c", b"aXXbYYc"));
+        assert!(!glob_match(b"a*b*c", b"aXXbYY"));
+    }
+
+    #[test]
+    fn matches_character_classes() {
+        assert!(glob_match(b"h[ae]llo", b"hello"));
+        assert!(!glob_match(b"h[ae]llo", b"hillo"));
+        assert!(glob_match(b"h[^e]llo", b"hallo"));
+        assert!(!glob_match(b"h[^e]llo", b"hello"));
+        assert!(glob_match(b"h[a-c]llo", b"hbllo"));
+        assert!(!glob_match(b"h[a-c]llo", b"hdllo"));
+    }
+
+    #[test]
+    fn matches_escaped_characters() {
+        assert!(glob_match(b"a\\*b", b"a*b"));
+        assert!(!glob_match(b"a\\*b", b"aXb"));
+    }
+
+    #[test]
+    fn matches_ignoring_case() {
+        assert!(glob_match_nocase(b"MAX*", b"maxmemory"));
+        assert!(!glob_match(b"MAX*", b"maxmemory"));
+    }
+}
@@ -0,0 +1,366 @@
+use std::fmt::{Display, Write};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::state::ServerState;
+
+/// Sections reported by a plain `INFO` or `INFO default`.
+const DEFAULT_SECTIONS: &[&str] = &[
+    "server",
+    "clients",
+    "memory",
+    "persistence",
+    "stats",
+    "replication",
+    "modules",
+    "keyspace",
+];
+/// Sections reported by `INFO all` and `INFO everything`.
+const ALL_SECTIONS: &[&str] = &[
+    "server",
+    "clients",
+    "memory",
+    "persistence",
+    "stats",
+    "replication",
+    "modules",
+    "commandstats",
+    "keyspace",
+];
+
+/// Formats a number of bytes the way Redis does in `*_human` fields, e.g. `1.50M`.
+fn bytes_to_human(bytes: u64) -> String {
+    const UNITS: &[(u64, &str)] = &[
+        (1 << 50, "P"),
+        (1 << 40, "T"),
+        (1 << 30, "G"),
+        (1 << 20, "M"),
+        (1 << 10, "K"),
+    ];
+    UNITS
+        .iter()
+        .find(|(size, _)| bytes >= *size)
+        .map(|(size, unit)| format!("{:.2}{}", bytes as f64 / *size as f64, unit))
+        .unwrap_or_else(|| format!("{}B", bytes))
+}
+
+fn field(info: &mut String, name: &str, value: impl Display) {
+    write!(info, "{}:{}\r\n", name, value).unwrap();
+}
+
+/// Builds the `INFO` reply for the requested `sections`.
+pub fn info(state: &ServerState, sections: &[String]) -> String {
+    let sections: Vec<String> = sections.iter().map(|s| s.to_lowercase()).collect();
+    let selected: Vec<&str> = match sections.first().map(String::as_str) {
+        None | Some("default") => DEFAULT_SECTIONS.to_vec(),
+        Some("all") | Some("everything") => ALL_SECTIONS.to_vec(),
+        _ => ALL_SECTIONS
+            .iter()
+            .copied()
+            .filter(|section| sections.iter().any(|s| s == section))
+            .collect(),
+    };
+
+    let mut info = String::new();
+    for section in selected {
+        if !info.is_empty() {
+            info.push_str("\r\n");
+        }
+        match section {
+            "server" => server(state, &mut info),
+            "clients" => clients(state, &mut info),
+            "memory" => memory(state, &mut info),
+            "persistence" => persistence(state, &mut info),
+            "stats" => stats(state, &mut info),
+            "replication" => replication(state, &mut info),
+            "modules" => modules(state, &mut info),
+            "commandstats" => commandstats(state, &mut info),
+            "keyspace" => keyspace(state, &mut info),
+            _ => unreachable!("unknown INFO section {}", section),
+        }
+    }
+    info
+}
+
+fn server(state: &ServerState, info: &mut String) {
+    let config = state.config.read().unwrap();
+    let uptime = state.stats.uptime().as_secs();
+    let now_usec = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_micros())
+        .unwrap_or_default();
+    let executable = std::env::current_exe()
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+    let config_file = config
+        .config_file
+        .as_ref()
+        .and_then(|path| std::fs::canonicalize(path).ok())
+        .map(|path| path.display().to_string())
+        .unwrap_or_default();
+
+    info.push_str("# Server\r\n");
+    // clients decide which commands they can use based on the Redis version
+    field(info, "redis_version", "7.0.0");
+    field(info, "serir_version", env!("CARGO_PKG_VERSION"));
+    field(info, "redis_mode", "standalone");
+    field(
+        info,
+        "os",
+        format!("{} {}", std::env::consts::OS, std::env::consts::ARCH),
+    );
+    field(info, "arch_bits", usize::BITS);
+    field(info, "multiplexing_api", "tokio");
+    field(info, "process_id", std::process::id());
+    field(info, "run_id", &state.run_id);
+    field(info, "tcp_port", config.port);
+    field(info, "server_time_usec", now_usec);
+    field(info, "uptime_in_seconds", uptime);
+    field(info, "uptime_in_days", uptime / 86400);
+    field(info, "hz", "10");
+    field(info, "executable", executable);
+    field(info, "config_file", config_file);
+    field(info, "io_threads_active", config.io_threads);
+}
+
+fn clients(state: &ServerState, info: &mut String) {
+    info.push_str("# Clients\r\n");
+    field(info, "connected_clients", state.stats.connected_clients());
+    field(info, "maxclients", state.clients.maxclients());
+    field(info, "blocked_clients", "0");
+    field(info, "tracking_clients", state.tracking.num_clients());
+}
+
+fn memory(state: &ServerState, info: &mut String) {
+    let config = state.config.read().unwrap();
+    let used_memory = state.store.used_memory() as u64;
+    state.stats.memory_used(used_memory as usize);
+    let peak = state.stats.used_memory_peak() as u64;
+
+    info.push_str("# Memory\r\n");
+    field(info, "used_memory", used_memory);
+    field(info, "used_memory_human", bytes_to_human(used_memory));
+    field(info, "used_memory_peak", peak);
+    field(info, "used_memory_peak_human", bytes_to_human(peak));
+    field(info, "maxmemory", config.maxmemory);
+    field(info, "maxmemory_human", bytes_to_human(config.maxmemory));
+    field(info, "maxmemory_policy", config.maxmemory_policy);
+}
+
+fn persistence(state: &ServerState, info: &mut String) {
+    let config = state.config.read().unwrap();
+    info.push_str("# Persistence\r\n");
+    field(info, "loading", "0");
+    field(info, "aof_enabled", config.appendonly as u8);
+}
+
+fn stats(state: &ServerState, info: &mut String) {
+    let stats = &state.stats;
+    let (expired_keys, evicted_keys, hits, misses) =
+        state
+            .store
+            .fold((0, 0, 0, 0), |(expired, evicted, hits, misses), shard| {
+                (
+                    expired + shard.expired_keys(),
+                    evicted + shard.evicted_keys(),
+                    hits + shard.keyspace_hits(),
+                    misses + shard.keyspace_misses(),
+                )
+            });
+
+    info.push_str("# Stats\r\n");
+    field(
+        info,
+        "total_connections_received",
+        stats.total_connections_received(),
+    );
+    field(
+        info,
+        "total_commands_processed",
+        stats.total_commands_processed(),
+    );
+    field(
+        info,
+        "instantaneous_ops_per_sec",
+        stats.instantaneous_ops_per_sec(),
+    );
+    field(info, "total_net_input_bytes", stats.total_net_input_bytes());
+    field(
+        info,
+        "total_net_output_bytes",
+        stats.total_net_output_bytes(),
+    );
+    field(
+        info,
+        "instantaneous_input_kbps",
+        format!("{:.2}", stats.instantaneous_input_kbps()),
+    );
+    field(
+        info,
+        "instantaneous_output_kbps",
+        format!("{:.2}", stats.instantaneous_output_kbps()),
+    );
+    field(info, "rejected_connections", stats.rejected_connections());
+    field(
+        info,
+        "client_query_buffer_limit_disconnections",
+        stats.client_query_buffer_limit_disconnections(),
+    );
+    field(
+        info,
+        "client_output_buffer_limit_disconnections",
+        stats.client_output_buffer_limit_disconnections(),
+    );
+    field(info, "expired_keys", expired_keys);
+    field(info, "evicted_keys", evicted_keys);
+    field(info, "keyspace_hits", hits);
+    field(info, "keyspace_misses", misses);
+    field(info, "tracking_total_keys", state.tracking.num_keys());
+    field(
+        info,
+        "tracking_total_prefixes",
+        state.tracking.num_prefixes(),
+    );
+}
+
+fn replication(state: &ServerState, info: &mut String) {
+    info.push_str("# Replication\r\n");
+    field(info, "role", "master");
+    field(info, "connected_slaves", "0");
+    field(info, "master_replid", &state.run_id);
+    field(info, "master_repl_offset", "0");
+}
+
+fn modules(state: &ServerState, info: &mut String) {
+    info.push_str("# Modules\r\n");
+    for (name, version, _) in state.modules.list() {
+        field(
+            info,
+            "module",
+            format!(
+                "name={},ver={},api=1,filters=0,usedby=[],using=[],options=[]",
+                name, version
+            ),
+        );
+    }
+}
+
+fn commandstats(state: &ServerState, info: &mut String) {
+    info.push_str("# Commandstats\r\n");
+    state.stats.for_each_command(|name, command| {
+        let calls = command.calls();
+        let usec = command.usec();
+        field(
+            info,
+            &format!("cmdstat_{}", name),
+            format!(
+                "calls={},usec={},usec_per_call={:.2},rejected_calls=0,failed_calls=0",
+                calls,
+                usec,
+                usec as f64 / calls.max(1) as f64
+            ),
+        );
+    });
+}
+
+fn keyspace(state: &ServerState, info: &mut String) {
+    let (keys, expires) = state.store.fold((0, 0), |(keys, expires), shard| {
+        (keys + shard.len(), expires + shard.volatile_len())
+    });
+
+    info.push_str("# Keyspace\r\n");
+    if keys > 0 {
+        field(
+            info,
+            "db0",
+            format!("keys={},expires={},avg_ttl=0", keys, expires),
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    use crate::commands::Command;
+    use crate::config::Config;
+
+    fn fields(info: &str) -> Vec<(&str, &str)> {
+        info.split("\r\n")
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .map(|line| line.split_once(':').unwrap())
+            .collect()
+    }
+
+    fn field<'a>(info: &'a str, name: &str) -> Option<&'a str> {
+        fields(info)
+            .into_iter()
+            .find(|(field, _)| *field == name)
+            .map(|(_, value)| value)
+    }
+
+    #[test]
+    fn formats_human_readable_bytes() {
+        assert_eq!(bytes_to_human(100), "100B");
+        assert_eq!(bytes_to_human(1536), "1.50K");
+        assert_eq!(bytes_to_human(3 * 1024 * 1024), "3.00M");
+    }
+
+    #[test]
+    fn reports_default_sections() {
+        let state = ServerState::new(Config::default());
+        let info = info(&state, &[]);
+        let sections: Vec<_> = info
+            .split("\r\n")
+            .filter(|line| line.starts_with('#'))
+            .collect();
+        assert_eq!(
+            sections,
+            vec![
+                "# Server",
+                "# Clients",
+                "# Memory",
+                "# Persistence",
+                "# Stats",
+                "# Replication",
+                "# Modules",
+                "# Keyspace"
+            ]
+        );
+        assert_eq!(field(&info, "role"), Some("master"));
+        assert_eq!(field(&info, "tcp_port"), Some("6379"));
+    }
+
+    #[test]
+    fn reports_keyspace_and_hits() {
+        let state = ServerState::new(Config::default());
+        state
+            .exec(Command::Set((
+                b"key".to_vec(),
+                Bytes::from_static(b"value"),
+            )))
+            .unwrap();
+        state.exec(Command::Expire((b"key".to_vec(), 100))).unwrap();
+        state.exec(Command::Get(b"key".to_vec())).unwrap();
+        state.exec(Command::Get(b"missing".to_vec())).unwrap();
+
+        let info = info(&state, &["stats".to_string(), "KEYSPACE".to_string()]);
+        assert_eq!(field(&info, "db0"), Some("keys=1,expires=1,avg_ttl=0"));
+        assert_eq!(field(&info, "keyspace_hits"), Some("1"));
+        assert_eq!(field(&info, "keyspace_misses"), Some("1"));
+        assert_eq!(field(&info, "total_commands_processed"), Some("4"));
+        assert_eq!(field(&info, "role"), None);
+    }
+
+    #[test]
+    fn reports_commandstats_only_when_asked() {
+        let state = ServerState::new(Config::default());
+        state.exec(Command::Get(b"key".to_vec())).unwrap();
+
+        assert!(!info(&state, &[]).contains("cmdstat_get"));
+        let info = info(&state, &["commandstats".to_string()]);
+        assert!(field(&info, "cmdstat_get")
+            .unwrap()
+            .starts_with("calls=1,usec="));
+    }
+}
@@ -0,0 +1,228 @@
+use std::collections::{BTreeMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Samples kept per event, at most one per second.
+const HISTORY_LEN: usize = 160;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LatencySample {
+    /// Unix time of the sample, in seconds.
+    pub time: u64,
+    /// Latency in milliseconds.
+    pub latency: u64,
+}
+
+#[derive(Debug, Default)]
+struct EventHistory {
+    samples: VecDeque<LatencySample>,
+    max: u64,
+}
+
+impl EventHistory {
+    fn add(&mut self, sample: LatencySample) {
+        self.max = self.max.max(sample.latency);
+        match self.samples.back_mut() {
+            Some(last) if last.time == sample.time => {
+                last.latency = last.latency.max(sample.latency)
+            }
+            _ => {
+                if self.samples.len() == HISTORY_LEN {
+                    self.samples.pop_front();
+                }
+                self.samples.push_back(sample);
+            }
+        }
+    }
+}
+
+/// Records events that took at least `latency-monitor-threshold` milliseconds, the way
+/// the Redis latency monitor does: commands, expire cycles and eviction cycles.
+#[derive(Debug, Default)]
+pub struct LatencyMonitor {
+    threshold: AtomicU64,
+    events: Mutex<BTreeMap<&'static str, EventHistory>>,
+}
+
+impl LatencyMonitor {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the threshold in milliseconds, 0 disables the monitor.
+    pub fn configure(&self, threshold: u64) {
+        self.threshold.store(threshold, Ordering::Relaxed);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.threshold.load(Ordering::Relaxed) > 0
+    }
+
+    /// Records that `event` took `duration`, if that is above the threshold.
+    pub fn record(&self, event: &'static str, duration: Duration) {
+        let threshold = self.threshold.load(Ordering::Relaxed);
+        let latency = duration.as_millis() as u64;
+        if threshold == 0 || latency < threshold {
+            return;
+        }
+        let time = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|now| now.as_secs())
+            .unwrap_or_default();
+        self.add_sample(event, LatencySample { time, latency });
+    }
+
+    fn add_sample(&self, event: &'static str, sample: LatencySample) {
+        self.events
+            .lock()
+            .unwrap()
+            .entry(event)
+            .or_default()
+            .add(sample);
+    }
+
+    /// Latest sample and all time maximum latency of every event.
+    pub fn latest(&self) -> Vec<(&'static str, LatencySample, u64)> {
+        let events = self.events.lock().unwrap();
+        events
+            .iter()
+            .filter_map(|(event, history)| {
+                let latest = *history.samples.back()?;
+                Some((*event, latest, history.max))
+            })
+            .collect()
+    }
+
+    pub fn history(&self, event: &str) -> Vec<LatencySample> {
+        let events = self.events.lock().unwrap();
+        events
+            .get(event)
+            .map(|history| history.samples.iter().copied().collect())
+            .unwrap_or_default()
+    }
+
+    /// Forgets the given events, or all of them if `events` is empty. Returns the number
+    /// of events that were reset.
+    pub fn reset(&self, events: &[String]) -> usize {
+        let mut all = self.events.lock().unwrap();
+        if events.is_empty() {
+            let count = all.len();
+            all.clear();
+            return count;
+        }
+        events
+            .iter()
+            .filter(|event| all.remove(event.to_lowercase().as_str()).is_some())
+            .count()
+    }
+
+    /// Human readable analysis of the recorded events, for `LATENCY DOCTOR`.
+    pub fn doctor(&self) -> String {
+        if !self.is_enabled() {
+            return "Latency monitoring is disabled. Use \
+                    \"CONFIG SET latency-monitor-threshold <milliseconds>\" to enable it.\n"
+                .to_string();
+        }
+        let events = self.events.lock().unwrap();
+        if events.is_empty() {
+            return "No latency spike was observed during the lifetime of this instance.\n"
+                .to_string();
+        }
+
+        let mut report = String::from("Latency spikes observed:\n\n");
+        for (i, (event, history)) in events.iter().enumerate() {
+            let samples = &history.samples;
+            let count = samples.len() as u64;
+            let sum: u64 = samples.iter().map(|sample| sample.latency).sum();
+            let average = sum / count.max(1);
+            let deviation = samples
+                .iter()
+                .map(|sample| sample.latency.abs_diff(average))
+                .sum::<u64>()
+                / count.max(1);
+            let period = match (samples.front(), samples.back()) {
+                (Some(first), Some(last)) if count > 1 => (last.time - first.time) / (count - 1),
+                _ => 0,
+            };
+            writeln!(
+                report,
+                "{}. {}: {} latency spikes (average {}ms, mean deviation {}ms, period {} sec). \
+                 Worst all time event {}ms.",
+                i + 1,
+                event,
+                count,
+                average,
+                deviation,
+                period,
+                history.max
+            )
+            .unwrap();
+        }
+        if events.contains_key("command") {
+            report.push_str(
+                "\nSlow commands were observed: check SLOWLOG GET and LATENCY HISTOGRAM to \
+                 find out which ones.\n",
+            );
+        }
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn records_events_above_threshold() {
+        let monitor = LatencyMonitor::new();
+        monitor.record("command", Duration::from_millis(500));
+        assert!(monitor.latest().is_empty());
+
+        monitor.configure(100);
+        monitor.record("command", Duration::from_millis(99));
+        monitor.record("command", Duration::from_millis(150));
+        monitor.record("expire-cycle", Duration::from_millis(100));
+
+        let latest = monitor.latest();
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].0, "command");
+        assert_eq!(latest[0].1.latency, 150);
+        assert_eq!(latest[0].2, 150);
+        assert_eq!(monitor.history("expire-cycle").len(), 1);
+        assert!(monitor.doctor().contains("expire-cycle: 1 latency spikes"));
+
+        assert_eq!(monitor.reset(&["COMMAND".to_string()]), 1);
+        assert_eq!(monitor.reset(&[]), 1);
+        assert!(monitor.latest().is_empty());
+    }
+
+    #[test]
+    fn keeps_one_sample_per_second() {
+        let monitor = LatencyMonitor::new();
+        for (time, latency) in [(1, 10), (1, 30), (1, 20), (2, 5)] {
+            monitor.add_sample("command", LatencySample { time, latency });
+        }
+        assert_eq!(
+            monitor.history("command"),
+            vec![
+                LatencySample {
+                    time: 1,
+                    latency: 30
+                },
+                LatencySample {
+                    time: 2,
+                    latency: 5
+                }
+            ]
+        );
+
+        for time in 3..(3 + HISTORY_LEN as u64) {
+            monitor.add_sample("command", LatencySample { time, latency: 1 });
+        }
+        let history = monitor.history("command");
+        assert_eq!(history.len(), HISTORY_LEN);
+        assert_eq!(history[0].time, 3);
+    }
+}
@@ -1,31 +1,343 @@
+pub mod acl;
+pub mod builder;
+pub mod client;
 pub mod commands;
+pub mod config;
 pub mod error;
+pub mod eviction;
+pub mod glob;
+pub mod info;
+pub mod latency;
+pub mod metrics;
+pub mod modules;
+pub mod monitor;
+pub mod registry;
 pub mod resp;
 pub mod server;
+pub mod sharded;
+pub mod shutdown;
+pub mod slowlog;
+pub mod state;
+pub mod stats;
 pub mod store;
+pub mod testing;
+pub mod tls;
+pub mod tracking;
 
+use std::fs;
 use std::future::Future;
-use std::sync::{Arc, Mutex};
+use std::net::SocketAddr;
+#[cfg(unix)]
+use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::thread;
 
 use error::SerirError;
+use socket2::{Domain, Protocol, Socket, Type};
+use tokio::io;
 use tokio::net::TcpListener;
-
-use server::Server;
-use store::KeyValueStore;
+#[cfg(unix)]
+use tokio::net::UnixListener;
+use tokio::runtime;
 use tokio::select;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+use config::{BindAddr, Config};
+use registry::CommandRegistry;
+use server::{Listener, Server};
+use shutdown::ShutdownOptions;
+use state::ServerState;
+
+/// Number of worker threads to use when none was configured: one per available core.
+pub fn default_num_workers() -> usize {
+    thread::available_parallelism()
+        .map(|cores| cores.get())
+        .unwrap_or(1)
+}
+
+/// Serves clients the commands of `commands` until `signal` resolves or `SHUTDOWN` is
+/// sent, then shuts down gracefully, see [`ServerState::shut_down`].
+pub async fn run(
+    config: Config,
+    commands: CommandRegistry,
+    signal: impl Future,
+) -> Result<(), SerirError> {
+    check_listeners(&config)?;
+    let listeners = Listeners::bind(&config)?;
+    let unixsocket = config.unixsocket.clone();
+    let state = Arc::new(ServerState::with_commands(config, commands));
+    state.load_modules()?;
+    state.load_acl_file()?;
+    state.load_tls()?;
+    tokio::spawn(state.clone().cron());
+    listeners.serve(&state)?;
+
+    let options = select! {
+        _ = signal => ShutdownOptions::default(),
+        options = state.shutdown.requested() => options,
+    };
+    state.shut_down(options).await;
+    remove_unix_socket(unixsocket);
+    Ok(())
+}
 
-pub async fn run(port: u16, sigint: impl Future) -> Result<(), SerirError> {
-    let store = Arc::new(Mutex::new(KeyValueStore::new()));
-    let listener = TcpListener::bind(format!("0.0.0.0:{}", port)).await?;
-    let server = Server::new(store, listener);
+/// Runs `io-threads` threads, each with its own single-threaded runtime and its own
+/// listeners bound to the configured addresses with `SO_REUSEPORT`, so the kernel spreads
+/// incoming connections across the threads. All threads share the same store. The Unix
+/// socket, if any, is served by the first thread.
+///
+/// Blocks the calling thread until the server has shut down, like [`run`].
+#[cfg(unix)]
+pub fn run_thread_per_core(
+    config: Config,
+    commands: CommandRegistry,
+    signal: impl Future,
+) -> Result<(), SerirError> {
+    check_listeners(&config)?;
+    let num_workers = config.io_threads;
+    let bind = config.bind_addrs();
+    let backlog = config.tcp_backlog as i32;
+    let (port, tls_port) = (config.port, config.tls_port);
+    let mut metrics_listeners = Some(bind_listeners(&bind, config.metrics_port, false, backlog)?);
+    let mut unix_listener = config
+        .unixsocket
+        .as_deref()
+        .map(|path| unix_listener(path, config.unixsocketperm))
+        .transpose()?;
+    let unixsocket = config.unixsocket.clone();
+    let state = Arc::new(ServerState::with_commands(config, commands));
+    state.load_modules()?;
+    state.load_acl_file()?;
+    state.load_tls()?;
+    let (shutdown_tx, shutdown_rx) = watch::channel(());
 
-    select! {
-        _ = server.run() => {
+    let workers = (0..num_workers)
+        .map(|worker| {
+            let state = state.clone();
+            let mut shutdown_rx = shutdown_rx.clone();
+            // Bind here rather than on the worker thread so that errors like the port being
+            // in use are reported to the caller.
+            let listeners = bind_listeners(&bind, port, true, backlog)?;
+            let tls_listeners = bind_listeners(&bind, tls_port, true, backlog)?;
+            let run_cron = worker == 0;
+            let metrics_listeners = metrics_listeners.take().unwrap_or_default();
+            let unix_listener = unix_listener.take();
+            thread::Builder::new()
+                .name(format!("serir-worker-{}", worker))
+                .spawn(move || -> Result<(), SerirError> {
+                    let runtime = runtime::Builder::new_current_thread()
+                        .enable_all()
+                        .build()?;
+                    runtime.block_on(async move {
+                        if run_cron {
+                            tokio::spawn(state.clone().cron());
+                        }
+                        for listener in metrics_listeners {
+                            let listener = TcpListener::from_std(listener)?;
+                            tokio::spawn(serve_metrics(state.clone(), listener));
+                        }
+                        for listener in listeners {
+                            let listener = TcpListener::from_std(listener)?;
+                            tokio::spawn(serve(Server::new(state.clone(), listener)));
+                        }
+                        for listener in tls_listeners {
+                            let listener = TcpListener::from_std(listener)?;
+                            tokio::spawn(serve(Server::with_tls(state.clone(), listener)));
+                        }
+                        if let Some(listener) = unix_listener {
+                            let listener = UnixListener::from_std(listener)?;
+                            tokio::spawn(serve(Server::new(state, listener)));
+                        }
+                        // Workers only fail to see this if the sender is gone, which also
+                        // means it's time to stop.
+                        let _ = shutdown_rx.changed().await;
+                        Ok(())
+                    })
+                })
+                .map_err(SerirError::from)
+        })
+        .collect::<Result<Vec<_>, SerirError>>()?;
 
-        },
-        _ = sigint => {
+    let runtime = runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()?;
+    runtime.block_on(async {
+        let options = select! {
+            _ = signal => ShutdownOptions::default(),
+            options = state.shutdown.requested() => options,
+        };
+        state.shut_down(options).await;
+    });
+    // Workers only fail to see this if they have already exited.
+    let _ = shutdown_tx.send(());
 
+    for worker in workers {
+        if let Err(e) = worker.join().expect("worker thread panicked") {
+            eprintln!("Worker failed: {}", e);
         }
     }
+    remove_unix_socket(unixsocket);
+    Ok(())
+}
+
+/// Listeners of a server, bound before it starts so that errors like a port being in use
+/// are reported to the caller.
+struct Listeners {
+    tcp: Vec<std::net::TcpListener>,
+    tls: Vec<std::net::TcpListener>,
+    metrics: Vec<std::net::TcpListener>,
+    #[cfg(unix)]
+    unix: Option<std::os::unix::net::UnixListener>,
+}
+
+impl Listeners {
+    /// Binds the listeners `config` asks for.
+    fn bind(config: &Config) -> Result<Self, SerirError> {
+        let bind = config.bind_addrs();
+        let backlog = config.tcp_backlog as i32;
+        Ok(Self {
+            tcp: bind_listeners(&bind, config.port, false, backlog)?,
+            tls: bind_listeners(&bind, config.tls_port, false, backlog)?,
+            metrics: bind_listeners(&bind, config.metrics_port, false, backlog)?,
+            #[cfg(unix)]
+            unix: config
+                .unixsocket
+                .as_deref()
+                .map(|path| unix_listener(path, config.unixsocketperm))
+                .transpose()?,
+        })
+    }
+
+    /// Accepts connections on every listener, on the current runtime, until a shutdown
+    /// is requested. Returns the tasks of the listeners, which close them when they end.
+    fn serve(self, state: &Arc<ServerState>) -> Result<Vec<JoinHandle<()>>, SerirError> {
+        let mut tasks = vec![];
+        for listener in self.metrics {
+            let listener = TcpListener::from_std(listener)?;
+            tasks.push(tokio::spawn(serve_metrics(state.clone(), listener)));
+        }
+        for listener in self.tcp {
+            let listener = TcpListener::from_std(listener)?;
+            tasks.push(tokio::spawn(serve(Server::new(state.clone(), listener))));
+        }
+        for listener in self.tls {
+            let listener = TcpListener::from_std(listener)?;
+            tasks.push(tokio::spawn(serve(Server::with_tls(
+                state.clone(),
+                listener,
+            ))));
+        }
+        #[cfg(unix)]
+        if let Some(listener) = self.unix {
+            let listener = UnixListener::from_std(listener)?;
+            tasks.push(tokio::spawn(serve(Server::new(state.clone(), listener))));
+        }
+        Ok(tasks)
+    }
+}
+
+/// Refuses configurations that wouldn't accept any client, like Redis does.
+fn check_listeners(config: &Config) -> Result<(), SerirError> {
+    if config.port == 0 && config.tls_port == 0 && config.unixsocket.is_none() {
+        return Err(SerirError::ConfigError(
+            "configured to not listen anywhere, set port, tls-port or unixsocket".to_string(),
+        ));
+    }
+    if cfg!(not(unix)) && config.unixsocket.is_some() {
+        return Err(SerirError::ConfigError(
+            "unixsocket is not supported on this platform".to_string(),
+        ));
+    }
     Ok(())
 }
+
+async fn serve<L: Listener>(server: Server<L>) {
+    if let Err(e) = server.run().await {
+        eprintln!("Listener failed: {}", e);
+    }
+}
+
+async fn serve_metrics(state: Arc<ServerState>, listener: TcpListener) {
+    if let Err(e) = metrics::serve(state, listener).await {
+        eprintln!("Metrics listener failed: {}", e);
+    }
+}
+
+/// Binds the `unixsocket` listener, replacing the socket a previous run may have left
+/// behind, and applies `unixsocketperm` unless it is 0.
+#[cfg(unix)]
+fn unix_listener(path: &Path, perm: u32) -> Result<std::os::unix::net::UnixListener, SerirError> {
+    use std::os::unix::fs::PermissionsExt;
+
+    let _ = fs::remove_file(path);
+    let listener = std::os::unix::net::UnixListener::bind(path)?;
+    listener.set_nonblocking(true)?;
+    if perm != 0 {
+        fs::set_permissions(path, fs::Permissions::from_mode(perm))?;
+    }
+    Ok(listener)
+}
+
+fn remove_unix_socket(path: Option<PathBuf>) {
+    if let Some(path) = path {
+        let _ = fs::remove_file(path);
+    }
+}
+
+/// Binds a listener on `port` for every `bind` address, none if `port` is 0. Optional
+/// addresses that can't be bound are skipped, unless they are already in use.
+fn bind_listeners(
+    bind: &[BindAddr],
+    port: u16,
+    reuseport: bool,
+    backlog: i32,
+) -> Result<Vec<std::net::TcpListener>, SerirError> {
+    if port == 0 {
+        return Ok(vec![]);
+    }
+    let mut listeners = vec![];
+    for addr in bind {
+        match bind_listener((addr.ip, port).into(), reuseport, backlog) {
+            Ok(listener) => listeners.push(listener),
+            Err(e) if addr.optional && e.kind() != io::ErrorKind::AddrInUse => {
+                eprintln!("Skipping optional address {}:{}: {}", addr.ip, port, e);
+            }
+            Err(e) => {
+                return Err(SerirError::ConfigError(format!(
+                    "could not listen on {}:{}: {}",
+                    addr.ip, port, e
+                )))
+            }
+        }
+    }
+    if listeners.is_empty() {
+        return Err(SerirError::ConfigError(format!(
+            "no bind address is available for port {}",
+            port
+        )));
+    }
+    Ok(listeners)
+}
+
+fn bind_listener(
+    addr: SocketAddr,
+    reuseport: bool,
+    backlog: i32,
+) -> io::Result<std::net::TcpListener> {
+    let socket = Socket::new(Domain::for_address(addr), Type::STREAM, Some(Protocol::TCP))?;
+    // `::` would otherwise also take the IPv4 addresses, which `*` binds separately
+    if addr.is_ipv6() {
+        socket.set_only_v6(true)?;
+    }
+    socket.set_reuse_address(true)?;
+    #[cfg(unix)]
+    socket.set_reuse_port(reuseport)?;
+    #[cfg(not(unix))]
+    let _ = reuseport;
+    socket.bind(&addr.into())?;
+    socket.listen(backlog)?;
+    socket.set_nonblocking(true)?;
+    Ok(socket.into())
+}
@@ -1,4 +1,12 @@
+use std::future;
+use std::path::PathBuf;
+use std::process::ExitCode;
+
+use serir::config::Config;
+use serir::registry::CommandRegistry;
 use serir::{error::SerirError, run};
+use tokio::runtime;
+use tokio::select;
 use tokio::signal;
 
 use structopt::StructOpt;
@@ -6,13 +14,125 @@ use structopt::StructOpt;
 #[derive(StructOpt)]
 #[structopt(name = "serir")]
 struct Opt {
-    /// Port to listen on.
-    #[structopt(short, long, default_value = "6379")]
-    port: u16,
+    /// Path to a redis.conf-style configuration file. Flags take precedence over it.
+    #[structopt(parse(from_os_str))]
+    config_file: Option<PathBuf>,
+
+    /// Port to listen on. Defaults to 6379.
+    #[structopt(short, long)]
+    port: Option<u16>,
+
+    /// Port of the HTTP listener exporting Prometheus metrics on /metrics. Disabled by
+    /// default.
+    #[structopt(long)]
+    metrics_port: Option<u16>,
+
+    /// Port accepting TLS connections, see the tls-* configuration parameters. Disabled by
+    /// default.
+    #[structopt(long)]
+    tls_port: Option<u16>,
+
+    /// "no" accepts clients from other hosts even when the default user has no password.
+    #[structopt(long)]
+    protected_mode: Option<String>,
+
+    /// Number of worker threads. Defaults to the number of available cores.
+    #[structopt(short, long)]
+    num_workers: Option<usize>,
+
+    /// Run one single-threaded worker per core, each accepting connections on its own
+    /// SO_REUSEPORT listener, instead of a work-stealing runtime shared by all workers.
+    #[structopt(long)]
+    thread_per_core: bool,
+}
+
+impl Opt {
+    fn into_config(self) -> Result<Config, SerirError> {
+        let mut config = match &self.config_file {
+            Some(path) => Config::from_file(path)?,
+            None => Config::default(),
+        };
+        let flags = [
+            ("port", self.port.map(|port| port.to_string())),
+            (
+                "metrics-port",
+                self.metrics_port.map(|port| port.to_string()),
+            ),
+            ("tls-port", self.tls_port.map(|port| port.to_string())),
+            ("protected-mode", self.protected_mode),
+            ("io-threads", self.num_workers.map(|n| n.to_string())),
+        ];
+        for (name, value) in flags {
+            if let Some(value) = value {
+                config
+                    .apply(name, &[value])
+                    .map_err(|e| SerirError::ConfigError(format!("--{}: {}", name, e)))?;
+            }
+        }
+        Ok(config)
+    }
+}
+
+fn main() -> ExitCode {
+    match serve(Opt::from_args()) {
+        Ok(()) => {
+            eprintln!("serir is now ready to exit, bye bye...");
+            ExitCode::SUCCESS
+        }
+        Err(e) => {
+            eprintln!("{}", e);
+            ExitCode::FAILURE
+        }
+    }
+}
+
+fn serve(opt: Opt) -> Result<(), SerirError> {
+    let thread_per_core = opt.thread_per_core;
+    let config = opt.into_config()?;
+
+    if thread_per_core {
+        return run_thread_per_core(config);
+    }
+
+    let runtime = runtime::Builder::new_multi_thread()
+        .worker_threads(config.io_threads)
+        .enable_all()
+        .build()?;
+    runtime.block_on(run(config, CommandRegistry::default(), shutdown_signal()))
+}
+
+/// Resolves on SIGINT (Ctrl-C) or, on unix, SIGTERM.
+async fn shutdown_signal() {
+    #[cfg(unix)]
+    let terminate = async {
+        match signal::unix::signal(signal::unix::SignalKind::terminate()) {
+            Ok(mut terminate) => {
+                terminate.recv().await;
+            }
+            Err(e) => {
+                eprintln!("Unable to handle SIGTERM: {}", e);
+                future::pending::<()>().await
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = future::pending::<()>();
+
+    let received = select! {
+        _ = signal::ctrl_c() => "SIGINT",
+        _ = terminate => "SIGTERM",
+    };
+    eprintln!("Received {}, shutting down", received);
+}
+
+#[cfg(unix)]
+fn run_thread_per_core(config: Config) -> Result<(), SerirError> {
+    serir::run_thread_per_core(config, CommandRegistry::default(), shutdown_signal())
 }
 
-#[tokio::main]
-async fn main() -> Result<(), SerirError> {
-    let opt = Opt::from_args();
-    run(opt.port, signal::ctrl_c()).await
+#[cfg(not(unix))]
+fn run_thread_per_core(_config: Config) -> Result<(), SerirError> {
+    Err(SerirError::ConfigError(
+        "--thread-per-core requires SO_REUSEPORT, which is only available on unix".to_string(),
+    ))
 }
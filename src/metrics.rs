@@ -0,0 +1,331 @@
+use std::fmt::{Display, Write};
+use std::sync::Arc;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::select;
+
+use crate::error::SerirResult;
+use crate::state::ServerState;
+use crate::stats::LATENCY_BUCKETS;
+
+/// Requests are tiny, anything bigger than this is not a scraper talking to us.
+const MAX_REQUEST_SIZE: u64 = 8 * 1024;
+
+/// Serves the Prometheus text format on `GET /metrics`, one request per connection, until
+/// a shutdown is requested.
+pub async fn serve(state: Arc<ServerState>, listener: TcpListener) -> SerirResult<()> {
+    loop {
+        let (socket, _) = select! {
+            connection = listener.accept() => connection?,
+            _ = state.shutdown.requested() => return Ok(()),
+        };
+        let state = state.clone();
+        tokio::spawn(async move {
+            if let Err(e) = handle_request(&state, socket).await {
+                eprintln!("Error serving metrics: {}", e);
+            }
+        });
+    }
+}
+
+async fn handle_request(state: &ServerState, mut socket: TcpStream) -> SerirResult<()> {
+    let (reader, mut writer) = socket.split();
+    let mut reader = BufReader::new(reader.take(MAX_REQUEST_SIZE));
+    let mut request_line = String::new();
+    reader.read_line(&mut request_line).await?;
+    // headers are of no interest, but must be read before answering
+    loop {
+        let mut header = String::new();
+        if reader.read_line(&mut header).await? == 0 || header.trim_end().is_empty() {
+            break;
+        }
+    }
+
+    let mut parts = request_line.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts
+        .next()
+        .and_then(|target| target.split('?').next())
+        .unwrap_or_default();
+    let (status, body) = match (method, path) {
+        ("GET", "/metrics") => ("200 OK", render(state)),
+        ("GET", _) => ("404 Not Found", "Not Found\n".to_string()),
+        _ => ("405 Method Not Allowed", "Method Not Allowed\n".to_string()),
+    };
+
+    let response = format!(
+        "HTTP/1.1 {}\r\n\
+         Content-Type: text/plain; version=0.0.4; charset=utf-8\r\n\
+         Content-Length: {}\r\n\
+         Connection: close\r\n\
+         \r\n\
+         {}",
+        status,
+        body.len(),
+        body
+    );
+    writer.write_all(response.as_bytes()).await?;
+    writer.shutdown().await?;
+    Ok(())
+}
+
+fn header(metrics: &mut String, name: &str, kind: &str, help: &str) {
+    write!(
+        metrics,
+        "# HELP {} {}\n# TYPE {} {}\n",
+        name, help, name, kind
+    )
+    .unwrap();
+}
+
+fn sample(metrics: &mut String, name: &str, labels: &str, value: impl Display) {
+    if labels.is_empty() {
+        writeln!(metrics, "{} {}", name, value).unwrap();
+    } else {
+        writeln!(metrics, "{}{{{}}} {}", name, labels, value).unwrap();
+    }
+}
+
+fn metric(metrics: &mut String, name: &str, kind: &str, help: &str, value: impl Display) {
+    header(metrics, name, kind, help);
+    sample(metrics, name, "", value);
+}
+
+/// Renders every metric in the Prometheus text exposition format.
+pub fn render(state: &ServerState) -> String {
+    let stats = &state.stats;
+    let maxmemory = state.config.read().unwrap().maxmemory;
+    let (keys, expires, expired_keys, evicted_keys, hits, misses) = state.store.fold(
+        (0, 0, 0, 0, 0, 0),
+        |(keys, expires, expired, evicted, hits, misses), shard| {
+            (
+                keys + shard.len(),
+                expires + shard.volatile_len(),
+                expired + shard.expired_keys(),
+                evicted + shard.evicted_keys(),
+                hits + shard.keyspace_hits(),
+                misses + shard.keyspace_misses(),
+            )
+        },
+    );
+
+    let mut metrics = String::new();
+    let m = &mut metrics;
+    metric(
+        m,
+        "serir_uptime_seconds",
+        "gauge",
+        "Seconds since the server started.",
+        stats.uptime().as_secs(),
+    );
+    metric(
+        m,
+        "serir_connected_clients",
+        "gauge",
+        "Number of client connections.",
+        stats.connected_clients(),
+    );
+    metric(
+        m,
+        "serir_connections_received_total",
+        "counter",
+        "Connections accepted by the server.",
+        stats.total_connections_received(),
+    );
+    metric(
+        m,
+        "serir_rejected_connections_total",
+        "counter",
+        "Connections rejected by the server.",
+        stats.rejected_connections(),
+    );
+    metric(
+        m,
+        "serir_net_input_bytes_total",
+        "counter",
+        "Bytes read from clients.",
+        stats.total_net_input_bytes(),
+    );
+    metric(
+        m,
+        "serir_net_output_bytes_total",
+        "counter",
+        "Bytes written to clients.",
+        stats.total_net_output_bytes(),
+    );
+    metric(
+        m,
+        "serir_commands_in_flight",
+        "gauge",
+        "Commands executing or waiting for the shards they need.",
+        stats.commands_in_flight(),
+    );
+
+    header(
+        m,
+        "serir_commands_processed_total",
+        "counter",
+        "Commands processed, by command.",
+    );
+    stats.for_each_command(|name, command| {
+        sample(
+            m,
+            "serir_commands_processed_total",
+            &format!("cmd=\"{}\"", name),
+            command.calls(),
+        );
+    });
+
+    header(
+        m,
+        "serir_command_duration_seconds",
+        "histogram",
+        "Time spent executing commands, by command.",
+    );
+    stats.for_each_command(|name, command| {
+        let buckets = command.latency_buckets();
+        let mut cumulative = 0;
+        // the last bucket also holds everything slower, so it is only reported as +Inf
+        for (i, count) in buckets[..LATENCY_BUCKETS - 1].iter().enumerate() {
+            cumulative += count;
+            let le = (1u64 << i) as f64 / 1e6;
+            sample(
+                m,
+                "serir_command_duration_seconds_bucket",
+                &format!("cmd=\"{}\",le=\"{}\"", name, le),
+                cumulative,
+            );
+        }
+        let labels = format!("cmd=\"{}\"", name);
+        sample(
+            m,
+            "serir_command_duration_seconds_bucket",
+            &format!("{},le=\"+Inf\"", labels),
+            command.calls(),
+        );
+        sample(
+            m,
+            "serir_command_duration_seconds_sum",
+            &labels,
+            command.usec() as f64 / 1e6,
+        );
+        sample(
+            m,
+            "serir_command_duration_seconds_count",
+            &labels,
+            command.calls(),
+        );
+    });
+
+    header(m, "serir_keyspace_keys", "gauge", "Keys, by database.");
+    sample(m, "serir_keyspace_keys", "db=\"0\"", keys);
+    header(
+        m,
+        "serir_keyspace_expires",
+        "gauge",
+        "Keys with an expiration, by database.",
+    );
+    sample(m, "serir_keyspace_expires", "db=\"0\"", expires);
+    metric(
+        m,
+        "serir_expired_keys_total",
+        "counter",
+        "Keys deleted because they expired.",
+        expired_keys,
+    );
+    metric(
+        m,
+        "serir_evicted_keys_total",
+        "counter",
+        "Keys evicted because of maxmemory.",
+        evicted_keys,
+    );
+    metric(
+        m,
+        "serir_keyspace_hits_total",
+        "counter",
+        "Successful key lookups.",
+        hits,
+    );
+    metric(
+        m,
+        "serir_keyspace_misses_total",
+        "counter",
+        "Failed key lookups.",
+        misses,
+    );
+    metric(
+        m,
+        "serir_used_memory_bytes",
+        "gauge",
+        "Memory used by keys and values.",
+        state.store.used_memory(),
+    );
+    metric(
+        m,
+        "serir_maxmemory_bytes",
+        "gauge",
+        "Configured maxmemory, 0 when unlimited.",
+        maxmemory,
+    );
+    metrics
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    use crate::commands::Command;
+    use crate::config::Config;
+
+    #[test]
+    fn renders_counters_and_histograms() {
+        let state = ServerState::new(Config::default());
+        state
+            .exec(Command::Set((
+                b"key".to_vec(),
+                Bytes::from_static(b"value"),
+            )))
+            .unwrap();
+        state.exec(Command::Get(b"key".to_vec())).unwrap();
+        state.exec(Command::Get(b"key".to_vec())).unwrap();
+
+        let metrics = render(&state);
+        let lines: Vec<_> = metrics.lines().collect();
+        assert!(lines.contains(&"serir_commands_processed_total{cmd=\"get\"} 2"));
+        assert!(lines.contains(&"serir_command_duration_seconds_bucket{cmd=\"get\",le=\"+Inf\"} 2"));
+        assert!(lines.contains(&"serir_command_duration_seconds_count{cmd=\"set\"} 1"));
+        assert!(lines.contains(&"serir_keyspace_keys{db=\"0\"} 1"));
+        assert!(lines.contains(&"# TYPE serir_command_duration_seconds histogram"));
+        assert!(lines.iter().any(|line| line
+            .starts_with("serir_command_duration_seconds_bucket{cmd=\"get\",le=\"0.000001\"}")));
+    }
+
+    #[tokio::test]
+    async fn answers_http_requests() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(serve(
+            Arc::new(ServerState::new(Config::default())),
+            listener,
+        ));
+
+        async fn get(addr: std::net::SocketAddr, request: &str) -> String {
+            let mut socket = TcpStream::connect(addr).await.unwrap();
+            socket.write_all(request.as_bytes()).await.unwrap();
+            let mut response = String::new();
+            socket.read_to_string(&mut response).await.unwrap();
+            response
+        }
+
+        let response = get(addr, "GET /metrics HTTP/1.1\r\nHost: localhost\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.contains("serir_connected_clients 0\n"));
+        let response = get(addr, "GET / HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 404 Not Found\r\n"));
+        let response = get(addr, "POST /metrics HTTP/1.1\r\n\r\n").await;
+        assert!(response.starts_with("HTTP/1.1 405 Method Not Allowed\r\n"));
+    }
+}
@@ -0,0 +1,436 @@
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, OnceLock, RwLock, Weak};
+use std::thread;
+
+use crate::registry::CommandHandler;
+use crate::state::ServerState;
+
+/// Modules linked into the program, which `loadmodule` and `MODULE LOAD` can load by name.
+static AVAILABLE: RwLock<BTreeMap<&'static str, Arc<dyn Module>>> = RwLock::new(BTreeMap::new());
+
+/// Makes `module` available to `loadmodule` and `MODULE LOAD`, replacing any module with
+/// the same name. Loading it is up to the configuration or to clients.
+pub fn provide(module: impl Module + 'static) {
+    AVAILABLE
+        .write()
+        .unwrap()
+        .insert(module.name(), Arc::new(module));
+}
+
+fn available(name: &str) -> Option<Arc<dyn Module>> {
+    AVAILABLE.read().unwrap().get(name).cloned()
+}
+
+/// An extension that adds commands, watches the keyspace or does work in background
+/// threads. Modules are compiled into the program and made available with [`provide`].
+pub trait Module: Send + Sync {
+    /// Name `MODULE LOAD`, `MODULE UNLOAD` and `MODULE LIST` know the module by.
+    fn name(&self) -> &'static str;
+
+    /// Version reported by `MODULE LIST`.
+    fn version(&self) -> i64 {
+        1
+    }
+
+    /// Registers what the module provides with `context`. `arguments` follow the module
+    /// name in `loadmodule` or `MODULE LOAD`. Nothing is registered if loading fails.
+    fn load(&self, context: &mut ModuleContext, arguments: &[String]) -> Result<(), String>;
+
+    /// Called by `MODULE UNLOAD` before the module's commands and subscriptions are
+    /// removed and its threads asked to stop. An error keeps the module loaded.
+    fn unload(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+/// A key that changed, as told to modules subscribed to keyspace events.
+#[derive(Debug, Clone, Copy)]
+pub struct KeyspaceEvent<'a> {
+    /// Command that changed the key, `None` if it expired or got evicted in the
+    /// background.
+    pub command: Option<&'a str>,
+    pub key: &'a [u8],
+}
+
+type KeyspaceSubscriber = Arc<dyn Fn(&KeyspaceEvent) + Send + Sync>;
+type ThreadBody = Box<dyn FnOnce(ModuleThread) + Send>;
+
+/// What a module registers while it loads.
+#[derive(Default)]
+pub struct ModuleContext {
+    commands: Vec<Arc<dyn CommandHandler>>,
+    subscribers: Vec<KeyspaceSubscriber>,
+    threads: Vec<(String, ThreadBody)>,
+}
+
+impl ModuleContext {
+    /// Adds a command. Loading fails if a command with the same name exists.
+    pub fn register_command(&mut self, handler: impl CommandHandler + 'static) {
+        self.commands.push(Arc::new(handler));
+    }
+
+    /// Calls `subscriber` for every key that changes, once the command that changed it
+    /// has run.
+    pub fn subscribe_to_keyspace_events(
+        &mut self,
+        subscriber: impl Fn(&KeyspaceEvent) + Send + Sync + 'static,
+    ) {
+        self.subscribers.push(Arc::new(subscriber));
+    }
+
+    /// Runs `body` in a thread named `name` once the module is loaded.
+    pub fn spawn_thread(&mut self, name: &str, body: impl FnOnce(ModuleThread) + Send + 'static) {
+        self.threads.push((name.to_string(), Box::new(body)));
+    }
+}
+
+/// Handed to the background threads of a module.
+pub struct ModuleThread {
+    server: Weak<ServerState>,
+    stop: Arc<AtomicBool>,
+}
+
+impl ModuleThread {
+    /// The server, unless it is gone. Threads should only hold on to it briefly, so that
+    /// it can be dropped.
+    pub fn server(&self) -> Option<Arc<ServerState>> {
+        self.server.upgrade()
+    }
+
+    /// Whether the module got unloaded or the server is shutting down, in which case the
+    /// thread should return.
+    pub fn should_stop(&self) -> bool {
+        self.stop.load(Ordering::Relaxed)
+    }
+}
+
+struct LoadedModule {
+    module: Arc<dyn Module>,
+    arguments: Vec<String>,
+    /// Names of the commands the module registered.
+    commands: Vec<String>,
+    subscribers: Vec<KeyspaceSubscriber>,
+    /// Tells the module's threads to stop.
+    stop: Arc<AtomicBool>,
+}
+
+/// Modules loaded in a server.
+#[derive(Default)]
+pub struct Modules {
+    loaded: Mutex<BTreeMap<String, LoadedModule>>,
+    /// Subscribers of all loaded modules, to notify without going through `loaded`.
+    subscribers: RwLock<Vec<KeyspaceSubscriber>>,
+    /// The server the modules are loaded in, for their threads. Unset until the server
+    /// runs.
+    server: OnceLock<Weak<ServerState>>,
+}
+
+impl Modules {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Sets the server module threads get to access.
+    pub(crate) fn attach(&self, server: Weak<ServerState>) {
+        let _ = self.server.set(server);
+    }
+
+    /// Loads the module available as `name`. `register` adds its commands to the server,
+    /// failing if any of them exists already. Returns whether the module subscribed to
+    /// keyspace events.
+    pub(crate) fn load(
+        &self,
+        name: &str,
+        arguments: &[String],
+        register: impl FnOnce(&[Arc<dyn CommandHandler>]) -> Result<(), String>,
+    ) -> Result<bool, String> {
+        let mut loaded = self.loaded.lock().unwrap();
+        if loaded.contains_key(name) {
+            return Err(format!("module {} is already loaded", name));
+        }
+        let module = available(name).ok_or_else(|| format!("no module named {}", name))?;
+        let mut context = ModuleContext::default();
+        module.load(&mut context, arguments)?;
+        register(&context.commands)?;
+
+        let subscribes = !context.subscribers.is_empty();
+        self.subscribers
+            .write()
+            .unwrap()
+            .extend(context.subscribers.iter().cloned());
+        let stop = Arc::new(AtomicBool::new(false));
+        loaded.insert(
+            name.to_string(),
+            LoadedModule {
+                module,
+                arguments: arguments.to_vec(),
+                commands: context
+                    .commands
+                    .iter()
+                    .map(|handler| handler.spec().name.to_lowercase())
+                    .collect(),
+                subscribers: context.subscribers,
+                stop: stop.clone(),
+            },
+        );
+
+        let server = self.server.get().cloned().unwrap_or_default();
+        for (thread_name, body) in context.threads {
+            let thread = ModuleThread {
+                server: server.clone(),
+                stop: stop.clone(),
+            };
+            if let Err(e) = thread::Builder::new()
+                .name(thread_name.clone())
+                .spawn(move || body(thread))
+            {
+                eprintln!(
+                    "Module {} could not start thread {}: {}",
+                    name, thread_name, e
+                );
+            }
+        }
+        Ok(subscribes)
+    }
+
+    /// Unloads a module, asking its threads to stop. Returns the commands it registered
+    /// and whether it had subscribed to keyspace events.
+    pub(crate) fn unload(&self, name: &str) -> Result<(Vec<String>, bool), String> {
+        let mut loaded = self.loaded.lock().unwrap();
+        let module = loaded
+            .get(name)
+            .ok_or_else(|| "no such module with that name".to_string())?;
+        module.module.unload()?;
+        let module = loaded.remove(name).unwrap();
+        module.stop.store(true, Ordering::Relaxed);
+        self.subscribers.write().unwrap().retain(|subscriber| {
+            !module
+                .subscribers
+                .iter()
+                .any(|unloaded| Arc::ptr_eq(subscriber, unloaded))
+        });
+        Ok((module.commands, !module.subscribers.is_empty()))
+    }
+
+    /// Asks the threads of every module to stop.
+    pub(crate) fn stop_threads(&self) {
+        for module in self.loaded.lock().unwrap().values() {
+            module.stop.store(true, Ordering::Relaxed);
+        }
+    }
+
+    /// Tells subscribed modules that `keys` changed.
+    pub(crate) fn notify(&self, command: Option<&str>, keys: &[Vec<u8>]) {
+        let subscribers = self.subscribers.read().unwrap();
+        if subscribers.is_empty() {
+            return;
+        }
+        for key in keys {
+            let event = KeyspaceEvent { command, key };
+            for subscriber in subscribers.iter() {
+                subscriber(&event);
+            }
+        }
+    }
+
+    /// Names of the commands registered by module `name`.
+    pub fn commands_of(&self, name: &str) -> Vec<String> {
+        self.loaded
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|module| module.commands.clone())
+            .unwrap_or_default()
+    }
+
+    /// Name, version and arguments of every loaded module.
+    pub fn list(&self) -> Vec<(String, i64, Vec<String>)> {
+        self.loaded
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, module)| {
+                (
+                    name.clone(),
+                    module.module.version(),
+                    module.arguments.clone(),
+                )
+            })
+            .collect()
+    }
+}
+
+impl fmt::Debug for Modules {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list()
+            .entries(self.loaded.lock().unwrap().keys())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::mpsc::{self, Sender};
+    use std::time::Duration;
+
+    use bytes::Bytes;
+
+    use crate::client::Client;
+    use crate::commands::{Command, CommandDocs, CommandSpec};
+    use crate::config::Config;
+    use crate::error::{SerirError, SerirResult};
+    use crate::resp::Resp;
+
+    type Changes = Arc<Mutex<Vec<(Option<String>, Vec<u8>)>>>;
+
+    static CHANGES: CommandSpec = CommandSpec {
+        name: "watcher.changes",
+        arity: 1,
+        flags: &["readonly", "fast"],
+        categories: &["read", "fast"],
+        key_specs: &[],
+        docs: CommandDocs {
+            summary: "Returns how many key changes the watcher saw.",
+            since: "1.0.0",
+            group: "module",
+            complexity: "O(1)",
+        },
+        subcommands: &[],
+    };
+
+    struct ChangesCommand(Changes);
+
+    impl CommandHandler for ChangesCommand {
+        fn spec(&self) -> &'static CommandSpec {
+            &CHANGES
+        }
+
+        fn exec(&self, _: &ServerState, _: &Client, _: Command) -> SerirResult<Resp> {
+            Ok(Resp::Integer(self.0.lock().unwrap().len() as i64))
+        }
+    }
+
+    /// Records key changes and runs a thread that reports whether it could reach the
+    /// server once it is told to stop.
+    struct Watcher {
+        changes: Changes,
+        stopped: Sender<bool>,
+    }
+
+    impl Module for Watcher {
+        fn name(&self) -> &'static str {
+            "watcher"
+        }
+
+        fn load(&self, context: &mut ModuleContext, arguments: &[String]) -> Result<(), String> {
+            if !arguments.is_empty() {
+                return Err("no arguments expected".to_string());
+            }
+            context.register_command(ChangesCommand(self.changes.clone()));
+            let changes = self.changes.clone();
+            context.subscribe_to_keyspace_events(move |event| {
+                changes
+                    .lock()
+                    .unwrap()
+                    .push((event.command.map(str::to_string), event.key.to_vec()));
+            });
+            let stopped = self.stopped.clone();
+            context.spawn_thread("watcher", move |thread| {
+                let reachable = thread.server().is_some();
+                while !thread.should_stop() {
+                    thread::sleep(Duration::from_millis(1));
+                }
+                stopped.send(reachable).unwrap();
+            });
+            Ok(())
+        }
+    }
+
+    fn request(state: &ServerState, client: &Client, args: &[&str]) -> Vec<u8> {
+        let request = Resp::Array(Some(
+            args.iter()
+                .map(|arg| Resp::BulkString(Some(Bytes::copy_from_slice(arg.as_bytes()))))
+                .collect(),
+        ));
+        match state.parse_request(client, request) {
+            Ok(request) => state.exec_request(client, request).unwrap(),
+            Err(SerirError::CommandError(message)) => Resp::Error(message.into_bytes()),
+            Err(e) => panic!("{}", e),
+        }
+        .serialize()
+        .unwrap()
+    }
+
+    #[test]
+    fn loads_and_unloads_modules() {
+        let changes = Changes::default();
+        let (stopped, thread_stopped) = mpsc::channel();
+        provide(Watcher {
+            changes: changes.clone(),
+            stopped,
+        });
+        let state = Arc::new(ServerState::new(Config::default()));
+        state.load_modules().unwrap();
+        let client = Client::new(1, String::new(), String::new());
+
+        let load_error = b"-ERR Error loading the extension. Please check the server logs.\r\n";
+        assert_eq!(
+            request(&state, &client, &["MODULE", "LOAD", "watcher", "extra"]),
+            load_error.to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["MODULE", "LOAD", "nosuchmodule"]),
+            load_error.to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["MODULE", "LOAD", "watcher"]),
+            b"+OK\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["MODULE", "LOAD", "watcher"]),
+            load_error.to_vec()
+        );
+
+        request(&state, &client, &["SET", "a", "1"]);
+        request(&state, &client, &["MSET", "b", "2", "c", "3"]);
+        request(&state, &client, &["DEL", "a"]);
+        assert_eq!(
+            request(&state, &client, &["WATCHER.CHANGES"]),
+            b":4\r\n".to_vec()
+        );
+        assert_eq!(
+            changes.lock().unwrap()[3],
+            (Some("del".to_string()), b"a".to_vec())
+        );
+        assert_eq!(
+            request(&state, &client, &["MODULE", "LIST"]),
+            b"*1\r\n*8\r\n$4\r\nname\r\n$7\r\nwatcher\r\n$3\r\nver\r\n:1\r\n\
+              $4\r\npath\r\n$7\r\nwatcher\r\n$4\r\nargs\r\n*0\r\n"
+                .to_vec()
+        );
+        assert_eq!(
+            request(
+                &state,
+                &client,
+                &["COMMAND", "LIST", "FILTERBY", "MODULE", "watcher"]
+            ),
+            b"*1\r\n$15\r\nwatcher.changes\r\n".to_vec()
+        );
+
+        assert_eq!(
+            request(&state, &client, &["MODULE", "UNLOAD", "watcher"]),
+            b"+OK\r\n".to_vec()
+        );
+        assert!(thread_stopped.recv_timeout(Duration::from_secs(5)).unwrap());
+        assert!(request(&state, &client, &["WATCHER.CHANGES"]).starts_with(b"-ERR unknown"));
+        assert_eq!(
+            request(&state, &client, &["MODULE", "UNLOAD", "watcher"]),
+            b"-ERR Error unloading module: no such module with that name\r\n".to_vec()
+        );
+        request(&state, &client, &["SET", "a", "1"]);
+        assert_eq!(changes.lock().unwrap().len(), 4);
+    }
+}
@@ -0,0 +1,136 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use tokio::sync::mpsc;
+
+use crate::client::Client;
+use crate::resp::Resp;
+
+/// Lines a monitor can fall behind before it gets disconnected, so that a slow monitor
+/// never makes the server wait or buffer without bound.
+const MONITOR_BUFFER_LINES: usize = 1024;
+
+/// Connections in `MONITOR` mode, fed a line for every executed command.
+#[derive(Debug, Default)]
+pub struct Monitors {
+    count: AtomicUsize,
+    senders: Mutex<Vec<mpsc::Sender<Arc<[u8]>>>>,
+}
+
+impl Monitors {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a new monitor. The receiver is closed when the monitor falls behind.
+    pub fn subscribe(&self) -> mpsc::Receiver<Arc<[u8]>> {
+        let (sender, receiver) = mpsc::channel(MONITOR_BUFFER_LINES);
+        let mut senders = self.senders.lock().unwrap();
+        senders.push(sender);
+        self.count.store(senders.len(), Ordering::Relaxed);
+        receiver
+    }
+
+    /// Whether anybody is listening, checked before paying for formatting the feed.
+    pub fn is_active(&self) -> bool {
+        self.count.load(Ordering::Relaxed) > 0
+    }
+
+    /// Sends `line` to every monitor, dropping the ones that are gone or too slow.
+    pub fn feed(&self, line: Vec<u8>) {
+        let line: Arc<[u8]> = line.into();
+        let mut senders = self.senders.lock().unwrap();
+        senders.retain(|sender| sender.try_send(line.clone()).is_ok());
+        self.count.store(senders.len(), Ordering::Relaxed);
+    }
+}
+
+/// Formats a request the way `MONITOR` shows it:
+/// `+1700000000.123456 [0 127.0.0.1:5555] "SET" "k" "v"`.
+pub fn format_line(client: &Client, elements: &[Resp]) -> Vec<u8> {
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap_or_default();
+    let mut line = format!(
+        "+{}.{:06} [0 {}]",
+        now.as_secs(),
+        now.subsec_micros(),
+        client.addr
+    )
+    .into_bytes();
+    for element in elements {
+        line.push(b' ');
+        match element {
+            Resp::BulkString(Some(arg)) => quote(&mut line, arg),
+            Resp::Integer(value) => quote(&mut line, value.to_string().as_bytes()),
+            _ => quote(&mut line, b""),
+        }
+    }
+    line.extend_from_slice(b"\r\n");
+    line
+}
+
+/// Quotes and escapes `arg` so the line stays printable, like Redis' `sdscatrepr`.
+fn quote(line: &mut Vec<u8>, arg: &[u8]) {
+    line.push(b'"');
+    for &byte in arg {
+        match byte {
+            b'\\' => line.extend_from_slice(b"\\\\"),
+            b'"' => line.extend_from_slice(b"\\\""),
+            b'\n' => line.extend_from_slice(b"\\n"),
+            b'\r' => line.extend_from_slice(b"\\r"),
+            b'\t' => line.extend_from_slice(b"\\t"),
+            0x07 => line.extend_from_slice(b"\\a"),
+            0x08 => line.extend_from_slice(b"\\b"),
+            byte if byte.is_ascii_graphic() || byte == b' ' => line.push(byte),
+            byte => line.extend_from_slice(format!("\\x{:02x}", byte).as_bytes()),
+        }
+    }
+    line.push(b'"');
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn bulk(arg: &[u8]) -> Resp {
+        Resp::BulkString(Some(Bytes::copy_from_slice(arg)))
+    }
+
+    #[test]
+    fn formats_commands_like_redis() {
+        let client = Client::new(1, "127.0.0.1:5555".to_string(), String::new());
+        let line = format_line(&client, &[bulk(b"SET"), bulk(b"k"), bulk(b"a \"b\"\n\x01")]);
+        let line = String::from_utf8(line).unwrap();
+
+        let (timestamp, rest) = line.split_once(' ').unwrap();
+        assert!(timestamp.starts_with('+'));
+        assert_eq!(timestamp.split_once('.').unwrap().1.len(), 6);
+        assert_eq!(
+            rest,
+            "[0 127.0.0.1:5555] \"SET\" \"k\" \"a \\\"b\\\"\\n\\x01\"\r\n"
+        );
+    }
+
+    #[test]
+    fn drops_monitors_that_fall_behind() {
+        let monitors = Monitors::new();
+        let mut fast = monitors.subscribe();
+        let slow = monitors.subscribe();
+        assert!(monitors.is_active());
+
+        for i in 0..MONITOR_BUFFER_LINES + 1 {
+            monitors.feed(i.to_string().into_bytes());
+            assert_eq!(&*fast.try_recv().unwrap(), i.to_string().as_bytes());
+        }
+        // the slow monitor was disconnected once its buffer filled up
+        assert_eq!(monitors.senders.lock().unwrap().len(), 1);
+        drop(slow);
+
+        drop(fast);
+        monitors.feed(b"nobody listens".to_vec());
+        assert!(!monitors.is_active());
+    }
+}
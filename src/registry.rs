@@ -0,0 +1,314 @@
+use std::fmt;
+use std::sync::Arc;
+
+use indexmap::IndexMap;
+
+use crate::client::Client;
+use crate::commands::{
+    builtin_parser, split_request, unknown_command, Command, CommandSpec, CustomCommand, Parser,
+    COMMAND_TABLE,
+};
+use crate::error::SerirResult;
+use crate::resp::Resp;
+use crate::state::ServerState;
+
+/// Parses and executes a command. Built-in commands are handlers too, so a handler
+/// registered under the name of one replaces it.
+pub trait CommandHandler: Send + Sync {
+    /// Name, arity, flags, ACL categories, where the keys are and documentation of the
+    /// command, as reported by `COMMAND` and used by ACLs, client side caching and
+    /// `CLIENT PAUSE WRITE`.
+    fn spec(&self) -> &'static CommandSpec;
+
+    /// Parses the arguments following the command name. By default they are checked
+    /// against the arity of the spec and kept as a [`CustomCommand`].
+    fn parse(&self, arguments: &[Resp]) -> SerirResult<Command> {
+        Ok(Command::Custom(CustomCommand::parse(
+            self.spec(),
+            arguments,
+        )?))
+    }
+
+    /// Executes a command returned by [`CommandHandler::parse`] on behalf of `client`.
+    /// Errors to send back to the client are replies, like `Resp::Error`.
+    fn exec(&self, state: &ServerState, client: &Client, command: Command) -> SerirResult<Resp>;
+}
+
+/// A command implemented by serir itself.
+struct Builtin {
+    spec: &'static CommandSpec,
+    parse: Parser,
+}
+
+impl CommandHandler for Builtin {
+    fn spec(&self) -> &'static CommandSpec {
+        self.spec
+    }
+
+    fn parse(&self, arguments: &[Resp]) -> SerirResult<Command> {
+        (self.parse)(arguments)
+    }
+
+    fn exec(&self, state: &ServerState, client: &Client, command: Command) -> SerirResult<Resp> {
+        state.exec_builtin(client, command)
+    }
+}
+
+/// The commands a server knows, by name. It starts with the built-in ones, which can be
+/// replaced or removed before the server starts, and more can be added.
+#[derive(Clone)]
+pub struct CommandRegistry {
+    /// Handlers by lowercase name, in the order they were registered.
+    handlers: IndexMap<String, Arc<dyn CommandHandler>>,
+}
+
+impl CommandRegistry {
+    /// A registry without any command.
+    pub fn empty() -> Self {
+        Self {
+            handlers: IndexMap::new(),
+        }
+    }
+
+    /// A registry with every command serir implements.
+    pub fn builtins() -> Self {
+        let mut registry = Self::empty();
+        for spec in COMMAND_TABLE {
+            let parse = builtin_parser(spec.name).expect("built-in command without a parser");
+            registry.register(Builtin { spec, parse });
+        }
+        registry
+    }
+
+    /// Adds a command, named after its spec. Returns the handler it replaces, if any,
+    /// which the new one may call.
+    pub fn register(
+        &mut self,
+        handler: impl CommandHandler + 'static,
+    ) -> Option<Arc<dyn CommandHandler>> {
+        self.insert(Arc::new(handler))
+    }
+
+    pub(crate) fn insert(
+        &mut self,
+        handler: Arc<dyn CommandHandler>,
+    ) -> Option<Arc<dyn CommandHandler>> {
+        let name = handler.spec().name.to_lowercase();
+        self.handlers.insert(name, handler)
+    }
+
+    /// Removes a command, which clients then get told is unknown.
+    pub fn remove(&mut self, name: &str) -> Option<Arc<dyn CommandHandler>> {
+        self.handlers.shift_remove(&name.to_lowercase())
+    }
+
+    /// Looks a handler up, ignoring case.
+    pub fn get(&self, name: &str) -> Option<&Arc<dyn CommandHandler>> {
+        match self.handlers.get(name) {
+            Some(handler) => Some(handler),
+            None => self.handlers.get(&name.to_lowercase()),
+        }
+    }
+
+    /// Looks the spec of a command up, ignoring case.
+    pub fn spec(&self, name: &str) -> Option<&'static CommandSpec> {
+        self.get(name).map(|handler| handler.spec())
+    }
+
+    /// Looks a command or a subcommand named like `client|kill` up, ignoring case.
+    pub fn lookup(&self, name: &str) -> Option<&'static CommandSpec> {
+        match name.split_once('|') {
+            Some((name, subcommand)) => self.spec(name)?.subcommand(subcommand),
+            None => self.spec(name),
+        }
+    }
+
+    /// Specs of every command, in the order they were registered.
+    pub fn specs(&self) -> impl Iterator<Item = &'static CommandSpec> + '_ {
+        self.handlers.values().map(|handler| handler.spec())
+    }
+
+    pub fn len(&self) -> usize {
+        self.handlers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.handlers.is_empty()
+    }
+
+    /// Parses a request with the handler of the command it names.
+    pub fn parse(&self, request: Resp) -> SerirResult<Command> {
+        let (name, elements) = split_request(request)?;
+        match self.handlers.get(&name) {
+            Some(handler) => handler.parse(&elements[1..]),
+            None => Err(unknown_command(&name, &elements[1..])),
+        }
+    }
+}
+
+impl Default for CommandRegistry {
+    fn default() -> Self {
+        Self::builtins()
+    }
+}
+
+impl fmt::Debug for CommandRegistry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.handlers.keys()).finish()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    use crate::commands::{CommandDocs, KeySpec};
+    use crate::config::Config;
+    use crate::error::SerirError;
+
+    static GETDEL: CommandSpec = CommandSpec {
+        name: "getdel",
+        arity: 2,
+        flags: &["write", "fast"],
+        categories: &["write", "string", "fast"],
+        key_specs: &[KeySpec {
+            flags: &["RW", "access", "delete"],
+            begin_index: 1,
+            last_key: 0,
+            key_step: 1,
+        }],
+        docs: CommandDocs {
+            summary: "Returns the string value of a key after deleting the key.",
+            since: "6.2.0",
+            group: "string",
+            complexity: "O(1)",
+        },
+        subcommands: &[],
+    };
+
+    struct GetDel;
+
+    impl CommandHandler for GetDel {
+        fn spec(&self) -> &'static CommandSpec {
+            &GETDEL
+        }
+
+        fn exec(&self, state: &ServerState, _: &Client, command: Command) -> SerirResult<Resp> {
+            let Command::Custom(command) = command else {
+                unreachable!()
+            };
+            let key = &command.arguments[0];
+            let value = state.store.get(key);
+            state.store.del(&[key]);
+            Ok(Resp::BulkString(value))
+        }
+    }
+
+    /// Replies to `GET` in uppercase.
+    struct Shout(Arc<dyn CommandHandler>);
+
+    impl CommandHandler for Shout {
+        fn spec(&self) -> &'static CommandSpec {
+            self.0.spec()
+        }
+
+        fn parse(&self, arguments: &[Resp]) -> SerirResult<Command> {
+            self.0.parse(arguments)
+        }
+
+        fn exec(
+            &self,
+            state: &ServerState,
+            client: &Client,
+            command: Command,
+        ) -> SerirResult<Resp> {
+            Ok(match self.0.exec(state, client, command)? {
+                Resp::BulkString(Some(value)) => {
+                    Resp::BulkString(Some(value.to_ascii_uppercase().into()))
+                }
+                reply => reply,
+            })
+        }
+    }
+
+    fn request(state: &ServerState, client: &Client, args: &[&str]) -> Vec<u8> {
+        let request = Resp::Array(Some(
+            args.iter()
+                .map(|arg| Resp::BulkString(Some(Bytes::copy_from_slice(arg.as_bytes()))))
+                .collect(),
+        ));
+        match state.parse_request(client, request) {
+            Ok(request) => state.exec_request(client, request).unwrap(),
+            Err(SerirError::CommandError(message)) => Resp::Error(message.into_bytes()),
+            Err(e) => panic!("{}", e),
+        }
+        .serialize()
+        .unwrap()
+    }
+
+    #[test]
+    fn adds_overrides_and_removes_commands() {
+        let mut commands = CommandRegistry::builtins();
+        assert!(commands.register(GetDel).is_none());
+        let get = commands.remove("GET").unwrap();
+        commands.register(Shout(get));
+        commands.remove("config");
+        let state = ServerState::with_commands(Config::default(), commands);
+        let client = Client::new(1, String::new(), String::new());
+
+        request(&state, &client, &["SET", "greeting", "hello"]);
+        assert_eq!(
+            request(&state, &client, &["GET", "greeting"]),
+            b"$5\r\nHELLO\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["GETDEL", "greeting"]),
+            b"$5\r\nhello\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["EXISTS", "greeting"]),
+            b":0\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["GETDEL"]),
+            b"-ERR wrong number of arguments for 'getdel' command\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["CONFIG", "GET", "port"]),
+            b"-ERR unknown command 'config', with args beginning with: 'GET' 'port' \r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["COMMAND", "GETKEYS", "GETDEL", "key"]),
+            b"*1\r\n$3\r\nkey\r\n".to_vec()
+        );
+        // GETDEL was added and CONFIG removed
+        assert_eq!(
+            request(&state, &client, &["COMMAND", "COUNT"]),
+            format!(":{}\r\n", COMMAND_TABLE.len()).into_bytes()
+        );
+    }
+
+    #[test]
+    fn applies_acl_rules_to_registered_commands() {
+        let mut commands = CommandRegistry::builtins();
+        commands.register(GetDel);
+        let state = ServerState::with_commands(Config::default(), commands);
+        assert!(state
+            .acl
+            .category_commands("string")
+            .contains(&"getdel".to_string()));
+
+        let client = Client::new(1, String::new(), String::new());
+        request(
+            &state,
+            &client,
+            &["ACL", "SETUSER", "reader", "on", "nopass", "~*", "+@read"],
+        );
+        request(&state, &client, &["AUTH", "reader", "anything"]);
+        assert_eq!(
+            request(&state, &client, &["GETDEL", "key"]),
+            b"-NOPERM User reader has no permissions to run the 'getdel' command\r\n".to_vec()
+        );
+    }
+}
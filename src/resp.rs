@@ -1,94 +1,343 @@
-use std::io::prelude::*;
-use std::io::BufReader;
+use std::collections::VecDeque;
+use std::io::IoSlice;
+use std::str::FromStr;
+
+use bytes::{Buf, Bytes, BytesMut};
 
 use crate::error::SerirError;
 use crate::error::SerirResult;
 
-#[derive(Debug)]
+/// Version of the protocol spoken with a client, switched with `HELLO`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Protocol {
+    #[default]
+    Resp2,
+    Resp3,
+}
+
+impl Protocol {
+    pub fn from_version(version: i64) -> Option<Self> {
+        match version {
+            2 => Some(Protocol::Resp2),
+            3 => Some(Protocol::Resp3),
+            _ => None,
+        }
+    }
+
+    pub fn version(&self) -> i64 {
+        match self {
+            Protocol::Resp2 => 2,
+            Protocol::Resp3 => 3,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum Resp {
     SimpleString(Vec<u8>),
     Integer(i64),
     Error(Vec<u8>),
-    BulkString(Option<Vec<u8>>),
+    BulkString(Option<Bytes>),
     Array(Option<Vec<Resp>>),
+    // RESP3 types, RESP2 clients get the closest RESP2 type instead
+    Null,
+    Boolean(bool),
+    Double(f64),
+    /// An integer too large for `Integer`, as its decimal digits.
+    BigNumber(String),
+    /// A string along with its three letter format, `txt` or `mkd`.
+    VerbatimString(String, Vec<u8>),
+    Map(Vec<(Resp, Resp)>),
+    Set(Vec<Resp>),
+    /// Auxiliary data about the reply that follows it, which RESP2 clients don't get.
+    Attribute(Vec<(Resp, Resp)>),
+    /// Out of band data, like pub/sub messages.
+    Push(Vec<Resp>),
 }
 
 impl Resp {
+    /// Parses every complete object in `buffer`, which is copied once so that the bulk
+    /// strings can share it.
     pub fn deserialize(buffer: &[u8]) -> SerirResult<Vec<Self>> {
-        let resps = Parser::new(buffer).parse()?;
+        let resps = Parser::new(Bytes::copy_from_slice(buffer)).parse()?;
         Ok(resps)
     }
 
+    /// Serializes the object for a RESP2 client.
     pub fn serialize(&self) -> SerirResult<Vec<u8>> {
-        let mut buffer = vec![];
+        self.serialize_for(Protocol::Resp2)
+    }
+
+    /// Serializes the object for a client speaking `protocol`, see [`Resp::write_to`].
+    pub fn serialize_for(&self, protocol: Protocol) -> SerirResult<Vec<u8>> {
+        let mut output = OutputBuffer::new();
+        self.write_to(&mut output, protocol);
+        Ok(output.to_vec())
+    }
 
+    /// Appends the object to `output` for a client speaking `protocol`. RESP3 types are
+    /// turned into their RESP2 counterparts for RESP2 clients, e.g. maps into flat arrays,
+    /// while nulls are sent as the RESP3 null to RESP3 clients.
+    pub fn write_to(&self, output: &mut OutputBuffer, protocol: Protocol) {
+        let resp3 = protocol == Protocol::Resp3;
         match self {
             Resp::BulkString(Some(val)) => {
-                buffer.write_all(b"$")?;
-                buffer
-                    .write_all(&format!("{}\r\n", val.len()).into_bytes())
-                    ?;
-                buffer.write_all(val)?;
-                buffer.write_all(b"\r\n")?;
+                output.put_header(b'$', val.len() as i64);
+                output.put_shared(val);
+                output.put(b"\r\n");
             }
-            Resp::BulkString(None) => buffer.write_all(b"$-1\r\n")?,
-            Resp::Integer(val) => {
-                buffer.write_all(b":")?;
-                buffer
-                    .write_all(&format!("{}\r\n", val).into_bytes())
-                    ?;
+            Resp::BulkString(None) | Resp::Null if !resp3 => output.put(b"$-1\r\n"),
+            Resp::Array(None) if !resp3 => output.put(b"*-1\r\n"),
+            Resp::BulkString(None) | Resp::Array(None) | Resp::Null => output.put(b"_\r\n"),
+            Resp::Integer(val) => output.put_header(b':', *val),
+            Resp::SimpleString(val) => put_line(output, b'+', val),
+            Resp::Error(val) => put_line(output, b'-', val),
+            Resp::Array(Some(val)) => put_aggregate(output, b'*', val, protocol),
+            Resp::Boolean(val) if resp3 => output.put(if *val { b"#t\r\n" } else { b"#f\r\n" }),
+            Resp::Boolean(val) => output.put(if *val { b":1\r\n" } else { b":0\r\n" }),
+            Resp::Double(val) if resp3 => put_line(output, b',', format_double(*val).as_bytes()),
+            Resp::Double(val) => put_blob(output, b'$', format_double(*val).as_bytes()),
+            Resp::BigNumber(val) if resp3 => put_line(output, b'(', val.as_bytes()),
+            Resp::BigNumber(val) => put_blob(output, b'$', val.as_bytes()),
+            Resp::VerbatimString(format, val) if resp3 => {
+                output.put_header(b'=', (format.len() + 1 + val.len()) as i64);
+                output.put(format.as_bytes());
+                output.put(b":");
+                output.put(val);
+                output.put(b"\r\n");
             }
-            Resp::SimpleString(val) => {
-                buffer.write_all(b"+")?;
-                buffer.write_all(val)?;
-                buffer.write_all(b"\r\n")?;
+            Resp::VerbatimString(_, val) => put_blob(output, b'$', val),
+            Resp::Map(pairs) if resp3 => put_pairs(output, b'%', pairs.len(), pairs, protocol),
+            // flattened into key, value, key, value...
+            Resp::Map(pairs) => put_pairs(output, b'*', pairs.len() * 2, pairs, protocol),
+            Resp::Attribute(pairs) if resp3 => {
+                put_pairs(output, b'|', pairs.len(), pairs, protocol)
             }
-            Resp::Error(val) => {
-                buffer.write_all(b"-")?;
-                buffer.write_all(val)?;
-                buffer.write_all(b"\r\n")?;
+            Resp::Attribute(_) => {}
+            Resp::Set(val) => put_aggregate(output, if resp3 { b'~' } else { b'*' }, val, protocol),
+            Resp::Push(val) => {
+                put_aggregate(output, if resp3 { b'>' } else { b'*' }, val, protocol)
             }
-            Resp::Array(Some(val)) => {
-                buffer.write_all(b"*")?;
-                buffer
-                    .write_all(&format!("{}\r\n", val.len()).into_bytes())?;
-                for item in val {
-                    buffer.write_all(&item.serialize()?)?;
-                }
+        }
+    }
+}
+
+fn put_line(output: &mut OutputBuffer, marker: u8, val: &[u8]) {
+    output.put(&[marker]);
+    output.put(val);
+    output.put(b"\r\n");
+}
+
+fn put_blob(output: &mut OutputBuffer, marker: u8, val: &[u8]) {
+    output.put_header(marker, val.len() as i64);
+    output.put(val);
+    output.put(b"\r\n");
+}
+
+fn put_aggregate(output: &mut OutputBuffer, marker: u8, items: &[Resp], protocol: Protocol) {
+    output.put_header(marker, items.len() as i64);
+    for item in items {
+        item.write_to(output, protocol);
+    }
+}
+
+fn put_pairs(
+    output: &mut OutputBuffer,
+    marker: u8,
+    len: usize,
+    pairs: &[(Resp, Resp)],
+    protocol: Protocol,
+) {
+    output.put_header(marker, len as i64);
+    for (key, value) in pairs {
+        key.write_to(output, protocol);
+        value.write_to(output, protocol);
+    }
+}
+
+/// Formats a double the way RESP3 spells them, with `inf`, `-inf` and `nan`.
+fn format_double(val: f64) -> String {
+    if val.is_nan() {
+        "nan".to_string()
+    } else if val.is_infinite() {
+        if val > 0.0 { "inf" } else { "-inf" }.to_string()
+    } else {
+        val.to_string()
+    }
+}
+
+/// Bulk strings at least this long are queued as they are by [`OutputBuffer`] rather than
+/// copied.
+const SHARED_WRITE_MIN_LEN: usize = 16 * 1024;
+
+/// Replies waiting to be written to a connection. Replies are encoded straight into it and
+/// small pieces are copied into a contiguous buffer, while large bulk strings are queued
+/// as the shared buffers they already are, so that everything goes out with one vectored
+/// write. It implements [`Buf`] to be handed to `write_all_buf`.
+#[derive(Debug, Default)]
+pub struct OutputBuffer {
+    /// Data queued before `pending`.
+    chunks: VecDeque<Bytes>,
+    pending: BytesMut,
+    len: usize,
+}
+
+impl OutputBuffer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Number of bytes waiting to be written.
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn put(&mut self, data: &[u8]) {
+        self.pending.extend_from_slice(data);
+        self.len += data.len();
+    }
+
+    /// Queues `data` without copying it if it is large.
+    pub fn put_shared(&mut self, data: &Bytes) {
+        if data.len() < SHARED_WRITE_MIN_LEN {
+            return self.put(data);
+        }
+        if !self.pending.is_empty() {
+            self.chunks.push_back(self.pending.split().freeze());
+        }
+        self.chunks.push_back(data.clone());
+        self.len += data.len();
+    }
+
+    /// Writes a type marker followed by a length or an integer and `\r\n`, without the
+    /// allocation `format!` would make.
+    fn put_header(&mut self, marker: u8, val: i64) {
+        let mut digits = [0; 20];
+        let mut start = digits.len();
+        let mut rest = val.unsigned_abs();
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (rest % 10) as u8;
+            rest /= 10;
+            if rest == 0 {
+                break;
             }
-            Resp::Array(None) => buffer.write_all(b"*-1\r\n")?,
         }
+        self.pending.reserve(24);
+        self.pending.extend_from_slice(&[marker]);
+        if val < 0 {
+            self.pending.extend_from_slice(b"-");
+        }
+        self.pending.extend_from_slice(&digits[start..]);
+        self.pending.extend_from_slice(b"\r\n");
+        self.len += 3 + (val < 0) as usize + digits.len() - start;
+    }
+
+    /// Copies what's waiting to be written.
+    pub fn to_vec(&self) -> Vec<u8> {
+        let mut data = Vec::with_capacity(self.len);
+        for chunk in &self.chunks {
+            data.extend_from_slice(chunk);
+        }
+        data.extend_from_slice(&self.pending);
+        data
+    }
+}
+
+impl Buf for OutputBuffer {
+    fn remaining(&self) -> usize {
+        self.len
+    }
+
+    fn chunk(&self) -> &[u8] {
+        match self.chunks.front() {
+            Some(chunk) => chunk,
+            None => &self.pending,
+        }
+    }
 
-        Ok(buffer)
+    fn chunks_vectored<'a>(&'a self, dst: &mut [IoSlice<'a>]) -> usize {
+        let chunks = self.chunks.iter().map(|chunk| &chunk[..]);
+        let all = chunks.chain(Some(&self.pending[..]).filter(|pending| !pending.is_empty()));
+        let mut filled = 0;
+        for (slot, chunk) in dst.iter_mut().zip(all) {
+            *slot = IoSlice::new(chunk);
+            filled += 1;
+        }
+        filled
+    }
+
+    fn advance(&mut self, mut cnt: usize) {
+        assert!(
+            cnt <= self.len,
+            "advanced past the end of the output buffer"
+        );
+        self.len -= cnt;
+        while let Some(chunk) = self.chunks.front_mut() {
+            if cnt < chunk.len() {
+                chunk.advance(cnt);
+                return;
+            }
+            cnt -= chunk.len();
+            self.chunks.pop_front();
+        }
+        self.pending.advance(cnt);
     }
 }
 
-pub struct Parser<'a> {
-    reader: BufReader<&'a [u8]>,
+/// Longest line accepted before its `\r\n`, like Redis' `PROTO_INLINE_MAX_SIZE`.
+const MAX_LINE_LEN: usize = 64 * 1024;
+/// Deepest nesting of arrays accepted, to bound the recursion of the parser.
+const MAX_DEPTH: usize = 128;
+
+/// Parses RESP objects from a buffer that may end in the middle of an object, like the
+/// data read so far from a connection. Bulk strings are slices of that buffer rather than
+/// copies.
+pub struct Parser {
+    buffer: Bytes,
+    pos: usize,
+    max_bulk_len: usize,
 }
 
-impl<'a> Parser<'a> {
-    pub fn new(buffer: &'a [u8]) -> Self {
+impl Parser {
+    pub fn new(buffer: Bytes) -> Self {
+        Self::with_max_bulk_len(buffer, usize::MAX)
+    }
+
+    /// A parser refusing bulk strings longer than `max_bulk_len` bytes, see
+    /// `proto-max-bulk-len`.
+    pub fn with_max_bulk_len(buffer: Bytes, max_bulk_len: usize) -> Self {
         Self {
-            reader: BufReader::new(buffer),
+            buffer,
+            pos: 0,
+            max_bulk_len,
         }
     }
 
+    /// Number of bytes taken by the objects parsed so far.
+    pub fn position(&self) -> usize {
+        self.pos
+    }
+
+    /// Gives back the part of the buffer that wasn't parsed.
+    pub fn into_remaining(mut self) -> Bytes {
+        self.buffer.advance(self.pos);
+        self.buffer
+    }
+
+    /// Parses the next object, or returns `None` if the buffer ends before it does. An
+    /// incomplete object isn't consumed, so parsing can resume once more data arrived.
     pub fn parse_single_resp_object(&mut self) -> SerirResult<Option<Resp>> {
-        let mut buf = [0u8; 1];
-        if self.reader.read_exact(&mut buf).is_err() {
-            return Ok(None);
-        };
-        let type_byte = buf[0];
-        let resp = match type_byte {
-            b'*' => self.parse_array()?,
-            b'$' => self.parse_bulk_string()?,
-            b':' => self.parse_integer()?,
-            b'+' => self.parse_simple_string()?,
-            b'-' => self.parse_error()?,
-            _ => panic!("Unsupported byte type: {}", type_byte as char),
-        };
-        Ok(Some(resp))
+        let start = self.pos;
+        let resp = self.parse_object(0)?;
+        if resp.is_none() {
+            self.pos = start;
+        }
+        Ok(resp)
     }
 
     pub fn parse(&mut self) -> SerirResult<Vec<Resp>> {
@@ -103,62 +352,332 @@ impl<'a> Parser<'a> {
         Ok(resps)
     }
 
-    fn parse_len(&mut self) -> SerirResult<isize> {
-        let mut line = String::new();
-        self.reader.read_line(&mut line)?;
-        let len = line.trim_end().parse::<isize>()?;
-        Ok(len)
+    fn parse_object(&mut self, depth: usize) -> SerirResult<Option<Resp>> {
+        // loops over empty inline lines rather than recursing, so that a flood of them
+        // can't overflow the stack
+        loop {
+            let type_byte = match self.buffer.get(self.pos) {
+                Some(&type_byte) => type_byte,
+                None => return Ok(None),
+            };
+            self.pos += 1;
+            return match type_byte {
+                b'*' => self.parse_array(depth),
+                b'$' => self.parse_bulk_string(),
+                b':' => self.parse_integer(),
+                b'+' => Ok(self
+                    .read_line()?
+                    .map(|line| Resp::SimpleString(line.to_vec()))),
+                b'-' => Ok(self.read_line()?.map(|line| Resp::Error(line.to_vec()))),
+                b'_' => self.parse_null(),
+                b'#' => self.parse_boolean(),
+                b',' => self.parse_double(),
+                b'(' => self.parse_big_number(),
+                b'=' => self.parse_verbatim_string(),
+                b'%' => Ok(self
+                    .parse_aggregate(depth, 2)?
+                    .map(|values| Resp::Map(pairs(values)))),
+                b'|' => Ok(self
+                    .parse_aggregate(depth, 2)?
+                    .map(|values| Resp::Attribute(pairs(values)))),
+                b'~' => Ok(self.parse_aggregate(depth, 1)?.map(Resp::Set)),
+                b'>' => Ok(self.parse_aggregate(depth, 1)?.map(Resp::Push)),
+                // what people type into telnet or netcat
+                _ if depth == 0 => {
+                    self.pos -= 1;
+                    match self.parse_inline()? {
+                        Some(arguments) if arguments.is_empty() => continue,
+                        arguments => Ok(arguments.map(|arguments| {
+                            Resp::Array(Some(
+                                arguments
+                                    .into_iter()
+                                    .map(|argument| Resp::BulkString(Some(argument.into())))
+                                    .collect(),
+                            ))
+                        })),
+                    }
+                }
+                _ => Err(SerirError::RespParseError(format!(
+                    "expected a type byte, got '{}'",
+                    type_byte.escape_ascii()
+                ))),
+            };
+        }
+    }
+
+    /// Reads an inline command, a line of space separated arguments that may be quoted
+    /// like in `redis-cli`. Empty lines have no arguments.
+    fn parse_inline(&mut self) -> SerirResult<Option<Vec<Vec<u8>>>> {
+        let rest = &self.buffer[self.pos..];
+        let len = match rest.iter().position(|&byte| byte == b'\n') {
+            Some(len) if len <= MAX_LINE_LEN => len,
+            None if rest.len() <= MAX_LINE_LEN => return Ok(None),
+            _ => {
+                return Err(SerirError::RespParseError(
+                    "too big inline request".to_string(),
+                ))
+            }
+        };
+        self.pos += len + 1;
+        let line = rest[..len].strip_suffix(b"\r").unwrap_or(&rest[..len]);
+        let arguments = split_arguments(line).ok_or_else(|| {
+            SerirError::RespParseError("unbalanced quotes in request".to_string())
+        })?;
+        Ok(Some(arguments))
     }
 
-    fn parse_array(&mut self) -> SerirResult<Resp> {
-        let len = self.parse_len()?;
-        let values: SerirResult<Vec<Resp>> = (0..len)
-            .map(|_| -> SerirResult<Resp> {
-                let parsed = self.parse_single_resp_object()?;
-                parsed.ok_or_else(|| SerirError::RespParseError(String::from("Corrupted array.")))
-            })
-            .collect();
-        Ok(Resp::Array(Some(values?)))
+    /// Reads up to the next `\r\n`, returning the line without it.
+    fn read_line(&mut self) -> SerirResult<Option<&[u8]>> {
+        let rest = &self.buffer[self.pos..];
+        match rest.windows(2).position(|window| window == b"\r\n") {
+            Some(len) if len <= MAX_LINE_LEN => {
+                self.pos += len + 2;
+                Ok(Some(&rest[..len]))
+            }
+            None if rest.len() <= MAX_LINE_LEN => Ok(None),
+            _ => Err(SerirError::RespParseError("too big line".to_string())),
+        }
     }
 
-    fn parse_bulk_string(&mut self) -> SerirResult<Resp> {
-        let len = self.parse_len()?;
+    /// Reads a line holding a number, `what` names it in errors.
+    fn parse_number<T: FromStr>(&mut self, what: &str) -> SerirResult<Option<T>> {
+        let line = match self.read_line()? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        std::str::from_utf8(line)
+            .ok()
+            .and_then(|line| line.parse().ok())
+            .map(Some)
+            .ok_or_else(|| SerirError::RespParseError(format!("invalid {}", what)))
+    }
+
+    /// Reads `len` bytes followed by `\r\n`.
+    fn read_blob(&mut self, len: usize) -> SerirResult<Option<Bytes>> {
+        let rest = &self.buffer[self.pos..];
+        if rest.len().saturating_sub(2) < len {
+            return Ok(None);
+        }
+        if &rest[len..len + 2] != b"\r\n" {
+            return Err(SerirError::RespParseError(
+                "expected '\\r\\n' after bulk string".to_string(),
+            ));
+        }
+        let blob = self.buffer.slice(self.pos..self.pos + len);
+        self.pos += len + 2;
+        Ok(Some(blob))
+    }
+
+    /// Reads the length of a blob, negative for nulls.
+    fn parse_blob_len(&mut self) -> SerirResult<Option<i64>> {
+        let len = match self.parse_number::<i64>("bulk length")? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        if len > 0 && len as u64 > self.max_bulk_len as u64 {
+            return Err(SerirError::RespParseError(
+                "invalid bulk length".to_string(),
+            ));
+        }
+        Ok(Some(len))
+    }
+
+    /// Reads the length of an aggregate, negative for nulls.
+    fn parse_aggregate_len(&mut self, depth: usize) -> SerirResult<Option<i64>> {
+        if depth >= MAX_DEPTH {
+            return Err(SerirError::RespParseError(
+                "too deeply nested arrays".to_string(),
+            ));
+        }
+        self.parse_number("multibulk length")
+    }
+
+    /// Reads `len` elements of an aggregate.
+    fn parse_elements(&mut self, len: usize, depth: usize) -> SerirResult<Option<Vec<Resp>>> {
+        // the length is untrusted, the vector grows as the elements actually arrive
+        let mut values = Vec::with_capacity(len.min(1024));
+        for _ in 0..len {
+            match self.parse_object(depth + 1)? {
+                Some(value) => values.push(value),
+                None => return Ok(None),
+            }
+        }
+        Ok(Some(values))
+    }
+
+    fn parse_array(&mut self, depth: usize) -> SerirResult<Option<Resp>> {
+        let len = match self.parse_aggregate_len(depth)? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
         if len < 0 {
-            return Ok(Resp::BulkString(None));
+            return Ok(Some(Resp::Array(None)));
         }
+        Ok(self
+            .parse_elements(len as usize, depth)?
+            .map(|values| Resp::Array(Some(values))))
+    }
 
-        let len = len as usize;
-        let mut buf = vec![0u8; (len + 2) as usize];
-        self.reader.read_exact(&mut buf)?;
-        if buf.split_off(len) != b"\r\n" {
-            // TODO: should all be changed to byte-by-byte parsing anyway
-            todo!();
+    /// Reads a RESP3 aggregate, whose entries are made of `entry_len` elements.
+    fn parse_aggregate(
+        &mut self,
+        depth: usize,
+        entry_len: usize,
+    ) -> SerirResult<Option<Vec<Resp>>> {
+        let len = match self.parse_aggregate_len(depth)? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        let len = usize::try_from(len)
+            .ok()
+            .and_then(|len| len.checked_mul(entry_len))
+            .ok_or_else(|| SerirError::RespParseError("invalid multibulk length".to_string()))?;
+        self.parse_elements(len, depth)
+    }
+
+    fn parse_bulk_string(&mut self) -> SerirResult<Option<Resp>> {
+        let len = match self.parse_blob_len()? {
+            Some(len) => len,
+            None => return Ok(None),
+        };
+        if len < 0 {
+            return Ok(Some(Resp::BulkString(None)));
         }
-        Ok(Resp::BulkString(Some(buf)))
+        Ok(self
+            .read_blob(len as usize)?
+            .map(|blob| Resp::BulkString(Some(blob))))
     }
 
-    fn parse_integer(&mut self) -> SerirResult<Resp> {
-        let mut line = String::new();
-        self.reader.read_line(&mut line)?;
-        let parsed_int = line.trim_end().parse::<i64>()?;
-        Ok(Resp::Integer(parsed_int))
+    fn parse_verbatim_string(&mut self) -> SerirResult<Option<Resp>> {
+        let invalid = || SerirError::RespParseError("invalid verbatim string".to_string());
+        let len = match self.parse_blob_len()? {
+            Some(len) => usize::try_from(len).map_err(|_| invalid())?,
+            None => return Ok(None),
+        };
+        match self.read_blob(len)? {
+            Some(blob) if blob.len() >= 4 && blob[3] == b':' => Ok(Some(Resp::VerbatimString(
+                String::from_utf8_lossy(&blob[..3]).to_string(),
+                blob[4..].to_vec(),
+            ))),
+            Some(_) => Err(invalid()),
+            None => Ok(None),
+        }
     }
 
-    fn parse_simple_string(&mut self) -> SerirResult<Resp> {
-        let mut line = String::new();
-        self.reader.read_line(&mut line)?;
-        let simple_string = line.trim_end().as_bytes().to_vec();
-        Ok(Resp::SimpleString(simple_string))
+    fn parse_integer(&mut self) -> SerirResult<Option<Resp>> {
+        Ok(self.parse_number("integer")?.map(Resp::Integer))
     }
 
-    fn parse_error(&mut self) -> SerirResult<Resp> {
-        let mut line = String::new();
-        self.reader.read_line(&mut line)?;
-        let parsed_error = line.trim_end().as_bytes().to_vec();
-        Ok(Resp::Error(parsed_error))
+    fn parse_null(&mut self) -> SerirResult<Option<Resp>> {
+        match self.read_line()? {
+            Some(b"") => Ok(Some(Resp::Null)),
+            Some(_) => Err(SerirError::RespParseError("invalid null".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn parse_boolean(&mut self) -> SerirResult<Option<Resp>> {
+        match self.read_line()? {
+            Some(b"t") => Ok(Some(Resp::Boolean(true))),
+            Some(b"f") => Ok(Some(Resp::Boolean(false))),
+            Some(_) => Err(SerirError::RespParseError("invalid boolean".to_string())),
+            None => Ok(None),
+        }
+    }
+
+    fn parse_double(&mut self) -> SerirResult<Option<Resp>> {
+        Ok(self.parse_number("double")?.map(Resp::Double))
+    }
+
+    fn parse_big_number(&mut self) -> SerirResult<Option<Resp>> {
+        let line = match self.read_line()? {
+            Some(line) => line,
+            None => return Ok(None),
+        };
+        let digits = line.strip_prefix(b"-").unwrap_or(line);
+        if digits.is_empty() || !digits.iter().all(u8::is_ascii_digit) {
+            return Err(SerirError::RespParseError("invalid big number".to_string()));
+        }
+        Ok(Some(Resp::BigNumber(
+            String::from_utf8_lossy(line).to_string(),
+        )))
     }
 }
 
+/// Splits an inline command into its arguments like Redis' `sdssplitargs`: arguments are
+/// separated by whitespace and may be quoted. Double quoted ones understand the `\n`,
+/// `\r`, `\t`, `\b`, `\a` and `\xHH` escapes, single quoted ones only `\'`. Returns
+/// `None` if quotes are unbalanced or a closing quote isn't followed by a space.
+fn split_arguments(line: &[u8]) -> Option<Vec<Vec<u8>>> {
+    let mut arguments = vec![];
+    let mut i = 0;
+    loop {
+        while line.get(i).is_some_and(u8::is_ascii_whitespace) {
+            i += 1;
+        }
+        if i >= line.len() {
+            return Some(arguments);
+        }
+        let mut argument = vec![];
+        let mut quote = None;
+        loop {
+            let byte = line.get(i).copied();
+            i += 1;
+            match (quote, byte) {
+                // the line ended before the closing quote
+                (Some(_), None) => return None,
+                (None, None) => break,
+                (None, Some(byte)) if byte.is_ascii_whitespace() => break,
+                (None, Some(byte @ (b'"' | b'\''))) => quote = Some(byte),
+                (None, Some(byte)) => argument.push(byte),
+                (Some(b'"'), Some(b'\\')) => {
+                    let hex = line
+                        .get(i + 1..i + 3)
+                        .and_then(|digits| std::str::from_utf8(digits).ok())
+                        .and_then(|digits| u8::from_str_radix(digits, 16).ok());
+                    match (line.get(i), hex) {
+                        (Some(b'x'), Some(hex)) => {
+                            argument.push(hex);
+                            i += 2;
+                        }
+                        (Some(b'n'), _) => argument.push(b'\n'),
+                        (Some(b'r'), _) => argument.push(b'\r'),
+                        (Some(b't'), _) => argument.push(b'\t'),
+                        (Some(b'b'), _) => argument.push(0x08),
+                        (Some(b'a'), _) => argument.push(0x07),
+                        (Some(&byte), _) => argument.push(byte),
+                        (None, _) => return None,
+                    }
+                    i += 1;
+                }
+                (Some(b'\''), Some(b'\\')) if line.get(i) == Some(&b'\'') => {
+                    argument.push(b'\'');
+                    i += 1;
+                }
+                (Some(open), Some(byte)) if byte == open => {
+                    // closing quotes must end the argument
+                    if line.get(i).is_some_and(|byte| !byte.is_ascii_whitespace()) {
+                        return None;
+                    }
+                    break;
+                }
+                (Some(_), Some(byte)) => argument.push(byte),
+            }
+        }
+        arguments.push(argument);
+    }
+}
+
+/// Pairs up the keys and values of a map, flattened as they are on the wire.
+fn pairs(values: Vec<Resp>) -> Vec<(Resp, Resp)> {
+    let mut values = values.into_iter();
+    let mut pairs = Vec::with_capacity(values.len() / 2);
+    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+        pairs.push((key, value));
+    }
+    pairs
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -254,4 +773,174 @@ mod tests {
         let result = Resp::deserialize(&b"*1\r\n$1\r\nA\r\n*1\r\n$1\r\nB\r\n"[..]);
         assert_eq!(result.unwrap().len(), 2);
     }
+
+    #[test]
+    fn waits_for_incomplete_objects() {
+        let input = b"*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n";
+        for end in 0..input.len() {
+            let mut parser = Parser::new(Bytes::copy_from_slice(&input[..end]));
+            assert!(parser.parse_single_resp_object().unwrap().is_none());
+            assert_eq!(parser.position(), 0);
+        }
+        let mut parser = Parser::new(Bytes::from_static(input));
+        assert!(parser.parse_single_resp_object().unwrap().is_some());
+        assert_eq!(parser.position(), input.len());
+    }
+
+    #[test]
+    fn refuses_bulk_strings_over_the_limit() {
+        let mut parser = Parser::with_max_bulk_len(Bytes::from_static(b"$10\r\n"), 5);
+        assert!(matches!(
+            parser.parse_single_resp_object(),
+            Err(SerirError::RespParseError(msg)) if msg == "invalid bulk length"
+        ));
+        let mut parser = Parser::with_max_bulk_len(Bytes::from_static(b"$5\r\nSerir\r\n"), 5);
+        assert!(parser.parse_single_resp_object().unwrap().is_some());
+    }
+
+    #[test]
+    fn refuses_malformed_input() {
+        let mut long_line = b"*".to_vec();
+        long_line.resize(MAX_LINE_LEN + 2, b'9');
+        for input in [
+            b"*x\r\n".to_vec(),
+            b"$3\r\nabcd\r\n".to_vec(),
+            b"*1\r\nGET\r\n".to_vec(),
+            long_line,
+            b"*1\r\n".repeat(MAX_DEPTH + 1),
+        ] {
+            assert!(matches!(
+                Parser::new(Bytes::copy_from_slice(&input)).parse_single_resp_object(),
+                Err(SerirError::RespParseError(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn round_trips_resp3_types() {
+        let bulk = |value: &str| Resp::BulkString(Some(Bytes::copy_from_slice(value.as_bytes())));
+        let reply = Resp::Array(Some(vec![
+            Resp::Null,
+            Resp::Boolean(true),
+            Resp::Double(1.5),
+            Resp::Double(f64::NEG_INFINITY),
+            Resp::BigNumber("3492890328409238509324850943850943825024385".to_string()),
+            Resp::VerbatimString("txt".to_string(), b"Some string".to_vec()),
+            Resp::Map(vec![(bulk("key"), Resp::Integer(1))]),
+            Resp::Set(vec![bulk("member")]),
+            Resp::Attribute(vec![(bulk("ttl"), Resp::Integer(3600))]),
+            Resp::Push(vec![bulk("message"), bulk("channel"), bulk("hello")]),
+        ]));
+        let serialized = reply.serialize_for(Protocol::Resp3).unwrap();
+        assert_eq!(
+            serialized,
+            b"*10\r\n_\r\n#t\r\n,1.5\r\n,-inf\r\n\
+              (3492890328409238509324850943850943825024385\r\n=15\r\ntxt:Some string\r\n\
+              %1\r\n$3\r\nkey\r\n:1\r\n~1\r\n$6\r\nmember\r\n|1\r\n$3\r\nttl\r\n:3600\r\n\
+              >3\r\n$7\r\nmessage\r\n$7\r\nchannel\r\n$5\r\nhello\r\n"
+                .to_vec()
+        );
+        assert_eq!(Resp::deserialize(&serialized).unwrap(), vec![reply]);
+    }
+
+    #[test]
+    fn downgrades_resp3_types_for_resp2_clients() {
+        let bulk = |value: &str| Resp::BulkString(Some(Bytes::copy_from_slice(value.as_bytes())));
+        let reply = Resp::Array(Some(vec![
+            Resp::Null,
+            Resp::Boolean(false),
+            Resp::Double(0.25),
+            Resp::VerbatimString("txt".to_string(), b"text".to_vec()),
+            Resp::Map(vec![(bulk("key"), Resp::Integer(1))]),
+            Resp::Attribute(vec![(bulk("ttl"), Resp::Integer(3600))]),
+            Resp::Push(vec![bulk("message")]),
+        ]));
+        assert_eq!(
+            reply.serialize().unwrap(),
+            b"*7\r\n$-1\r\n:0\r\n$4\r\n0.25\r\n$4\r\ntext\r\n*2\r\n$3\r\nkey\r\n:1\r\n\
+              *1\r\n$7\r\nmessage\r\n"
+                .to_vec()
+        );
+        assert_eq!(
+            Resp::BulkString(None)
+                .serialize_for(Protocol::Resp3)
+                .unwrap(),
+            b"_\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn parses_inline_commands_like_multibulk_ones() {
+        let inline = Resp::deserialize(b"\r\nSET key  \"hello world\"\r\nGET key\n").unwrap();
+        let multibulk = Resp::deserialize(
+            b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$11\r\nhello world\r\n*2\r\n$3\r\nGET\r\n$3\r\nkey\r\n",
+        )
+        .unwrap();
+        assert_eq!(inline, multibulk);
+
+        let mut parser = Parser::new(Bytes::from_static(b"GET ke"));
+        assert!(parser.parse_single_resp_object().unwrap().is_none());
+        assert_eq!(parser.position(), 0);
+    }
+
+    #[test]
+    fn skips_floods_of_empty_inline_lines() {
+        let mut request = b"\r\n".repeat(50_000);
+        request.extend_from_slice(&b"\n".repeat(50_000));
+        request.extend_from_slice(b"GET key\r\n");
+        let mut parser = Parser::new(request.into());
+        assert_eq!(
+            parser.parse_single_resp_object().unwrap(),
+            Some(Resp::Array(Some(vec![
+                Resp::BulkString(Some(Bytes::from_static(b"GET"))),
+                Resp::BulkString(Some(Bytes::from_static(b"key"))),
+            ])))
+        );
+    }
+
+    #[test]
+    fn splits_quoted_inline_arguments() {
+        let split = |line: &[u8]| split_arguments(line);
+        assert_eq!(
+            split(br#"set "a\tb\x41\xZZ" 'it\'s' x"y z" """#),
+            Some(vec![
+                b"set".to_vec(),
+                b"a\tbAxZZ".to_vec(),
+                b"it's".to_vec(),
+                b"xy z".to_vec(),
+                b"".to_vec(),
+            ])
+        );
+        assert_eq!(split(b"  "), Some(vec![]));
+        for unbalanced in [&b"get \"key"[..], b"get 'key", b"get \"key\"x"] {
+            assert_eq!(split(unbalanced), None);
+        }
+        assert!(matches!(
+            Parser::new(Bytes::from_static(b"get \"key\r\n")).parse_single_resp_object(),
+            Err(SerirError::RespParseError(msg)) if msg == "unbalanced quotes in request"
+        ));
+    }
+
+    #[test]
+    fn output_buffer_shares_large_values() {
+        let large = Bytes::from(vec![b'x'; SHARED_WRITE_MIN_LEN]);
+        let mut output = OutputBuffer::new();
+        Resp::Integer(-42).write_to(&mut output, Protocol::Resp2);
+        Resp::BulkString(Some(large.clone())).write_to(&mut output, Protocol::Resp2);
+        Resp::BulkString(Some(Bytes::from_static(b"small"))).write_to(&mut output, Protocol::Resp2);
+
+        let mut slices = [IoSlice::new(&[]); 4];
+        assert_eq!(output.chunks_vectored(&mut slices), 3);
+        assert_eq!(&slices[0][..], b":-42\r\n$16384\r\n");
+        assert_eq!(slices[1].as_ptr(), large.as_ptr());
+        assert_eq!(&slices[2][..], b"\r\n$5\r\nsmall\r\n");
+
+        let expected = [&b":-42\r\n$16384\r\n"[..], &large, b"\r\n$5\r\nsmall\r\n"].concat();
+        assert_eq!(output.to_vec(), expected);
+        output.advance(20);
+        assert_eq!(output.len(), expected.len() - 20);
+        assert_eq!(output.chunk(), &large[20 - 14..]);
+        output.advance(output.remaining());
+        assert!(output.is_empty());
+    }
 }
@@ -1,85 +1,637 @@
-use std::sync::{Arc, Mutex};
+use std::future::Future;
+use std::mem;
+use std::net::IpAddr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use bytes::BytesMut;
+use socket2::{SockRef, TcpKeepalive};
+
+use tokio::io::{self, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
 use tokio::net::{TcpListener, TcpStream};
-use tokio::sync::{
-    mpsc::{self, Sender},
-    oneshot,
-};
-
-use crate::commands::Command;
-use crate::error::SerirResult;
-use crate::resp::Resp;
-use crate::store::KeyValueStore;
-
-pub struct Server {
-    store: Arc<Mutex<KeyValueStore>>,
-    listener: TcpListener,
+#[cfg(unix)]
+use tokio::net::{UnixListener, UnixStream};
+use tokio::select;
+use tokio::time;
+
+use crate::client::Client;
+use crate::error::{SerirError, SerirResult};
+use crate::resp::{OutputBuffer, Parser, Resp};
+use crate::state::ServerState;
+
+/// The error sent to remote clients refused by protected mode.
+const PROTECTED_MODE_ERROR: &[u8] = b"-DENIED serir is running in protected mode because \
+protected mode is enabled and no password is set for the default user. In this mode \
+connections are only accepted from the loopback interface. If you want to connect from \
+external computers to serir you may adopt one of the following solutions: 1) Just disable \
+protected mode sending the command 'CONFIG SET protected-mode no' from the loopback \
+interface by connecting to serir from the same host the server is running, however MAKE \
+SURE serir is not publicly accessible from internet if you do so. Use CONFIG REWRITE to \
+make this change permanent. 2) Alternatively you can just disable the protected mode by \
+editing the serir configuration file, and setting the protected mode option to 'no', and \
+then restarting the server. 3) If you started the server manually just for testing, \
+restart it with the '--protected-mode no' option. 4) Set up an authentication password \
+for the default user. NOTE: You only need to do one of the above things in order for the \
+server to start accepting connections from the outside.\r\n";
+
+/// Bytes of query buffer made available to each read from a connection.
+const READ_CHUNK: usize = 16 * 1024;
+
+/// Time given to clients to complete the TLS handshake when idle clients aren't timed
+/// out.
+const TLS_HANDSHAKE_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// An accepted connection.
+pub struct Connection<S> {
+    pub stream: S,
+    /// Peer and local addresses, as shown by `CLIENT LIST`.
+    pub addr: String,
+    pub laddr: String,
+    /// Whether the peer is on the loopback interface or the Unix socket, which protected
+    /// mode always lets in.
+    pub local: bool,
+}
+
+/// A source of connections for a [`Server`].
+pub trait Listener: Send + Sync + 'static {
+    type Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static;
+
+    fn accept(&self) -> impl Future<Output = io::Result<Connection<Self::Stream>>> + Send;
+
+    /// Enables keepalive probes every `interval` on connections that support them.
+    fn set_keepalive(_stream: &Self::Stream, _interval: Duration) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+impl Listener for TcpListener {
+    type Stream = TcpStream;
+
+    async fn accept(&self) -> io::Result<Connection<TcpStream>> {
+        let (stream, addr) = TcpListener::accept(self).await?;
+        let laddr = stream.local_addr()?;
+        Ok(Connection {
+            stream,
+            addr: addr.to_string(),
+            laddr: laddr.to_string(),
+            local: is_loopback(addr.ip()),
+        })
+    }
+
+    /// Like Redis, peers that don't answer 3 probes sent `interval` apart are considered
+    /// dead.
+    fn set_keepalive(stream: &TcpStream, interval: Duration) -> io::Result<()> {
+        let keepalive = TcpKeepalive::new().with_time(interval);
+        #[cfg(unix)]
+        let keepalive = keepalive
+            .with_interval((interval / 3).max(Duration::from_secs(1)))
+            .with_retries(3);
+        SockRef::from(stream).set_tcp_keepalive(&keepalive)
+    }
+}
+
+/// Whether `ip` is a loopback address, including IPv4 ones mapped to IPv6 by dual-stack
+/// sockets like `::ffff:127.0.0.1`.
+fn is_loopback(ip: IpAddr) -> bool {
+    ip.to_canonical().is_loopback()
+}
+
+/// Clients of a Unix socket have no address of their own, like Redis both addresses are
+/// the path of the socket followed by `:0`.
+#[cfg(unix)]
+impl Listener for UnixListener {
+    type Stream = UnixStream;
+
+    async fn accept(&self) -> io::Result<Connection<UnixStream>> {
+        let (stream, _) = UnixListener::accept(self).await?;
+        let path = self
+            .local_addr()?
+            .as_pathname()
+            .map(|path| path.display().to_string())
+            .unwrap_or_default();
+        let addr = format!("{}:0", path);
+        Ok(Connection {
+            stream,
+            addr: addr.clone(),
+            laddr: addr,
+            local: true,
+        })
+    }
 }
 
-#[derive(Debug)]
-pub struct Request {
-    command: Command,
-    response_tx: oneshot::Sender<Vec<u8>>,
+pub struct Server<L> {
+    state: Arc<ServerState>,
+    listener: L,
+    /// Whether connections start with a TLS handshake.
+    tls: bool,
 }
 
-async fn handle_client(commands_tx: Sender<Request>, mut socket: TcpStream) -> SerirResult<()> {
-    let mut buffer = vec![0; 1024];
+async fn handle_client<S: AsyncRead + AsyncWrite + Unpin>(
+    state: Arc<ServerState>,
+    client: Arc<Client>,
+    mut socket: S,
+) -> SerirResult<()> {
+    // requests can span several reads, what's left of them after parsing stays here
+    let mut query = BytesMut::new();
+    // replies are encoded straight into it, it keeps its capacity from a batch to the next
+    let mut output = OutputBuffer::new();
     loop {
-        let bytes_read = socket.read(&mut buffer).await?;
+        query.reserve(READ_CHUNK);
+        let bytes_read = select! {
+            bytes_read = socket.read_buf(&mut query) => bytes_read?,
+            // messages pushed while the client is idle go out right away
+            _ = client.pushed() => {
+                put_pushes(&client, &mut output);
+                state.stats.net_output(output.len());
+                socket.write_all_buf(&mut output).await?;
+                continue;
+            }
+            _ = client.killed() => return Ok(()),
+        };
         if bytes_read == 0 {
             return Ok(());
         }
-        let inputs = Resp::deserialize(&buffer[..bytes_read])?;
-        let mut response = vec![];
-        for input in inputs {
-            let command = Command::from(input);
-            let (response_tx, response_rx) = oneshot::channel();
-            let request = Request {
-                command,
-                response_tx,
+        state.stats.net_input(bytes_read);
+        let limits = state.clients.buffer_limits();
+        let output_limit = limits.output.normal;
+        // the requests parsed from it share the data read
+        let mut parser =
+            Parser::with_max_bulk_len(mem::take(&mut query).freeze(), limits.max_bulk_len);
+        // when the replies built so far went over the soft output limit
+        let mut soft_limit_since = None;
+        let mut protocol_error = None;
+        loop {
+            let input = match parser.parse_single_resp_object() {
+                Ok(Some(input)) => input,
+                Ok(None) => break,
+                Err(SerirError::RespParseError(msg)) => {
+                    protocol_error = Some(msg);
+                    break;
+                }
+                Err(e) => return Err(e),
+            };
+            let reply = match state.parse_request(&client, input) {
+                Ok(request) => {
+                    state.wait_if_paused(&request.command).await;
+                    state.exec_request(&client, request)?
+                }
+                Err(SerirError::CommandError(msg)) => Resp::Error(msg.into_bytes()),
+                Err(e) => return Err(e),
             };
-            commands_tx.send(request).await?;
-            let mut result = response_rx.await?;
-            response.append(&mut result);
+            if client.reply_allowed() {
+                // HELLO may just have switched the protocol, its reply is in the new one
+                reply.write_to(&mut output, client.protocol());
+            }
+            if output_limit.exceeds_hard(output.len())
+                || soft_limit_since
+                    .is_some_and(|since| Instant::now() >= output_limit.soft_deadline(since))
+            {
+                output_buffer_limit_reached(&state, &client);
+                return Ok(());
+            }
+            if soft_limit_since.is_none() && output_limit.exceeds_soft(output.len()) {
+                soft_limit_since = Some(Instant::now());
+            }
+            if client.is_monitor() || client.is_killed() {
+                break;
+            }
+        }
+        // without requests left pointing into it, the read buffer is reused as is
+        query = match parser.into_remaining().try_into_mut() {
+            Ok(remaining) => remaining,
+            Err(remaining) => BytesMut::from(&remaining[..]),
+        };
+
+        if let Some(msg) = protocol_error {
+            eprintln!(
+                "Protocol error ({}) from client: {}",
+                msg,
+                client.info_line().trim_end()
+            );
+            // like Redis, the replies to the requests before the bad one are still sent
+            output.put(format!("-ERR Protocol error: {}\r\n", msg).as_bytes());
+            state.stats.net_output(output.len());
+            socket.write_all_buf(&mut output).await?;
+            return Ok(());
+        }
+        if query.len() > limits.query_buffer {
+            eprintln!(
+                "Closing client that reached max query buffer length: {}",
+                client.info_line().trim_end()
+            );
+            state.stats.query_buffer_limit_reached();
+            return Ok(());
+        }
+
+        put_pushes(&client, &mut output);
+        // a client over the soft limit must take the replies before its time is up
+        let written = output.len();
+        let write = socket.write_all_buf(&mut output);
+        match soft_limit_since {
+            Some(since) => {
+                let deadline = time::Instant::from_std(output_limit.soft_deadline(since));
+                match time::timeout_at(deadline, write).await {
+                    Ok(written) => written?,
+                    Err(_) => {
+                        output_buffer_limit_reached(&state, &client);
+                        return Ok(());
+                    }
+                }
+            }
+            None => write.await?,
+        }
+        state.stats.net_output(written);
+        if client.is_killed() {
+            return Ok(());
+        }
+        if client.is_monitor() {
+            return monitor(&state, &client, socket).await;
         }
-        socket.write_all(&response).await?;
-        buffer = vec![0; 1024];
     }
 }
 
-impl Server {
-    pub fn new(store: Arc<Mutex<KeyValueStore>>, listener: TcpListener) -> Server {
-        Server { store, listener }
+/// Appends the messages pushed to `client`, like key invalidations, to its output.
+fn put_pushes(client: &Client, output: &mut OutputBuffer) {
+    for message in client.take_pushes() {
+        message.write_to(output, client.protocol());
     }
+}
 
-    pub async fn run(&self) -> SerirResult<()> {
-        // TODO: make the channel buffer size configurable
-        let (commands_tx, mut commands_rx) = mpsc::channel(10000);
-
-        let store = self.store.clone();
-        tokio::spawn(async move {
-            while let Some(request) = commands_rx.recv().await {
-                let Request {
-                    command,
-                    response_tx,
-                } = request;
-                let result = store.lock().unwrap().exec(command).unwrap();
-                response_tx.send(result).unwrap();
+fn output_buffer_limit_reached(state: &ServerState, client: &Client) {
+    eprintln!(
+        "Client {} closed for overcoming of output buffer limits.",
+        client.info_line().trim_end()
+    );
+    state.stats.output_buffer_limit_reached();
+}
+
+/// Streams the commands executed by every client to a connection that sent `MONITOR`,
+/// until it disconnects or falls too far behind.
+async fn monitor<S: AsyncRead + AsyncWrite + Unpin>(
+    state: &ServerState,
+    client: &Client,
+    socket: S,
+) -> SerirResult<()> {
+    let mut feed = state.monitors.subscribe();
+    let (mut reader, mut writer) = io::split(socket);
+    let mut buffer = [0; 1024];
+    loop {
+        select! {
+            line = feed.recv() => match line {
+                Some(line) => {
+                    writer.write_all(&line).await?;
+                    state.stats.net_output(line.len());
+                }
+                None => return Ok(()),
+            },
+            _ = client.killed() => return Ok(()),
+            // monitors can't run commands anymore, their input is only read to notice
+            // when they go away
+            bytes_read = reader.read(&mut buffer) => {
+                if bytes_read? == 0 {
+                    return Ok(());
+                }
             }
-        });
+        }
+    }
+}
+
+/// Registers a new connection and serves it until it closes.
+async fn serve<S: AsyncRead + AsyncWrite + Unpin>(
+    state: Arc<ServerState>,
+    connection: Connection<S>,
+) {
+    let mut socket = connection.stream;
+    // accepted while the listener was being stopped
+    if state.shutdown.is_requested() {
+        return;
+    }
+    if !connection.local && state.is_protected() {
+        let _ = socket.write_all(PROTECTED_MODE_ERROR).await;
+        return;
+    }
+    let client = match state
+        .clients
+        .try_register(connection.addr, connection.laddr)
+    {
+        Some(client) => client,
+        None => {
+            state.stats.connection_rejected();
+            let _ = socket
+                .write_all(b"-ERR max number of clients reached\r\n")
+                .await;
+            return;
+        }
+    };
+    state.stats.client_connected();
+    match handle_client(state.clone(), client.clone(), socket).await {
+        Ok(_) => {}
+        Err(e) => {
+            eprintln!("Error reading from stream: {}", e);
+        }
+    }
+    state.disable_tracking(&client);
+    state.clients.unregister(client.id);
+    state.stats.client_disconnected();
+}
+
+impl<L: Listener> Server<L> {
+    pub fn new(state: Arc<ServerState>, listener: L) -> Self {
+        Server {
+            state,
+            listener,
+            tls: false,
+        }
+    }
 
+    /// A server for the `tls-port` listener, using the certificates currently loaded in
+    /// `state.tls`.
+    pub fn with_tls(state: Arc<ServerState>, listener: L) -> Self {
+        Server {
+            state,
+            listener,
+            tls: true,
+        }
+    }
+
+    /// Accepts connections until a shutdown is requested.
+    pub async fn run(&self) -> SerirResult<()> {
         loop {
-            let (socket, _) = self.listener.accept().await?;
-            let commands_tx = commands_tx.clone();
+            let connection = select! {
+                connection = self.listener.accept() => connection?,
+                _ = self.state.shutdown.requested() => return Ok(()),
+            };
+            let state = self.state.clone();
+            if let Some(interval) = state.clients.tcp_keepalive() {
+                if let Err(e) = L::set_keepalive(&connection.stream, interval) {
+                    eprintln!("Error enabling keepalive for {}: {}", connection.addr, e);
+                }
+            }
+            if !self.tls {
+                tokio::spawn(serve(state, connection));
+                continue;
+            }
+            let acceptor = match state.tls.acceptor() {
+                Some(acceptor) => acceptor,
+                None => continue,
+            };
+            // a client stalling the handshake isn't registered yet, so neither `timeout`
+            // nor `maxclients` would get rid of it
+            let handshake_timeout = match state.config.read().unwrap().timeout {
+                0 => TLS_HANDSHAKE_TIMEOUT,
+                timeout => Duration::from_secs(timeout),
+            };
+            // the handshake runs on the connection's task so a slow client can't hold up
+            // the accept loop
             tokio::spawn(async move {
-                match handle_client(commands_tx, socket).await {
-                    Ok(_) => {}
-                    Err(e) => {
-                        eprintln!("Error reading from stream: {}", e);
+                let Connection {
+                    stream,
+                    addr,
+                    laddr,
+                    local,
+                } = connection;
+                let Ok(handshake) = time::timeout(handshake_timeout, acceptor.accept(stream)).await
+                else {
+                    eprintln!("TLS handshake with {} timed out", addr);
+                    return;
+                };
+                match handshake {
+                    Ok(stream) => {
+                        let connection = Connection {
+                            stream,
+                            addr,
+                            laddr,
+                            local,
+                        };
+                        serve(state, connection).await
                     }
+                    Err(e) => eprintln!("Error accepting a TLS connection from {}: {}", addr, e),
                 }
             });
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[cfg(unix)]
+    use std::env;
+
+    use crate::client::BufferLimits;
+    use crate::config::Config;
+
+    /// Serves a connection from a remote peer, returning what the server replies to `SET`.
+    async fn remote_set(config: Config) -> Vec<u8> {
+        set(Arc::new(ServerState::new(config)), false).await
+    }
+
+    async fn set(state: Arc<ServerState>, local: bool) -> Vec<u8> {
+        let (mut client, stream) = io::duplex(4096);
+        let connection = Connection {
+            stream,
+            addr: "10.0.0.2:5555".to_string(),
+            laddr: "10.0.0.1:6379".to_string(),
+            local,
+        };
+        tokio::spawn(serve(state, connection));
+        client
+            .write_all(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n")
+            .await
+            .unwrap();
+        let mut reply = vec![];
+        let mut buffer = [0; 4096];
+        // the server closes denied connections, others get a single short reply
+        while !reply.ends_with(b"\r\n") {
+            match client.read(&mut buffer).await.unwrap() {
+                0 => break,
+                n => reply.extend_from_slice(&buffer[..n]),
+            }
+        }
+        reply
+    }
+
+    #[test]
+    fn treats_mapped_ipv4_loopback_as_local() {
+        for ip in ["127.0.0.1", "::1", "::ffff:127.0.0.1", "::ffff:127.1.2.3"] {
+            assert!(is_loopback(ip.parse().unwrap()), "{}", ip);
+        }
+        for ip in ["10.0.0.2", "::ffff:10.0.0.2", "2001:db8::1"] {
+            assert!(!is_loopback(ip.parse().unwrap()), "{}", ip);
+        }
+    }
+
+    #[tokio::test]
+    async fn protected_mode_refuses_remote_clients_without_password() {
+        assert!(remote_set(Config::default()).await.starts_with(b"-DENIED "));
+
+        let unprotected = Config {
+            protected_mode: false,
+            ..Config::default()
+        };
+        assert_eq!(remote_set(unprotected).await, b"+OK\r\n".to_vec());
+
+        let with_password = Config {
+            requirepass: Some("secret".to_string()),
+            ..Config::default()
+        };
+        assert!(remote_set(with_password).await.starts_with(b"-NOAUTH "));
+    }
+
+    #[tokio::test]
+    async fn refuses_clients_past_maxclients() {
+        let config = Config {
+            maxclients: 1,
+            ..Config::default()
+        };
+        let state = Arc::new(ServerState::new(config));
+        state.clients.register(String::new(), String::new());
+        assert_eq!(
+            set(state.clone(), true).await,
+            b"-ERR max number of clients reached\r\n".to_vec()
+        );
+        assert_eq!(state.stats.rejected_connections(), 1);
+    }
+
+    /// Serves a local connection with `limits`, sends it `requests` and returns all it
+    /// replies until it closes or goes quiet.
+    async fn exchange(
+        state: Arc<ServerState>,
+        limits: BufferLimits,
+        requests: &[&[u8]],
+    ) -> Vec<u8> {
+        state.clients.set_buffer_limits(limits);
+        let (mut client, stream) = io::duplex(4096);
+        let connection = Connection {
+            stream,
+            addr: "127.0.0.1:5555".to_string(),
+            laddr: "127.0.0.1:6379".to_string(),
+            local: true,
+        };
+        tokio::spawn(serve(state, connection));
+        for request in requests {
+            client.write_all(request).await.unwrap();
+            tokio::task::yield_now().await;
+        }
+        let mut reply = vec![];
+        let mut buffer = [0; 4096];
+        while let Ok(Ok(n)) =
+            time::timeout(Duration::from_millis(200), client.read(&mut buffer)).await
+        {
+            if n == 0 {
+                break;
+            }
+            reply.extend_from_slice(&buffer[..n]);
+        }
+        reply
+    }
+
+    #[tokio::test]
+    async fn serves_requests_split_across_reads() {
+        let state = Arc::new(ServerState::new(Config::default()));
+        let reply = exchange(
+            state,
+            BufferLimits::default(),
+            &[
+                b"*3\r\n$3\r\nSET\r\n$1\r",
+                b"\nk\r\n$5\r\nva",
+                b"lue\r\n*2\r\n$3\r\nGET\r\n$1\r\nk\r\n",
+            ],
+        )
+        .await;
+        assert_eq!(reply, b"+OK\r\n$5\r\nvalue\r\n".to_vec());
+    }
+
+    #[tokio::test]
+    async fn disconnects_clients_over_buffer_limits() {
+        let state = Arc::new(ServerState::new(Config::default()));
+        let limits = BufferLimits {
+            query_buffer: 64,
+            ..BufferLimits::default()
+        };
+        let reply = exchange(state.clone(), limits, &[b"*1\r\n$1000\r\n", &[b'a'; 100]]).await;
+        assert!(reply.is_empty());
+        assert_eq!(state.stats.client_query_buffer_limit_disconnections(), 1);
+
+        let mut limits = BufferLimits::default();
+        limits.output.normal.hard = 16;
+        let reply = exchange(
+            state.clone(),
+            limits,
+            &[
+                b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n",
+                &b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n".repeat(4),
+            ],
+        )
+        .await;
+        assert_eq!(reply, b"+OK\r\n".to_vec());
+        assert_eq!(state.stats.client_output_buffer_limit_disconnections(), 1);
+
+        let limits = BufferLimits {
+            max_bulk_len: 10,
+            ..BufferLimits::default()
+        };
+        let reply = exchange(
+            state,
+            limits,
+            &[b"*2\r\n$3\r\nGET\r\n$1\r\nk\r\n*1\r\n$100\r\n"],
+        )
+        .await;
+        assert_eq!(
+            reply,
+            b"$1\r\nv\r\n-ERR Protocol error: invalid bulk length\r\n".to_vec()
+        );
+    }
+
+    #[tokio::test]
+    async fn drains_connections_on_shutdown() {
+        let state = Arc::new(ServerState::new(Config::default()));
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = Server::new(state.clone(), listener);
+        let accepting = tokio::spawn(async move { server.run().await });
+
+        let mut idle = TcpStream::connect(addr).await.unwrap();
+        idle.write_all(b"*3\r\n$3\r\nSET\r\n$1\r\nk\r\n$1\r\nv\r\n")
+            .await
+            .unwrap();
+        let mut reply = [0; 64];
+        assert_eq!(idle.read(&mut reply).await.unwrap(), 5);
+        let mut admin = TcpStream::connect(addr).await.unwrap();
+        admin
+            .write_all(b"*2\r\n$8\r\nSHUTDOWN\r\n$6\r\nNOSAVE\r\n")
+            .await
+            .unwrap();
+
+        let options = state.shutdown.requested().await;
+        assert_eq!(options.save, Some(false));
+        state.shut_down(options).await;
+        assert!(accepting.await.unwrap().is_ok());
+        assert!(state.clients.is_empty());
+        assert_eq!(idle.read(&mut reply).await.unwrap(), 0);
+        assert_eq!(admin.read(&mut reply).await.unwrap(), 0);
+        assert!(TcpStream::connect(addr).await.is_err());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn serves_clients_over_unix_sockets() {
+        let path = env::temp_dir().join(format!("serir-{}.sock", std::process::id()));
+        let _ = std::fs::remove_file(&path);
+        let state = Arc::new(ServerState::new(Config::default()));
+        let server = Server::new(state, UnixListener::bind(&path).unwrap());
+        tokio::spawn(async move { server.run().await });
+
+        let mut socket = UnixStream::connect(&path).await.unwrap();
+        socket
+            .write_all(b"*2\r\n$6\r\nCLIENT\r\n$4\r\nINFO\r\n")
+            .await
+            .unwrap();
+        let mut reply = vec![0; 1024];
+        let n = socket.read(&mut reply).await.unwrap();
+        let reply = String::from_utf8_lossy(&reply[..n]).to_string();
+        let addr = format!("{}:0", path.display());
+        assert!(reply.contains(&format!(" addr={} laddr={} ", addr, addr)));
+        std::fs::remove_file(&path).unwrap();
+    }
+}
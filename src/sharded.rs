@@ -0,0 +1,682 @@
+use std::collections::hash_map::DefaultHasher;
+use std::hash::{Hash, Hasher};
+use std::ops::{Deref, DerefMut};
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, MutexGuard, RwLock};
+use std::time::Duration;
+
+use bytes::Bytes;
+use rand::prelude::*;
+
+use crate::commands::Command;
+use crate::error::{SerirError, SerirResult};
+use crate::eviction::EvictionSettings;
+use crate::resp::Resp;
+use crate::store::{now_ms, KeyValueStore, Ttl};
+
+const SHARDS_PER_WORKER: usize = 4;
+/// Number of shards sampled when looking for the best key to evict.
+const EVICTION_SHARD_SAMPLES: usize = 4;
+/// Number of volatile keys sampled per shard by one active expire cycle.
+const ACTIVE_EXPIRE_SAMPLES: usize = 20;
+const OOM_ERROR: &[u8] = b"OOM command not allowed when used memory > 'maxmemory'.";
+
+/// Keyspace split into independently locked shards.
+///
+/// Every key is owned by exactly one shard, chosen by hashing the key. Single-key
+/// commands only lock the shard that owns the key, so commands touching different
+/// shards run in parallel. Multi-key commands lock every shard they touch in
+/// ascending index order, which keeps them atomic and free of lock-order deadlocks.
+///
+/// Applications can use a store directly, without a server, through its typed methods
+/// like [`ShardedStore::get`] and [`ShardedStore::set`]. Commands sent over the network
+/// are executed on top of them.
+#[derive(Debug)]
+pub struct ShardedStore {
+    shards: Vec<Mutex<KeyValueStore>>,
+    /// Sum of the memory used by all shards, kept up to date by [`ShardGuard`]s.
+    used_memory: AtomicUsize,
+    /// Memory limit in bytes, 0 for no limit.
+    maxmemory: AtomicU64,
+    settings: RwLock<EvictionSettings>,
+    /// How many subsystems, `CLIENT TRACKING` and modules subscribed to keyspace events,
+    /// need shards to record the keys that change.
+    track_modified: AtomicUsize,
+    /// Keys that changed on the store's own initiative, expired or evicted, waiting for
+    /// [`ShardedStore::take_modified`].
+    modified: Mutex<Vec<Vec<u8>>>,
+}
+
+/// Lock on a single shard which, once released, accounts the change of the shard's
+/// memory usage in the store-wide total.
+pub struct ShardGuard<'a> {
+    guard: MutexGuard<'a, KeyValueStore>,
+    used_memory_before: usize,
+    used_memory: &'a AtomicUsize,
+}
+
+impl Deref for ShardGuard<'_> {
+    type Target = KeyValueStore;
+
+    fn deref(&self) -> &Self::Target {
+        &self.guard
+    }
+}
+
+impl DerefMut for ShardGuard<'_> {
+    fn deref_mut(&mut self) -> &mut Self::Target {
+        &mut self.guard
+    }
+}
+
+impl Drop for ShardGuard<'_> {
+    fn drop(&mut self) {
+        let used_memory = self.guard.used_memory();
+        if used_memory > self.used_memory_before {
+            self.used_memory
+                .fetch_add(used_memory - self.used_memory_before, Ordering::Relaxed);
+        } else {
+            self.used_memory
+                .fetch_sub(self.used_memory_before - used_memory, Ordering::Relaxed);
+        }
+    }
+}
+
+impl ShardedStore {
+    pub fn new(num_shards: usize) -> Self {
+        assert!(num_shards > 0, "ShardedStore requires at least one shard");
+        Self {
+            shards: (0..num_shards)
+                .map(|_| Mutex::new(KeyValueStore::new()))
+                .collect(),
+            used_memory: AtomicUsize::new(0),
+            maxmemory: AtomicU64::new(0),
+            settings: RwLock::new(EvictionSettings::default()),
+            track_modified: AtomicUsize::new(0),
+            modified: Mutex::new(vec![]),
+        }
+    }
+
+    /// Creates a store with enough shards to keep `num_workers` threads busy without
+    /// them contending on the same lock too often.
+    pub fn for_workers(num_workers: usize) -> Self {
+        Self::new(num_workers.max(1) * SHARDS_PER_WORKER)
+    }
+
+    /// Applies a memory limit and eviction settings to all shards.
+    pub fn configure(&self, maxmemory: u64, settings: EvictionSettings) {
+        *self.settings.write().unwrap() = settings;
+        for index in 0..self.shards.len() {
+            self.lock(index).configure(settings);
+        }
+        self.maxmemory.store(maxmemory, Ordering::Relaxed);
+    }
+
+    pub fn num_shards(&self) -> usize {
+        self.shards.len()
+    }
+
+    pub fn shard_index(&self, key: &[u8]) -> usize {
+        let mut hasher = DefaultHasher::new();
+        key.hash(&mut hasher);
+        (hasher.finish() % self.shards.len() as u64) as usize
+    }
+
+    pub fn shard(&self, key: &[u8]) -> ShardGuard<'_> {
+        self.lock(self.shard_index(key))
+    }
+
+    fn lock(&self, index: usize) -> ShardGuard<'_> {
+        let mut guard = self.shards[index].lock().unwrap();
+        guard.set_track_modified(self.track_modified.load(Ordering::Relaxed) > 0);
+        ShardGuard {
+            used_memory_before: guard.used_memory(),
+            guard,
+            used_memory: &self.used_memory,
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.fold(0, |len, shard| len + shard.len())
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Approximate memory used by all keys and values, in bytes.
+    pub fn used_memory(&self) -> usize {
+        self.used_memory.load(Ordering::Relaxed)
+    }
+
+    /// Folds over all shards, locking one at a time.
+    pub fn fold<T>(&self, init: T, mut f: impl FnMut(T, &KeyValueStore) -> T) -> T {
+        self.shards
+            .iter()
+            .fold(init, |acc, shard| f(acc, &shard.lock().unwrap()))
+    }
+
+    pub fn reset_stats(&self) {
+        for index in 0..self.shards.len() {
+            self.lock(index).reset_stats();
+        }
+    }
+
+    /// Executes `command`. The keys it modifies, if shards record them, are left for
+    /// [`ShardedStore::take_modified`].
+    pub fn exec(&self, command: Command) -> SerirResult<Resp> {
+        let (reply, modified) = self.exec_tracked(command);
+        if !modified.is_empty() {
+            self.modified.lock().unwrap().extend(modified);
+        }
+        reply
+    }
+
+    /// Executes `command` and returns, along with its reply, the keys it modified if
+    /// shards record them. Keys evicted to make room for it are left for
+    /// [`ShardedStore::take_modified`].
+    pub fn exec_tracked(&self, command: Command) -> (SerirResult<Resp>, Vec<Vec<u8>>) {
+        if !self.free_memory_if_needed() && command.is_denyoom() {
+            return (Ok(Resp::Error(OOM_ERROR.to_vec())), vec![]);
+        }
+
+        let mut modified = vec![];
+        let reply = match command {
+            Command::MGet(keys) => Ok(Resp::Array(Some(
+                self.mget_tracked(&keys, &mut modified)
+                    .into_iter()
+                    .map(Resp::BulkString)
+                    .collect(),
+            ))),
+            Command::MSet(pairs) => {
+                self.mset_tracked(pairs, &mut modified);
+                Ok(Resp::SimpleString(b"OK".to_vec()))
+            }
+            Command::Del(keys) => Ok(Resp::Integer(self.del_tracked(&keys, &mut modified) as i64)),
+            Command::Exists(keys) => Ok(Resp::Integer(
+                self.exists_tracked(&keys, &mut modified) as i64
+            )),
+            command => {
+                let index = match command.keys().first() {
+                    Some(key) => self.shard_index(key),
+                    // keyless commands don't touch the data, any shard can answer them
+                    None => 0,
+                };
+                let mut shard = self.lock(index);
+                let reply = shard.exec(command);
+                modified.extend(shard.take_modified());
+                reply
+            }
+        };
+        (reply, modified)
+    }
+
+    /// Starts recording the keys that change, until a matching call to
+    /// [`ShardedStore::untrack_modified`].
+    pub fn track_modified(&self) {
+        self.track_modified.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Stops recording the keys that change once nothing needs them anymore.
+    pub fn untrack_modified(&self) {
+        if self.track_modified.fetch_sub(1, Ordering::Relaxed) == 1 {
+            self.modified.lock().unwrap().clear();
+        }
+    }
+
+    /// Keys that expired or got evicted since the last call, while recording them.
+    pub fn take_modified(&self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.modified.lock().unwrap())
+    }
+
+    /// Moves the keys `shard` recorded as modified to the ones waiting for
+    /// [`ShardedStore::take_modified`].
+    fn keep_modified(&self, shard: &mut KeyValueStore) {
+        let keys = shard.take_modified();
+        if !keys.is_empty() {
+            self.modified.lock().unwrap().extend(keys);
+        }
+    }
+
+    /// Evicts keys according to the eviction policy until memory usage drops below
+    /// `maxmemory`. Returns whether memory usage is within the limit.
+    ///
+    /// Eviction is approximated: every round samples a few keys from a few random shards
+    /// and evicts the best candidate among them.
+    pub fn free_memory_if_needed(&self) -> bool {
+        let maxmemory = self.maxmemory.load(Ordering::Relaxed);
+        if maxmemory == 0 {
+            return true;
+        }
+
+        let mut rng = thread_rng();
+        let mut order: Vec<usize> = (0..self.shards.len()).collect();
+        while self.used_memory() as u64 > maxmemory {
+            order.shuffle(&mut rng);
+            let best = order
+                .iter()
+                .filter_map(|&index| {
+                    let (score, key) = self.lock(index).eviction_candidate()?;
+                    Some((score, index, key))
+                })
+                .take(EVICTION_SHARD_SAMPLES)
+                .max_by_key(|(score, _, _)| *score);
+            match best {
+                Some((_, index, key)) => {
+                    let mut shard = self.lock(index);
+                    shard.evict(&key);
+                    self.keep_modified(&mut shard);
+                }
+                None => return false,
+            }
+        }
+        true
+    }
+
+    /// Deletes expired keys that clients haven't touched, sampling every shard. Shards
+    /// where many sampled keys turned out to be expired are sampled again, up to a few
+    /// times.
+    pub fn active_expire_cycle(&self) {
+        for index in 0..self.shards.len() {
+            for _ in 0..4 {
+                let mut shard = self.lock(index);
+                let (sampled, expired) = shard.active_expire(ACTIVE_EXPIRE_SAMPLES);
+                self.keep_modified(&mut shard);
+                drop(shard);
+                if sampled == 0 || expired * 4 < sampled {
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Locks every shard owning one of `keys`, in ascending shard order.
+    ///
+    /// Returns the guards together with a lookup table mapping a shard index to the
+    /// position of its guard.
+    fn lock_shards<'a, K: AsRef<[u8]> + 'a>(
+        &self,
+        keys: impl Iterator<Item = &'a K>,
+    ) -> (Vec<ShardGuard<'_>>, Vec<usize>) {
+        let mut indices: Vec<usize> = keys.map(|key| self.shard_index(key.as_ref())).collect();
+        indices.sort_unstable();
+        indices.dedup();
+
+        let mut positions = vec![usize::MAX; self.shards.len()];
+        let guards = indices
+            .iter()
+            .enumerate()
+            .map(|(position, &index)| {
+                positions[index] = position;
+                self.lock(index)
+            })
+            .collect();
+        (guards, positions)
+    }
+
+    fn mget_tracked<K: AsRef<[u8]>>(
+        &self,
+        keys: &[K],
+        modified: &mut Vec<Vec<u8>>,
+    ) -> Vec<Option<Bytes>> {
+        let (mut guards, positions) = self.lock_shards(keys.iter());
+        let values = keys
+            .iter()
+            .map(|key| {
+                let key = key.as_ref();
+                guards[positions[self.shard_index(key)]].get(key)
+            })
+            .collect();
+        // reading keys deletes the ones that expired
+        modified.extend(guards.iter_mut().flat_map(|shard| shard.take_modified()));
+        values
+    }
+
+    fn mset_tracked<K: AsRef<[u8]>>(&self, pairs: Vec<(K, Bytes)>, modified: &mut Vec<Vec<u8>>) {
+        let (mut guards, positions) = self.lock_shards(pairs.iter().map(|(key, _)| key));
+        for (key, value) in pairs {
+            let key = key.as_ref();
+            guards[positions[self.shard_index(key)]].set(key, value);
+        }
+        modified.extend(guards.iter_mut().flat_map(|shard| shard.take_modified()));
+    }
+
+    fn del_tracked<K: AsRef<[u8]>>(&self, keys: &[K], modified: &mut Vec<Vec<u8>>) -> usize {
+        let (mut guards, positions) = self.lock_shards(keys.iter());
+        let deleted = keys
+            .iter()
+            .filter(|key| {
+                let key = key.as_ref();
+                guards[positions[self.shard_index(key)]].del(key)
+            })
+            .count();
+        modified.extend(guards.iter_mut().flat_map(|shard| shard.take_modified()));
+        deleted
+    }
+
+    fn exists_tracked<K: AsRef<[u8]>>(&self, keys: &[K], modified: &mut Vec<Vec<u8>>) -> usize {
+        let (mut guards, positions) = self.lock_shards(keys.iter());
+        let existing = keys
+            .iter()
+            .filter(|key| {
+                let key = key.as_ref();
+                guards[positions[self.shard_index(key)]].exists(key)
+            })
+            .count();
+        modified.extend(guards.iter_mut().flat_map(|shard| shard.take_modified()));
+        existing
+    }
+
+    /// Runs `f` on the shard owning `key`. The keys it modifies are left for
+    /// [`ShardedStore::take_modified`].
+    fn with_shard<T>(&self, key: &[u8], f: impl FnOnce(&mut KeyValueStore) -> T) -> T {
+        let mut shard = self.shard(key);
+        let result = f(&mut shard);
+        self.keep_modified(&mut shard);
+        result
+    }
+
+    /// Runs `f` with a list to record modified keys in, which are then left for
+    /// [`ShardedStore::take_modified`].
+    fn with_modified<T>(&self, f: impl FnOnce(&mut Vec<Vec<u8>>) -> T) -> T {
+        let mut modified = vec![];
+        let result = f(&mut modified);
+        if !modified.is_empty() {
+            self.modified.lock().unwrap().extend(modified);
+        }
+        result
+    }
+
+    /// Evicts keys before a write if needed, refusing it when memory can't be freed.
+    fn check_memory(&self) -> SerirResult<()> {
+        match self.free_memory_if_needed() {
+            true => Ok(()),
+            false => Err(SerirError::CommandError(
+                String::from_utf8_lossy(OOM_ERROR).into_owned(),
+            )),
+        }
+    }
+
+    /// The value of `key`, if it exists.
+    pub fn get(&self, key: impl AsRef<[u8]>) -> Option<Bytes> {
+        let key = key.as_ref();
+        self.with_shard(key, |shard| shard.get(key))
+    }
+
+    /// Sets `key` to `value`, discarding its expire. Fails when used memory is over
+    /// `maxmemory` and eviction can't free enough.
+    pub fn set(&self, key: impl AsRef<[u8]>, value: impl Into<Bytes>) -> SerirResult<()> {
+        self.check_memory()?;
+        let key = key.as_ref();
+        self.with_shard(key, |shard| shard.set(key, value.into()));
+        Ok(())
+    }
+
+    /// The values of `keys`, `None` for the ones that don't exist.
+    pub fn mget<K: AsRef<[u8]>>(&self, keys: &[K]) -> Vec<Option<Bytes>> {
+        self.with_modified(|modified| self.mget_tracked(keys, modified))
+    }
+
+    /// Sets every key of `pairs` to its value atomically, like [`ShardedStore::set`].
+    pub fn mset<K: AsRef<[u8]>, V: Into<Bytes>>(&self, pairs: Vec<(K, V)>) -> SerirResult<()> {
+        self.check_memory()?;
+        let pairs = pairs
+            .into_iter()
+            .map(|(key, value)| (key, value.into()))
+            .collect();
+        self.with_modified(|modified| self.mset_tracked(pairs, modified));
+        Ok(())
+    }
+
+    /// Deletes `keys`. Returns how many existed.
+    pub fn del<K: AsRef<[u8]>>(&self, keys: &[K]) -> usize {
+        self.with_modified(|modified| self.del_tracked(keys, modified))
+    }
+
+    /// How many of `keys` exist, counting repeated ones every time.
+    pub fn exists<K: AsRef<[u8]>>(&self, keys: &[K]) -> usize {
+        self.with_modified(|modified| self.exists_tracked(keys, modified))
+    }
+
+    /// Makes `key` expire after `ttl`. Returns whether it exists.
+    pub fn expire(&self, key: impl AsRef<[u8]>, ttl: Duration) -> bool {
+        let at = now_ms().saturating_add(ttl.as_millis() as u64);
+        self.expire_at(key, at)
+    }
+
+    /// Makes `key` expire at `at` milliseconds since the unix epoch, right away if that's
+    /// in the past. Returns whether it exists.
+    pub fn expire_at(&self, key: impl AsRef<[u8]>, at: u64) -> bool {
+        let key = key.as_ref();
+        self.with_shard(key, |shard| shard.expire_at(key, at))
+    }
+
+    /// How long `key` has left to live.
+    pub fn ttl(&self, key: impl AsRef<[u8]>) -> Ttl {
+        let key = key.as_ref();
+        self.with_shard(key, |shard| shard.ttl(key))
+    }
+
+    /// Removes the expire of `key`. Returns whether it had one.
+    pub fn persist(&self, key: impl AsRef<[u8]>) -> bool {
+        let key = key.as_ref();
+        self.with_shard(key, |shard| shard.persist(key))
+    }
+}
+
+impl Default for ShardedStore {
+    fn default() -> Self {
+        Self::for_workers(crate::default_num_workers())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::eviction::EvictionPolicy;
+
+    fn keys(num_keys: usize) -> Vec<Vec<u8>> {
+        (0..num_keys)
+            .map(|i| format!("key:{}", i).into_bytes())
+            .collect()
+    }
+
+    #[test]
+    fn spreads_keys_across_shards() {
+        let store = ShardedStore::new(8);
+        for key in keys(1000) {
+            store.exec(Command::Set((key.clone(), key.into()))).unwrap();
+        }
+
+        assert_eq!(store.len(), 1000);
+        for shard in &store.shards {
+            assert!(!shard.lock().unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn gets_values_set_in_any_shard() {
+        let store = ShardedStore::new(8);
+        for key in keys(100) {
+            store.exec(Command::Set((key.clone(), key.into()))).unwrap();
+        }
+
+        for key in keys(100) {
+            let result = store.exec(Command::Get(key.clone())).unwrap();
+            assert_eq!(result, Resp::BulkString(Some(key.into())));
+        }
+    }
+
+    #[test]
+    fn coordinates_multi_key_commands_across_shards() {
+        let store = ShardedStore::new(8);
+        let pairs: Vec<_> = keys(20)
+            .into_iter()
+            .map(|key| (key.clone(), key.into()))
+            .collect();
+        store.exec(Command::MSet(pairs)).unwrap();
+
+        let mut requested = keys(20);
+        requested.push(b"missing".to_vec());
+        let result = store.exec(Command::MGet(requested.clone())).unwrap();
+        let mut expected: Vec<Resp> = keys(20)
+            .into_iter()
+            .map(|key| Resp::BulkString(Some(key.into())))
+            .collect();
+        expected.push(Resp::BulkString(None));
+        assert_eq!(result, Resp::Array(Some(expected)));
+
+        let result = store.exec(Command::Exists(requested.clone())).unwrap();
+        assert_eq!(result, Resp::Integer(20));
+
+        let result = store.exec(Command::Del(requested)).unwrap();
+        assert_eq!(result, Resp::Integer(20));
+        assert!(store.is_empty());
+    }
+
+    #[test]
+    fn serves_typed_values_without_commands() {
+        let store = ShardedStore::new(8);
+        store.set("greeting", "hello").unwrap();
+        store.mset(vec![("a", "1"), ("b", "2")]).unwrap();
+        assert_eq!(store.get("greeting"), Some(Bytes::from_static(b"hello")));
+        assert_eq!(
+            store.mget(&["a", "missing", "b"]),
+            vec![
+                Some(Bytes::from_static(b"1")),
+                None,
+                Some(Bytes::from_static(b"2"))
+            ]
+        );
+
+        assert_eq!(store.ttl("missing"), Ttl::Missing);
+        assert_eq!(store.ttl("a"), Ttl::Persistent);
+        assert!(store.expire("a", Duration::from_secs(100)));
+        assert!(matches!(store.ttl("a"), Ttl::Expires(ttl) if ttl > Duration::from_secs(99)));
+        assert!(store.persist("a"));
+        assert!(!store.persist("a"));
+        assert!(store.expire_at("b", now_ms() - 1));
+        assert!(!store.expire("missing", Duration::from_secs(100)));
+
+        assert_eq!(store.exists(&["greeting", "greeting", "b"]), 2);
+        assert_eq!(store.del(&["greeting", "a", "b"]), 2);
+        assert!(store.is_empty());
+
+        store.configure(1, EvictionSettings::default());
+        store.set("key", "value").unwrap();
+        assert!(matches!(
+            store.set("key", "value"),
+            Err(SerirError::CommandError(message)) if message.starts_with("OOM")
+        ));
+    }
+
+    #[test]
+    fn leaves_keys_modified_by_typed_methods_for_tracking() {
+        let store = ShardedStore::new(8);
+        store.set("untracked", "value").unwrap();
+        store.track_modified();
+        store.set("a", "1").unwrap();
+        store.mset(vec![("b", "2")]).unwrap();
+        store.get("a");
+        store.del(&["a", "missing"]);
+        assert_eq!(
+            store.take_modified(),
+            vec![b"a".to_vec(), b"b".to_vec(), b"a".to_vec()]
+        );
+    }
+
+    #[test]
+    fn tracks_memory_used_by_all_shards() {
+        let store = ShardedStore::new(8);
+        for key in keys(100) {
+            store.exec(Command::Set((key.clone(), key.into()))).unwrap();
+        }
+        let total = store.fold(0, |total, shard| total + shard.used_memory());
+        assert_eq!(store.used_memory(), total);
+
+        store.exec(Command::Del(keys(100))).unwrap();
+        assert_eq!(store.used_memory(), 0);
+    }
+
+    #[test]
+    fn keeps_serving_keys_after_overflowing_expire_times() {
+        let store = ShardedStore::new(8);
+        store.set("key", "value").unwrap();
+        for command in [
+            Command::Expire((b"key".to_vec(), i64::MAX)),
+            Command::PExpire((b"key".to_vec(), i64::MAX)),
+        ] {
+            let result = store.exec(command).unwrap();
+            assert!(matches!(result, Resp::Error(message) if message.starts_with(b"ERR invalid")));
+        }
+        assert_eq!(store.get("key"), Some(Bytes::from_static(b"value")));
+        assert_eq!(store.ttl("key"), Ttl::Persistent);
+    }
+
+    #[test]
+    fn refuses_writes_over_maxmemory_without_eviction() {
+        let store = ShardedStore::new(8);
+        store.configure(1000, EvictionSettings::default());
+        for key in keys(100) {
+            store.exec(Command::Set((key.clone(), key.into()))).unwrap();
+        }
+
+        let result = store
+            .exec(Command::Set((b"one".to_vec(), Bytes::from_static(b"more"))))
+            .unwrap();
+        assert!(matches!(result, Resp::Error(message) if message.starts_with(b"OOM")));
+        let result = store.exec(Command::Get(b"key:0".to_vec())).unwrap();
+        assert_eq!(result, Resp::BulkString(Some(Bytes::from_static(b"key:0"))));
+    }
+
+    #[test]
+    fn evicts_keys_to_stay_under_maxmemory() {
+        let store = ShardedStore::new(8);
+        store.configure(
+            10_000,
+            EvictionSettings {
+                policy: EvictionPolicy::AllKeysLru,
+                ..EvictionSettings::default()
+            },
+        );
+        for key in keys(1000) {
+            let result = store.exec(Command::Set((key.clone(), key.into()))).unwrap();
+            assert_eq!(result, Resp::SimpleString(b"OK".to_vec()));
+        }
+
+        store.free_memory_if_needed();
+        assert!(store.used_memory() <= 10_000);
+        let evicted = store.fold(0, |evicted, shard| evicted + shard.evicted_keys());
+        assert_eq!(evicted as usize, 1000 - store.len());
+    }
+
+    #[test]
+    fn volatile_policies_only_evict_keys_with_expire() {
+        let store = ShardedStore::new(8);
+        for key in keys(10) {
+            store
+                .exec(Command::Set((key.clone(), key.clone().into())))
+                .unwrap();
+            store.exec(Command::Expire((key, 100))).unwrap();
+        }
+        store
+            .exec(Command::Set((b"persistent".to_vec(), vec![0; 1000].into())))
+            .unwrap();
+        store.configure(
+            1000,
+            EvictionSettings {
+                policy: EvictionPolicy::VolatileRandom,
+                ..EvictionSettings::default()
+            },
+        );
+
+        assert!(!store.free_memory_if_needed());
+        assert_eq!(store.len(), 1);
+        assert!(
+            store
+                .exec(Command::Exists(vec![b"persistent".to_vec()]))
+                .unwrap()
+                == Resp::Integer(1)
+        );
+    }
+}
@@ -0,0 +1,138 @@
+use tokio::sync::watch;
+
+/// How the server was asked to stop, by `SHUTDOWN [NOSAVE|SAVE] [NOW] [FORCE]` or a
+/// signal.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct ShutdownOptions {
+    /// `Some(true)` for `SAVE`, `Some(false)` for `NOSAVE`, `None` to save only if save
+    /// points or the AOF are configured.
+    pub save: Option<bool>,
+    /// Doesn't wait for connections to finish their requests.
+    pub now: bool,
+    /// Exits even if the final save fails.
+    pub force: bool,
+}
+
+/// What `SHUTDOWN` asked for.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ShutdownCommand {
+    Shutdown(ShutdownOptions),
+    Abort,
+}
+
+impl ShutdownCommand {
+    /// Parses the arguments of `SHUTDOWN`, in any order and case.
+    pub fn parse(arguments: &[String]) -> Result<Self, String> {
+        let mut options = ShutdownOptions::default();
+        let mut abort = false;
+        for argument in arguments {
+            match argument.to_lowercase().as_str() {
+                "nosave" if options.save.is_none() => options.save = Some(false),
+                "save" if options.save.is_none() => options.save = Some(true),
+                "now" => options.now = true,
+                "force" => options.force = true,
+                "abort" => abort = true,
+                _ => return Err("syntax error".to_string()),
+            }
+        }
+        match abort {
+            true if options != ShutdownOptions::default() => Err("syntax error".to_string()),
+            true => Ok(ShutdownCommand::Abort),
+            false => Ok(ShutdownCommand::Shutdown(options)),
+        }
+    }
+}
+
+/// Tells the listeners and whoever runs the server that a shutdown was requested.
+#[derive(Debug)]
+pub struct Shutdown {
+    requested: watch::Sender<Option<ShutdownOptions>>,
+    /// Keeps the channel open and is cloned by tasks waiting for the request.
+    requested_rx: watch::Receiver<Option<ShutdownOptions>>,
+}
+
+impl Shutdown {
+    pub fn new() -> Self {
+        let (requested, requested_rx) = watch::channel(None);
+        Self {
+            requested,
+            requested_rx,
+        }
+    }
+
+    /// Requests a shutdown, returns false if one was already requested.
+    pub fn request(&self, options: ShutdownOptions) -> bool {
+        self.requested.send_if_modified(|requested| {
+            if requested.is_some() {
+                return false;
+            }
+            *requested = Some(options);
+            true
+        })
+    }
+
+    pub fn is_requested(&self) -> bool {
+        self.requested_rx.borrow().is_some()
+    }
+
+    /// Waits until a shutdown is requested.
+    pub async fn requested(&self) -> ShutdownOptions {
+        let mut requested = self.requested_rx.clone();
+        loop {
+            if let Some(options) = *requested.borrow_and_update() {
+                return options;
+            }
+            // the sender lives as long as `self`
+            let _ = requested.changed().await;
+        }
+    }
+}
+
+impl Default for Shutdown {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(arguments: &str) -> Result<ShutdownCommand, String> {
+        let arguments: Vec<String> = arguments.split_whitespace().map(String::from).collect();
+        ShutdownCommand::parse(&arguments)
+    }
+
+    #[test]
+    fn parses_shutdown_arguments() {
+        assert_eq!(
+            parse(""),
+            Ok(ShutdownCommand::Shutdown(ShutdownOptions::default()))
+        );
+        assert_eq!(
+            parse("now NOSAVE force"),
+            Ok(ShutdownCommand::Shutdown(ShutdownOptions {
+                save: Some(false),
+                now: true,
+                force: true,
+            }))
+        );
+        assert_eq!(parse("ABORT"), Ok(ShutdownCommand::Abort));
+        for invalid in ["save nosave", "abort now", "later"] {
+            assert!(parse(invalid).is_err());
+        }
+    }
+
+    #[tokio::test]
+    async fn only_the_first_request_counts() {
+        let shutdown = Shutdown::new();
+        assert!(!shutdown.is_requested());
+        let options = ShutdownOptions {
+            now: true,
+            ..ShutdownOptions::default()
+        };
+        assert!(shutdown.request(options));
+        assert!(!shutdown.request(ShutdownOptions::default()));
+        assert_eq!(shutdown.requested().await, options);
+    }
+}
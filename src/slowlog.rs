@@ -0,0 +1,215 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicI64, AtomicU64, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::client::Client;
+use crate::resp::Resp;
+
+/// Like Redis, only the first arguments of a command and the first bytes of each
+/// argument are kept, so huge commands don't blow up the log.
+const MAX_ARGC: usize = 32;
+const MAX_ARG_LEN: usize = 128;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SlowLogEntry {
+    pub id: u64,
+    /// Unix time the command was processed at, in seconds.
+    pub timestamp: u64,
+    pub duration: Duration,
+    pub args: Vec<Vec<u8>>,
+    pub client_addr: String,
+    pub client_name: String,
+}
+
+impl SlowLogEntry {
+    fn to_resp(&self) -> Resp {
+        Resp::Array(Some(vec![
+            Resp::Integer(self.id as i64),
+            Resp::Integer(self.timestamp as i64),
+            Resp::Integer(self.duration.as_micros() as i64),
+            Resp::Array(Some(
+                self.args
+                    .iter()
+                    .map(|arg| Resp::BulkString(Some(arg.clone().into())))
+                    .collect(),
+            )),
+            Resp::BulkString(Some(self.client_addr.clone().into())),
+            Resp::BulkString(Some(self.client_name.clone().into())),
+        ]))
+    }
+}
+
+/// The most recent commands that took longer than `slowlog-log-slower-than`.
+#[derive(Debug)]
+pub struct SlowLog {
+    slower_than: AtomicI64,
+    max_len: AtomicUsize,
+    next_id: AtomicU64,
+    entries: Mutex<VecDeque<SlowLogEntry>>,
+}
+
+impl SlowLog {
+    pub fn new() -> Self {
+        Self {
+            slower_than: AtomicI64::new(-1),
+            max_len: AtomicUsize::new(0),
+            next_id: AtomicU64::new(0),
+            entries: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    /// Applies `slowlog-log-slower-than` (in microseconds, negative disables the log) and
+    /// `slowlog-max-len`, dropping the oldest entries if the log got shorter.
+    pub fn configure(&self, slower_than: i64, max_len: usize) {
+        self.slower_than.store(slower_than, Ordering::Relaxed);
+        self.max_len.store(max_len, Ordering::Relaxed);
+        self.entries.lock().unwrap().truncate(max_len);
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.slower_than.load(Ordering::Relaxed) >= 0
+    }
+
+    /// Records the command if it was slow enough. `args` should come from
+    /// [`summarize_args`].
+    pub fn record(&self, args: Vec<Vec<u8>>, duration: Duration, client: &Client) {
+        let slower_than = self.slower_than.load(Ordering::Relaxed);
+        if slower_than < 0 || duration.as_micros() < slower_than as u128 {
+            return;
+        }
+        let entry = SlowLogEntry {
+            id: self.next_id.fetch_add(1, Ordering::Relaxed),
+            timestamp: SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map(|now| now.as_secs())
+                .unwrap_or_default(),
+            duration,
+            args,
+            client_addr: client.addr.clone(),
+            client_name: client.name(),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        entries.push_front(entry);
+        entries.truncate(self.max_len.load(Ordering::Relaxed));
+    }
+
+    /// The `count` most recent entries, newest first, or all of them if `count` is `None`.
+    pub fn get(&self, count: Option<usize>) -> Vec<SlowLogEntry> {
+        let entries = self.entries.lock().unwrap();
+        let count = count.unwrap_or(entries.len());
+        entries.iter().take(count).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn reset(&self) {
+        self.entries.lock().unwrap().clear();
+    }
+
+    /// Reply to `SLOWLOG GET`.
+    pub fn get_reply(&self, count: Option<usize>) -> Resp {
+        Resp::Array(Some(
+            self.get(count).iter().map(SlowLogEntry::to_resp).collect(),
+        ))
+    }
+}
+
+impl Default for SlowLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Copies the arguments of a request the way the slow log stores them.
+pub fn summarize_args(elements: &[Resp]) -> Vec<Vec<u8>> {
+    let argc = elements.len();
+    let kept = if argc > MAX_ARGC { MAX_ARGC - 1 } else { argc };
+    let mut args: Vec<Vec<u8>> = elements[..kept]
+        .iter()
+        .map(|element| match element {
+            Resp::BulkString(Some(arg)) if arg.len() > MAX_ARG_LEN => {
+                let mut summary = arg[..MAX_ARG_LEN].to_vec();
+                summary.extend_from_slice(
+                    format!("... ({} more bytes)", arg.len() - MAX_ARG_LEN).as_bytes(),
+                );
+                summary
+            }
+            Resp::BulkString(Some(arg)) => arg.to_vec(),
+            _ => vec![],
+        })
+        .collect();
+    if argc > MAX_ARGC {
+        args.push(format!("... ({} more arguments)", argc - kept).into_bytes());
+    }
+    args
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use bytes::Bytes;
+
+    fn bulk(arg: &[u8]) -> Resp {
+        Resp::BulkString(Some(Bytes::copy_from_slice(arg)))
+    }
+
+    #[test]
+    fn keeps_most_recent_slow_commands() {
+        let slowlog = SlowLog::new();
+        slowlog.configure(100, 2);
+        let client = Client::new(1, "127.0.0.1:5555".to_string(), String::new());
+
+        slowlog.record(vec![b"fast".to_vec()], Duration::from_micros(99), &client);
+        for name in ["first", "second", "third"] {
+            slowlog.record(
+                vec![name.as_bytes().to_vec()],
+                Duration::from_micros(100),
+                &client,
+            );
+        }
+
+        let entries = slowlog.get(None);
+        assert_eq!(slowlog.len(), 2);
+        assert_eq!(entries[0].args, vec![b"third".to_vec()]);
+        assert_eq!(entries[0].id, 2);
+        assert_eq!(entries[1].args, vec![b"second".to_vec()]);
+        assert_eq!(entries[1].client_addr, "127.0.0.1:5555");
+        assert_eq!(slowlog.get(Some(1)).len(), 1);
+
+        slowlog.reset();
+        assert!(slowlog.is_empty());
+    }
+
+    #[test]
+    fn disabled_by_negative_threshold() {
+        let slowlog = SlowLog::new();
+        slowlog.configure(-1, 128);
+        slowlog.record(
+            vec![],
+            Duration::from_secs(1),
+            &Client::new(1, String::new(), String::new()),
+        );
+        assert!(!slowlog.is_enabled());
+        assert!(slowlog.is_empty());
+    }
+
+    #[test]
+    fn summarizes_long_commands() {
+        let mut elements = vec![bulk(b"mset"), bulk(&[b'x'; 130])];
+        elements.extend((0..40).map(|_| bulk(b"arg")));
+
+        let args = summarize_args(&elements);
+        assert_eq!(args.len(), MAX_ARGC);
+        assert_eq!(args[0], b"mset".to_vec());
+        assert_eq!(args[1].len(), MAX_ARG_LEN + "... (2 more bytes)".len());
+        assert!(args[1].ends_with(b"... (2 more bytes)"));
+        assert_eq!(args[31], b"... (11 more arguments)".to_vec());
+    }
+}
@@ -0,0 +1,1988 @@
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
+
+use bytes::Bytes;
+use rand::prelude::*;
+
+use crate::acl::{Acl, CATEGORIES, DEFAULT_USER};
+use crate::client::{Client, Clients, PauseMode, ReplyMode};
+use crate::commands::Command;
+use crate::config::Config;
+use crate::error::{SerirError, SerirResult};
+use crate::glob::glob_match_nocase;
+use crate::info::info;
+use crate::latency::LatencyMonitor;
+use crate::modules::Modules;
+use crate::monitor::{format_line, Monitors};
+use crate::registry::CommandRegistry;
+use crate::resp::{Protocol, Resp};
+use crate::sharded::ShardedStore;
+use crate::shutdown::{Shutdown, ShutdownCommand, ShutdownOptions};
+use crate::slowlog::{summarize_args, SlowLog};
+use crate::stats::Stats;
+use crate::tls::Tls;
+use crate::tracking::{Tracking, TrackingCommand};
+
+/// Entries returned by `SLOWLOG GET` without a count.
+const SLOWLOG_DEFAULT_COUNT: usize = 10;
+
+/// Entries returned by `ACL LOG` without a count.
+const ACL_LOG_DEFAULT_COUNT: usize = 10;
+
+/// A request parsed into a command, along with what the slow log and the monitors need to
+/// know about it.
+#[derive(Debug)]
+pub struct Request {
+    pub command: Command,
+    slowlog_args: Option<Vec<Vec<u8>>>,
+    monitor_line: Option<Vec<u8>>,
+}
+
+/// State shared by all connections of a server.
+#[derive(Debug)]
+pub struct ServerState {
+    pub store: ShardedStore,
+    pub config: RwLock<Config>,
+    pub stats: Stats,
+    pub slowlog: SlowLog,
+    pub latency: LatencyMonitor,
+    pub monitors: Monitors,
+    pub clients: Clients,
+    pub acl: Acl,
+    pub tls: Tls,
+    pub shutdown: Shutdown,
+    pub tracking: Tracking,
+    /// Commands clients can run, modules add and remove some at runtime.
+    pub commands: RwLock<CommandRegistry>,
+    pub modules: Modules,
+    /// Random identifier of this server instance, changes on every start.
+    pub run_id: String,
+}
+
+impl ServerState {
+    pub fn new(config: Config) -> Self {
+        Self::with_commands(config, CommandRegistry::default())
+    }
+
+    /// A server knowing only the commands of `commands`.
+    pub fn with_commands(config: Config, commands: CommandRegistry) -> Self {
+        let mut rng = thread_rng();
+        let state = Self {
+            store: ShardedStore::for_workers(config.io_threads),
+            config: RwLock::new(config),
+            stats: Stats::new(),
+            slowlog: SlowLog::new(),
+            latency: LatencyMonitor::new(),
+            monitors: Monitors::new(),
+            clients: Clients::new(),
+            acl: Acl::with_commands(commands.specs().collect()),
+            tls: Tls::new(),
+            shutdown: Shutdown::new(),
+            tracking: Tracking::new(),
+            commands: RwLock::new(commands),
+            modules: Modules::new(),
+            run_id: (0..40)
+                .map(|_| format!("{:x}", rng.gen_range(0..16)))
+                .collect(),
+        };
+        {
+            let config = state.config.read().unwrap();
+            state.configure(&config);
+            if config.requirepass.is_some() {
+                state
+                    .acl
+                    .set_default_password(config.requirepass.as_deref());
+            }
+        }
+        state
+    }
+
+    /// Loads the modules of the `loadmodule` directives. Their threads get to access the
+    /// server from then on.
+    pub fn load_modules(self: &Arc<Self>) -> SerirResult<()> {
+        self.modules.attach(Arc::downgrade(self));
+        let modules = self.config.read().unwrap().loadmodule.clone();
+        for (name, arguments) in modules {
+            self.load_module(&name, &arguments)
+                .map_err(|e| SerirError::ConfigError(format!("loading module {}: {}", name, e)))?;
+        }
+        Ok(())
+    }
+
+    /// Loads a module and makes its commands available.
+    pub fn load_module(&self, name: &str, arguments: &[String]) -> Result<(), String> {
+        let subscribes = self.modules.load(name, arguments, |handlers| {
+            let mut commands = self.commands.write().unwrap();
+            if let Some(handler) = handlers
+                .iter()
+                .find(|handler| commands.get(handler.spec().name).is_some())
+            {
+                return Err(format!("command {} already exists", handler.spec().name));
+            }
+            for handler in handlers {
+                commands.insert(handler.clone());
+            }
+            self.acl.set_commands(commands.specs().collect());
+            Ok(())
+        })?;
+        if subscribes {
+            self.store.track_modified();
+        }
+        Ok(())
+    }
+
+    /// Unloads a module and removes its commands.
+    pub fn unload_module(&self, name: &str) -> Result<(), String> {
+        let (removed, subscribed) = self.modules.unload(name)?;
+        let mut commands = self.commands.write().unwrap();
+        for name in removed {
+            commands.remove(&name);
+        }
+        self.acl.set_commands(commands.specs().collect());
+        if subscribed {
+            self.store.untrack_modified();
+        }
+        Ok(())
+    }
+
+    /// Loads the users of the configured `aclfile`, if any, replacing the ones created
+    /// by `requirepass`.
+    pub fn load_acl_file(&self) -> SerirResult<()> {
+        match &self.config.read().unwrap().aclfile {
+            Some(path) => self
+                .acl
+                .load(path)
+                .map_err(|e| SerirError::ConfigError(format!("loading ACL file: {}", e))),
+            None => Ok(()),
+        }
+    }
+
+    /// Whether protected mode refuses clients from other hosts: it is enabled and anybody
+    /// could run commands as the default user.
+    pub fn is_protected(&self) -> bool {
+        self.config.read().unwrap().protected_mode && self.acl.default_user_is_open()
+    }
+
+    /// Loads the TLS certificates and keys, if TLS is enabled.
+    pub fn load_tls(&self) -> SerirResult<()> {
+        self.tls
+            .reload(&self.config.read().unwrap())
+            .map_err(|e| SerirError::ConfigError(format!("loading TLS configuration: {}", e)))
+    }
+
+    /// Applies the runtime tunable parts of `config` to the subsystems that keep their own
+    /// copy of them.
+    fn configure(&self, config: &Config) {
+        self.store
+            .configure(config.maxmemory, config.eviction_settings());
+        self.slowlog
+            .configure(config.slowlog_log_slower_than, config.slowlog_max_len);
+        self.latency.configure(config.latency_monitor_threshold);
+        self.acl.set_log_max_len(config.acllog_max_len);
+        self.clients
+            .configure(config.maxclients, config.timeout, config.tcp_keepalive);
+        self.clients.set_buffer_limits(config.buffer_limits());
+    }
+
+    /// Runs periodic housekeeping: deleting expired keys nobody accessed, evicting keys
+    /// when `maxmemory` was lowered at runtime, disconnecting idle clients and sampling
+    /// instantaneous metrics. Clients tracking keys that expired or got evicted are told.
+    /// Expiry and eviction are skipped while clients are paused.
+    pub async fn cron(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(Duration::from_millis(100));
+        loop {
+            interval.tick().await;
+            // like commands, keys expiring or being evicted would change the keyspace
+            if !self.clients.is_paused() {
+                let start = Instant::now();
+                self.store.active_expire_cycle();
+                self.latency.record("expire-cycle", start.elapsed());
+                let start = Instant::now();
+                self.store.free_memory_if_needed();
+                self.latency.record("eviction-cycle", start.elapsed());
+                self.keys_modified(self.store.take_modified(), None, None);
+            }
+            self.clients.kill_idle();
+            self.stats.memory_used(self.store.used_memory());
+            self.stats.sample();
+        }
+    }
+
+    /// Parses a request sent by `client`. Invalid commands are reported as
+    /// [`crate::error::SerirError::CommandError`], to be sent back to the client.
+    pub fn parse_request(&self, client: &Client, request: Resp) -> SerirResult<Request> {
+        let (slowlog_args, monitor_line) = match &request {
+            Resp::Array(Some(elements)) => (
+                self.slowlog.is_enabled().then(|| summarize_args(elements)),
+                self.monitors
+                    .is_active()
+                    .then(|| format_line(client, elements)),
+            ),
+            _ => (None, None),
+        };
+        let command = self.commands.read().unwrap().parse(request)?;
+        // passwords never show up in the slow log or in monitors
+        if let Command::Auth(_) | Command::Hello(_) = command {
+            return Ok(Request {
+                command,
+                slowlog_args: slowlog_args.map(|args| {
+                    let redacted = args.iter().skip(1).map(|_| b"(redacted)".to_vec());
+                    args[..1].iter().cloned().chain(redacted).collect()
+                }),
+                monitor_line: None,
+            });
+        }
+        Ok(Request {
+            command,
+            slowlog_args,
+            monitor_line,
+        })
+    }
+
+    /// Waits for a `CLIENT PAUSE` that applies to `command` to end. `CLIENT` commands are
+    /// never paused, so that the pause can always be lifted.
+    pub async fn wait_if_paused(&self, command: &Command) {
+        if !matches!(command, Command::Client(_)) {
+            self.clients.wait_for_unpause(command.is_write()).await;
+        }
+    }
+
+    /// Executes a request sent by `client`, if its user is allowed to. Slow commands are
+    /// recorded in the slow log and every command is fed to the monitors.
+    pub fn exec_request(&self, client: &Client, request: Request) -> SerirResult<Resp> {
+        if let Err(reply) = self.check_access(client, &request.command) {
+            return Ok(reply);
+        }
+        let is_monitor = matches!(request.command, Command::Monitor);
+        let (result, duration) = self.exec_timed(client, request.command);
+        if let Some(args) = request.slowlog_args {
+            self.slowlog.record(args, duration, client);
+        }
+        if let Some(line) = request.monitor_line.filter(|_| !is_monitor) {
+            self.monitors.feed(line);
+        }
+        result
+    }
+
+    /// Refuses commands from clients that must authenticate first, and commands or keys the
+    /// user of the client has no permissions for. Denials are recorded in the ACL log.
+    fn check_access(&self, client: &Client, command: &Command) -> Result<(), Resp> {
+        if let Command::Auth(_) | Command::Hello(_) = command {
+            return Ok(());
+        }
+        if !client.is_authenticated() && !self.acl.default_user_is_open() {
+            return Err(Resp::Error(b"NOAUTH Authentication required.".to_vec()));
+        }
+        let user = client.user();
+        self.acl.check(&user, command).map_err(|denial| {
+            self.acl.log(Some(&denial), &user, client.info_line());
+            Resp::Error(denial.message(&user).into_bytes())
+        })
+    }
+
+    /// Executes a command on behalf of no client in particular.
+    pub fn exec(&self, command: Command) -> SerirResult<Vec<u8>> {
+        let client = Client::new(0, String::new(), String::new());
+        self.exec_timed(&client, command).0?.serialize()
+    }
+
+    fn exec_timed(&self, client: &Client, command: Command) -> (SerirResult<Resp>, Duration) {
+        let name = command.name();
+        client.command_started(name);
+        self.stats.command_started();
+        let tracked_keys = self.tracked_keys(client, &command);
+        // CLIENT CACHING applies to the command after it
+        let is_caching = name == "client"
+            && command
+                .subcommand()
+                .is_some_and(|subcommand| subcommand.eq_ignore_ascii_case("caching"));
+        let start = Instant::now();
+        // commands like MODULE LOAD change the registry
+        let handler = self.commands.read().unwrap().get(name).cloned();
+        let result = match handler {
+            Some(handler) => handler.exec(self, client, command),
+            None => Ok(err(&format!("unknown command '{}'", name))),
+        };
+        // keys evicted to make room for the command, or modified by commands that don't
+        // report them
+        self.keys_modified(self.store.take_modified(), Some(name), None);
+        let duration = start.elapsed();
+        if let Some(keys) = tracked_keys {
+            self.tracking.remember(client.id, keys);
+        }
+        if !is_caching {
+            client.set_caching(false);
+        }
+        self.stats.command_processed(name, duration);
+        self.latency.record("command", duration);
+        (result, duration)
+    }
+
+    /// Executes a built-in command.
+    pub(crate) fn exec_builtin(&self, client: &Client, command: Command) -> SerirResult<Resp> {
+        let name = command.name();
+        match command {
+            Command::Config(arguments) => self.config(&arguments),
+            Command::Info(sections) => Ok(Resp::VerbatimString(
+                "txt".to_string(),
+                info(self, &sections).into_bytes(),
+            )),
+            Command::Slowlog(arguments) => self.slowlog(&arguments),
+            Command::Latency(arguments) => self.latency(&arguments),
+            Command::Monitor => {
+                client.set_monitor();
+                Ok(ok())
+            }
+            Command::Client(arguments) => self.client(client, &arguments),
+            Command::Auth((username, password)) => Ok(self.auth(client, username, &password)),
+            Command::Acl(arguments) => self.acl(client, &arguments),
+            Command::Shutdown(arguments) => self.shutdown_command(client, &arguments),
+            Command::Hello(arguments) => Ok(self.hello(client, &arguments)),
+            Command::Command(arguments) => Ok(self.command(&arguments)),
+            Command::Module(arguments) => Ok(self.module(&arguments)),
+            command => {
+                let (result, modified) = self.store.exec_tracked(command);
+                self.keys_modified(modified, Some(name), Some(client.id));
+                result
+            }
+        }
+    }
+
+    /// The keys `command` reads that `client` must be told about once they change.
+    fn tracked_keys(&self, client: &Client, command: &Command) -> Option<Vec<Vec<u8>>> {
+        let is_read = self
+            .commands
+            .read()
+            .unwrap()
+            .spec(command.name())
+            .is_some_and(|spec| spec.categories.contains(&"read"));
+        if !is_read || !client.tracks_reads() {
+            return None;
+        }
+        Some(command.keys().into_iter().map(|key| key.to_vec()).collect())
+    }
+
+    /// Tells modules subscribed to keyspace events and clients tracking `keys` that they
+    /// changed, because of `command` run by client `origin` or in the background.
+    fn keys_modified(&self, keys: Vec<Vec<u8>>, command: Option<&str>, origin: Option<u64>) {
+        self.modules.notify(command, &keys);
+        self.invalidate(keys, origin);
+    }
+
+    /// Sends invalidation messages for `keys` to the clients tracking them. `origin` is
+    /// the client that modified them, `None` when they expired or got evicted.
+    fn invalidate(&self, keys: Vec<Vec<u8>>, origin: Option<u64>) {
+        if keys.is_empty() {
+            return;
+        }
+        for (id, keys) in self.tracking.invalidated(&keys) {
+            let Some(client) = self.clients.get(id) else {
+                continue;
+            };
+            let Some(options) = client.tracking() else {
+                continue;
+            };
+            if options.noloop && origin == Some(id) {
+                continue;
+            }
+            let target = match options.redirect {
+                Some(redirect) => match self.clients.get(redirect) {
+                    Some(target) => target,
+                    None => {
+                        client.set_broken_redirect();
+                        if client.protocol() == Protocol::Resp3 {
+                            client.push(Resp::Push(vec![
+                                Resp::BulkString(Some(Bytes::from_static(
+                                    b"tracking-redir-broken",
+                                ))),
+                                Resp::Integer(redirect as i64),
+                            ]));
+                        }
+                        continue;
+                    }
+                },
+                None => client,
+            };
+            let keys = Resp::Array(Some(
+                keys.into_iter()
+                    .map(|key| Resp::BulkString(Some(key.into())))
+                    .collect(),
+            ));
+            match target.protocol() {
+                Protocol::Resp3 => target.push(Resp::Push(vec![
+                    Resp::BulkString(Some(Bytes::from_static(b"invalidate"))),
+                    keys,
+                ])),
+                // there is no SUBSCRIBE, RESP2 clients that tracking redirects to get the
+                // messages they would have subscribed to
+                Protocol::Resp2 if options.redirect.is_some() => {
+                    target.push(Resp::Array(Some(vec![
+                        Resp::BulkString(Some(Bytes::from_static(b"message"))),
+                        Resp::BulkString(Some(Bytes::from_static(b"__redis__:invalidate"))),
+                        keys,
+                    ])))
+                }
+                // they would be mistaken for replies
+                Protocol::Resp2 => {}
+            }
+        }
+    }
+
+    /// Turns tracking off for a client, when asked to or when it disconnects.
+    pub fn disable_tracking(&self, client: &Client) {
+        if let Some(options) = client.tracking() {
+            self.tracking.disable(&self.store, client.id, &options);
+            client.set_tracking(None);
+        }
+    }
+
+    /// `CLIENT TRACKING ON|OFF [REDIRECT id] [PREFIX prefix ...] [BCAST] [OPTIN]
+    /// [OPTOUT] [NOLOOP]`.
+    fn client_tracking(&self, client: &Client, arguments: &[String]) -> Result<(), String> {
+        let options = match TrackingCommand::parse(arguments)? {
+            TrackingCommand::On(options) => options,
+            TrackingCommand::Off => {
+                self.disable_tracking(client);
+                return Ok(());
+            }
+        };
+        if let Some(redirect) = options.redirect {
+            if self.clients.get(redirect).is_none() {
+                return Err("The client ID you want redirect to does not exist".to_string());
+            }
+        }
+        let previous = client.tracking();
+        let options = options.apply_to(previous.as_ref())?;
+        self.tracking
+            .enable(&self.store, client.id, previous.as_ref(), &options);
+        client.set_tracking(Some(options));
+        Ok(())
+    }
+
+    /// `CLIENT TRACKINGINFO`.
+    fn tracking_info(client: &Client) -> Resp {
+        let bulk = |value: &str| Resp::BulkString(Some(Bytes::copy_from_slice(value.as_bytes())));
+        let options = client.tracking();
+        let mut flags = vec![];
+        match &options {
+            None => flags.push("off"),
+            Some(options) => {
+                flags.push("on");
+                if options.bcast {
+                    flags.push("bcast");
+                }
+                if options.optin {
+                    flags.push("optin");
+                    if client.is_caching() {
+                        flags.push("caching-yes");
+                    }
+                }
+                if options.optout {
+                    flags.push("optout");
+                    if client.is_caching() {
+                        flags.push("caching-no");
+                    }
+                }
+                if options.noloop {
+                    flags.push("noloop");
+                }
+                if client.has_broken_redirect() {
+                    flags.push("broken_redirect");
+                }
+            }
+        }
+        let prefixes = options
+            .map(|options| options.prefixes)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|prefix| Resp::BulkString(Some(prefix.into())))
+            .collect();
+        Resp::Map(vec![
+            (
+                bulk("flags"),
+                Resp::Array(Some(flags.into_iter().map(bulk).collect())),
+            ),
+            (bulk("redirect"), Resp::Integer(client.tracking_redirect())),
+            (bulk("prefixes"), Resp::Array(Some(prefixes))),
+        ])
+    }
+
+    fn config(&self, arguments: &[String]) -> SerirResult<Resp> {
+        let subcommand = arguments
+            .first()
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default();
+        let arguments = arguments.get(1..).unwrap_or_default();
+
+        let reply = match (subcommand.as_str(), arguments.len()) {
+            ("get", len) if len > 0 => {
+                let values = self.config.read().unwrap().get(arguments);
+                let pairs = values
+                    .into_iter()
+                    .map(|(name, value)| {
+                        (
+                            Resp::BulkString(Some(Bytes::copy_from_slice(name.as_bytes()))),
+                            Resp::BulkString(Some(value.into())),
+                        )
+                    })
+                    .collect();
+                Resp::Map(pairs)
+            }
+            ("set", len) if len > 0 && len % 2 == 0 => {
+                let pairs: Vec<_> = arguments
+                    .chunks(2)
+                    .map(|pair| (pair[0].clone(), pair[1].clone()))
+                    .collect();
+                let mut config = self.config.write().unwrap();
+                let previous = config.clone();
+                let reload_tls = pairs
+                    .iter()
+                    .any(|(name, _)| name.to_lowercase().starts_with("tls-"));
+                // setting any TLS parameter reloads the certificates, even if the file
+                // names didn't change
+                let result = config.set(&pairs).and_then(|()| match reload_tls {
+                    true => self.tls.reload(&config).map_err(|e| {
+                        format!(
+                            "CONFIG SET failed - Unable to update TLS configuration: {}",
+                            e
+                        )
+                    }),
+                    false => Ok(()),
+                });
+                match result {
+                    Ok(()) => {
+                        self.configure(&config);
+                        // only a new password resets the ones given to the default user
+                        // with ACL SETUSER
+                        if config.requirepass != previous.requirepass {
+                            self.acl.set_default_password(config.requirepass.as_deref());
+                        }
+                        ok()
+                    }
+                    Err(e) => {
+                        *config = previous;
+                        err(&e)
+                    }
+                }
+            }
+            ("rewrite", 0) => {
+                let config = self.config.read().unwrap().clone();
+                match config.rewrite() {
+                    Ok(()) => ok(),
+                    Err(e) => err(&format!("Rewriting config file: {}", e)),
+                }
+            }
+            ("resetstat", 0) => {
+                self.stats.reset();
+                self.store.reset_stats();
+                ok()
+            }
+            ("get" | "set" | "rewrite" | "resetstat", _) => err(&format!(
+                "wrong number of arguments for 'config|{}' command",
+                subcommand
+            )),
+            _ => err(&format!(
+                "unknown subcommand '{}'. Try CONFIG GET, SET, REWRITE or RESETSTAT.",
+                subcommand
+            )),
+        };
+        Ok(reply)
+    }
+
+    fn slowlog(&self, arguments: &[String]) -> SerirResult<Resp> {
+        let subcommand = arguments
+            .first()
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default();
+        let arguments = arguments.get(1..).unwrap_or_default();
+
+        let reply = match (subcommand.as_str(), arguments) {
+            ("get", []) => self.slowlog.get_reply(Some(SLOWLOG_DEFAULT_COUNT)),
+            ("get", [count]) => match count.parse::<i64>() {
+                Ok(-1) => self.slowlog.get_reply(None),
+                Ok(count) if count >= 0 => self.slowlog.get_reply(Some(count as usize)),
+                Ok(_) => err("count should be greater than or equal to -1"),
+                Err(_) => err("value is not an integer or out of range"),
+            },
+            ("len", []) => Resp::Integer(self.slowlog.len() as i64),
+            ("reset", []) => {
+                self.slowlog.reset();
+                ok()
+            }
+            ("help", []) => help(&[
+                "SLOWLOG <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "GET [<count>]",
+                "    Return top <count> entries from the slowlog (default: 10, -1 mean all).",
+                "LEN",
+                "    Return the length of the slowlog.",
+                "RESET",
+                "    Reset the slowlog.",
+            ]),
+            ("get" | "len" | "reset" | "help", _) => err(&format!(
+                "wrong number of arguments for 'slowlog|{}' command",
+                subcommand
+            )),
+            _ => err(&format!(
+                "unknown subcommand '{}'. Try SLOWLOG HELP.",
+                subcommand
+            )),
+        };
+        Ok(reply)
+    }
+
+    fn latency(&self, arguments: &[String]) -> SerirResult<Resp> {
+        let subcommand = arguments
+            .first()
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default();
+        let arguments = arguments.get(1..).unwrap_or_default();
+
+        let reply = match (subcommand.as_str(), arguments) {
+            ("latest", []) => Resp::Array(Some(
+                self.latency
+                    .latest()
+                    .into_iter()
+                    .map(|(event, latest, max)| {
+                        Resp::Array(Some(vec![
+                            Resp::BulkString(Some(Bytes::copy_from_slice(event.as_bytes()))),
+                            Resp::Integer(latest.time as i64),
+                            Resp::Integer(latest.latency as i64),
+                            Resp::Integer(max as i64),
+                        ]))
+                    })
+                    .collect(),
+            )),
+            ("history", [event]) => Resp::Array(Some(
+                self.latency
+                    .history(&event.to_lowercase())
+                    .into_iter()
+                    .map(|sample| {
+                        Resp::Array(Some(vec![
+                            Resp::Integer(sample.time as i64),
+                            Resp::Integer(sample.latency as i64),
+                        ]))
+                    })
+                    .collect(),
+            )),
+            ("reset", events) => Resp::Integer(self.latency.reset(events) as i64),
+            ("doctor", []) => Resp::BulkString(Some(self.latency.doctor().into())),
+            ("histogram", commands) => self.latency_histogram(commands),
+            ("help", []) => help(&[
+                "LATENCY <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "DOCTOR",
+                "    Return a human readable latency analysis report.",
+                "HISTORY <event>",
+                "    Return time-latency samples for the <event> class.",
+                "LATEST",
+                "    Return the latest latency samples for all events.",
+                "RESET [<event> ...]",
+                "    Reset latency data of one or more <event> classes.",
+                "    (default: reset all data for all event classes)",
+                "HISTOGRAM [COMMAND ...]",
+                "    Return a cumulative distribution of latencies in the format of a histogram for the specified command names.",
+                "    If no commands are specified then all histograms are replied.",
+            ]),
+            ("latest" | "history" | "doctor" | "help", _) => err(&format!(
+                "wrong number of arguments for 'latency|{}' command",
+                subcommand
+            )),
+            _ => err(&format!(
+                "unknown subcommand '{}'. Try LATENCY HELP.",
+                subcommand
+            )),
+        };
+        Ok(reply)
+    }
+
+    /// Per command cumulative latency distribution: for every command, its number of calls
+    /// and the number of calls that took at most each power of two microseconds.
+    fn latency_histogram(&self, commands: &[String]) -> Resp {
+        let commands: Vec<String> = commands.iter().map(|s| s.to_lowercase()).collect();
+        let mut elements = vec![];
+        self.stats.for_each_command(|name, command| {
+            if !commands.is_empty() && !commands.iter().any(|c| c == name) {
+                return;
+            }
+            let mut cumulative = 0;
+            let mut histogram = vec![];
+            for (i, count) in command.latency_buckets().into_iter().enumerate() {
+                if count > 0 {
+                    cumulative += count;
+                    histogram.push(Resp::Integer(1 << i));
+                    histogram.push(Resp::Integer(cumulative as i64));
+                }
+            }
+            elements.push(Resp::BulkString(Some(Bytes::copy_from_slice(
+                name.as_bytes(),
+            ))));
+            elements.push(Resp::Array(Some(vec![
+                Resp::BulkString(Some(Bytes::from_static(b"calls"))),
+                Resp::Integer(command.calls() as i64),
+                Resp::BulkString(Some(Bytes::from_static(b"histogram_usec"))),
+                Resp::Array(Some(histogram)),
+            ])));
+        });
+        Resp::Array(Some(elements))
+    }
+
+    fn client(&self, client: &Client, arguments: &[String]) -> SerirResult<Resp> {
+        let subcommand = arguments
+            .first()
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default();
+        let arguments = arguments.get(1..).unwrap_or_default();
+
+        let reply = match (subcommand.as_str(), arguments) {
+            ("id", []) => Resp::Integer(client.id as i64),
+            ("info", []) => {
+                Resp::VerbatimString("txt".to_string(), client.info_line().into_bytes())
+            }
+            ("list", filters) => match self.list_clients(filters) {
+                Ok(list) => Resp::VerbatimString("txt".to_string(), list.into_bytes()),
+                Err(e) => err(&e),
+            },
+            ("setname", [name]) => match client.set_name(name) {
+                Ok(()) => ok(),
+                Err(e) => err(&e),
+            },
+            ("getname", []) => match client.name() {
+                name if name.is_empty() => Resp::BulkString(None),
+                name => Resp::BulkString(Some(name.into())),
+            },
+            // old form, kills a single client by address
+            ("kill", [addr]) => {
+                match self.clients.list().iter().find(|other| other.addr == *addr) {
+                    Some(other) => {
+                        other.kill();
+                        ok()
+                    }
+                    None => err("No such client"),
+                }
+            }
+            ("kill", filters) if !filters.is_empty() => match self.kill_clients(client, filters) {
+                Ok(killed) => Resp::Integer(killed as i64),
+                Err(e) => err(&e),
+            },
+            ("pause", [timeout]) | ("pause", [timeout, _]) => {
+                let mode = match arguments.get(1).map(|mode| mode.to_lowercase()).as_deref() {
+                    None | Some("all") => Some(PauseMode::All),
+                    Some("write") => Some(PauseMode::Write),
+                    Some(_) => None,
+                };
+                match (timeout.parse::<i64>(), mode) {
+                    (Ok(timeout), Some(mode)) if timeout >= 0 => {
+                        self.clients
+                            .pause(Duration::from_millis(timeout as u64), mode);
+                        ok()
+                    }
+                    (Ok(_), Some(_)) => err("timeout is negative"),
+                    (Err(_), _) => err("timeout is not an integer or out of range"),
+                    (_, None) => err("syntax error"),
+                }
+            }
+            ("unpause", []) => {
+                self.clients.unpause();
+                ok()
+            }
+            ("no-evict", [switch]) => match switch.to_lowercase().as_str() {
+                "on" => {
+                    client.set_no_evict(true);
+                    ok()
+                }
+                "off" => {
+                    client.set_no_evict(false);
+                    ok()
+                }
+                _ => err("syntax error"),
+            },
+            ("reply", [mode]) => match mode.to_lowercase().as_str() {
+                "on" => {
+                    client.set_reply_mode(ReplyMode::On);
+                    ok()
+                }
+                "off" => {
+                    client.set_reply_mode(ReplyMode::Off);
+                    ok()
+                }
+                "skip" => {
+                    client.set_reply_mode(ReplyMode::Skip);
+                    ok()
+                }
+                _ => err("syntax error"),
+            },
+            ("tracking", switch) if !switch.is_empty() => {
+                match self.client_tracking(client, switch) {
+                    Ok(()) => ok(),
+                    Err(e) => err(&e),
+                }
+            }
+            ("caching", [switch]) => match (client.tracking(), switch.to_lowercase().as_str()) {
+                (None, _) => err(
+                    "CLIENT CACHING can be called only when the client is in tracking mode \
+                     with OPTIN or OPTOUT mode enabled",
+                ),
+                (Some(options), "yes") if options.optin => {
+                    client.set_caching(true);
+                    ok()
+                }
+                (Some(_), "yes") => {
+                    err("CLIENT CACHING YES is only valid when tracking is enabled in OPTIN mode.")
+                }
+                (Some(options), "no") if options.optout => {
+                    client.set_caching(true);
+                    ok()
+                }
+                (Some(_), "no") => {
+                    err("CLIENT CACHING NO is only valid when tracking is enabled in OPTOUT mode.")
+                }
+                _ => err("syntax error"),
+            },
+            ("getredir", []) => Resp::Integer(client.tracking_redirect()),
+            ("trackinginfo", []) => Self::tracking_info(client),
+            ("help", []) => help(&[
+                "CLIENT <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "GETNAME",
+                "    Return the name of the current connection.",
+                "CACHING (YES|NO)",
+                "    Enable/disable tracking of the keys for next command in OPTIN/OPTOUT modes.",
+                "GETREDIR",
+                "    Return the client ID we are redirecting to when tracking is enabled.",
+                "ID",
+                "    Return the ID of the current connection.",
+                "INFO",
+                "    Return information about the current client connection.",
+                "KILL <ip:port>",
+                "    Kill connection made from <ip:port>.",
+                "KILL <option> <value> [<option> <value> [...]]",
+                "    Kill connections. Options are:",
+                "    * ADDR (<ip:port>|<unixsocket>:0)",
+                "      Kill connections made from the specified address",
+                "    * LADDR (<ip:port>|<unixsocket>:0)",
+                "      Kill connections made to specified local address",
+                "    * ID <client-id>",
+                "      Kill connections by client id.",
+                "    * USER <username>",
+                "      Kill connections authenticated by <username>.",
+                "    * SKIPME (YES|NO)",
+                "      Skip killing current connection (default: yes).",
+                "LIST [options ...]",
+                "    Return information about client connections. Options:",
+                "    * TYPE (NORMAL|MASTER|REPLICA|PUBSUB)",
+                "      Return clients of specified type.",
+                "    * ID <client-id> [<client-id> ...]",
+                "      Return clients of specified IDs only.",
+                "PAUSE <timeout> [WRITE|ALL]",
+                "    Suspend all, or just write, clients for <timeout> milliseconds.",
+                "UNPAUSE",
+                "    Stop the current client pause, resuming traffic.",
+                "SETNAME <name>",
+                "    Assign the name <name> to the current connection.",
+                "NO-EVICT (ON|OFF)",
+                "    Protect current client connection from eviction.",
+                "REPLY (ON|OFF|SKIP)",
+                "    Control the replies sent to the current connection.",
+                "TRACKING (ON|OFF) [REDIRECT <id>] [BCAST] [PREFIX <prefix> [...]]",
+                "         [OPTIN] [OPTOUT] [NOLOOP]",
+                "    Control server assisted client side caching.",
+                "TRACKINGINFO",
+                "    Report tracking status for the current connection.",
+            ]),
+            (
+                "id" | "info" | "setname" | "getname" | "kill" | "pause" | "unpause" | "no-evict"
+                | "reply" | "tracking" | "caching" | "getredir" | "trackinginfo" | "help",
+                _,
+            ) => err(&format!(
+                "wrong number of arguments for 'client|{}' command",
+                subcommand
+            )),
+            _ => err(&format!(
+                "unknown subcommand '{}'. Try CLIENT HELP.",
+                subcommand
+            )),
+        };
+        Ok(reply)
+    }
+
+    /// `CLIENT LIST [TYPE type] [ID id ...]`.
+    fn list_clients(&self, filters: &[String]) -> Result<String, String> {
+        let mut clients = self.clients.list();
+        match filters {
+            [] => {}
+            [option, kind] if option.eq_ignore_ascii_case("type") => {
+                match kind.to_lowercase().as_str() {
+                    // there are no replicas nor pub/sub clients (yet)
+                    "normal" => {}
+                    "master" | "replica" | "slave" | "pubsub" => clients.clear(),
+                    _ => return Err(format!("Unknown client type '{}'", kind)),
+                }
+            }
+            [option, ids @ ..] if option.eq_ignore_ascii_case("id") && !ids.is_empty() => {
+                let ids = ids
+                    .iter()
+                    .map(|id| id.parse::<u64>().ok().filter(|id| *id > 0))
+                    .collect::<Option<Vec<_>>>()
+                    .ok_or("Invalid client ID")?;
+                clients.retain(|client| ids.contains(&client.id));
+            }
+            _ => return Err("syntax error".to_string()),
+        }
+        Ok(clients.iter().map(|client| client.info_line()).collect())
+    }
+
+    /// `CLIENT KILL <filter> <value> ...`, returns the number of clients killed.
+    fn kill_clients(&self, client: &Client, filters: &[String]) -> Result<usize, String> {
+        if !filters.len().is_multiple_of(2) {
+            return Err("syntax error".to_string());
+        }
+        let mut id = None;
+        let mut addr = None;
+        let mut laddr = None;
+        let mut user = None;
+        let mut skipme = true;
+        for pair in filters.chunks(2) {
+            let value = &pair[1];
+            match pair[0].to_lowercase().as_str() {
+                "id" => match value.parse::<u64>() {
+                    Ok(value) if value > 0 => id = Some(value),
+                    _ => return Err("client-id should be greater than 0".to_string()),
+                },
+                "addr" => addr = Some(value),
+                "laddr" => laddr = Some(value),
+                "user" => user = Some(value),
+                "skipme" => match value.to_lowercase().as_str() {
+                    "yes" => skipme = true,
+                    "no" => skipme = false,
+                    _ => return Err("syntax error".to_string()),
+                },
+                _ => return Err("syntax error".to_string()),
+            }
+        }
+
+        let mut killed = 0;
+        for other in self.clients.list() {
+            let matches = id.is_none_or(|id| other.id == id)
+                && addr.is_none_or(|addr| other.addr == *addr)
+                && laddr.is_none_or(|laddr| other.laddr == *laddr)
+                && user.is_none_or(|user| other.user() == *user)
+                && !(skipme && other.id == client.id);
+            if matches {
+                other.kill();
+                killed += 1;
+            }
+        }
+        Ok(killed)
+    }
+
+    fn auth(&self, client: &Client, username: Option<String>, password: &[u8]) -> Resp {
+        if username.is_none() && self.acl.default_user_is_open() {
+            return err(
+                "AUTH <password> called without any password configured for the default user. \
+                 Are you sure your configuration is correct?",
+            );
+        }
+        let username = username.unwrap_or_else(|| DEFAULT_USER.to_string());
+        if self.acl.authenticate(&username, password) {
+            client.authenticate(&username);
+            ok()
+        } else {
+            self.acl.log(None, &username, client.info_line());
+            Resp::Error(b"WRONGPASS invalid username-password pair or user is disabled.".to_vec())
+        }
+    }
+
+    /// `HELLO [protover [AUTH username password] [SETNAME clientname]]` switches the
+    /// connection to `protover`, after authenticating and naming it if asked to, and
+    /// replies with a description of the server.
+    fn hello(&self, client: &Client, arguments: &[Vec<u8>]) -> Resp {
+        let text = |argument: &[u8]| String::from_utf8_lossy(argument).to_string();
+        let protocol = match arguments.first() {
+            None => client.protocol(),
+            Some(version) => match text(version).parse() {
+                Ok(version) => match Protocol::from_version(version) {
+                    Some(protocol) => protocol,
+                    None => return Resp::Error(b"NOPROTO unsupported protocol version".to_vec()),
+                },
+                Err(_) => return err("Protocol version is not an integer or out of range"),
+            },
+        };
+
+        let mut credentials = None;
+        let mut name = None;
+        let mut options = arguments.iter().skip(1);
+        while let Some(option) = options.next() {
+            let option = text(option);
+            match option.to_lowercase().as_str() {
+                "auth" => match (options.next(), options.next()) {
+                    (Some(username), Some(password)) => {
+                        credentials = Some((text(username), password))
+                    }
+                    _ => return err(&format!("Syntax error in HELLO option '{}'", option)),
+                },
+                "setname" => match options.next() {
+                    Some(value) => name = Some(text(value)),
+                    None => return err(&format!("Syntax error in HELLO option '{}'", option)),
+                },
+                _ => return err(&format!("Syntax error in HELLO option '{}'", option)),
+            }
+        }
+
+        if let Some((username, password)) = credentials {
+            let reply = self.auth(client, Some(username), password);
+            if let Resp::Error(_) = reply {
+                return reply;
+            }
+        }
+        if !client.is_authenticated() && !self.acl.default_user_is_open() {
+            return Resp::Error(
+                b"NOAUTH HELLO must be called with the client already authenticated, \
+                  otherwise the HELLO <proto> AUTH <user> <pass> option can be used to \
+                  authenticate the client and select the RESP protocol version at the same time"
+                    .to_vec(),
+            );
+        }
+        if let Some(name) = name {
+            if let Err(e) = client.set_name(&name) {
+                return err(&e);
+            }
+        }
+        client.set_protocol(protocol);
+
+        let bulk = |value: &str| Resp::BulkString(Some(Bytes::copy_from_slice(value.as_bytes())));
+        Resp::Map(vec![
+            (bulk("server"), bulk("redis")),
+            (bulk("version"), bulk("7.0.0")),
+            (bulk("proto"), Resp::Integer(protocol.version())),
+            (bulk("id"), Resp::Integer(client.id as i64)),
+            (bulk("mode"), bulk("standalone")),
+            (bulk("role"), bulk("master")),
+            (bulk("modules"), Resp::Array(Some(vec![]))),
+        ])
+    }
+
+    fn acl(&self, client: &Client, arguments: &[Vec<u8>]) -> SerirResult<Resp> {
+        // only the passwords of ACL SETUSER need the raw bytes
+        let raw_arguments = arguments.get(1..).unwrap_or_default();
+        let arguments: Vec<String> = arguments
+            .iter()
+            .map(|argument| String::from_utf8_lossy(argument).to_string())
+            .collect();
+        let subcommand = arguments
+            .first()
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default();
+        let arguments = arguments.get(1..).unwrap_or_default();
+
+        let bulk_array = |values: Vec<String>| {
+            Resp::Array(Some(
+                values
+                    .into_iter()
+                    .map(|value| Resp::BulkString(Some(value.into())))
+                    .collect(),
+            ))
+        };
+        let reply = match (subcommand.as_str(), arguments) {
+            ("setuser", [name, ..]) => match self.acl.set_user(name, &raw_arguments[1..]) {
+                Ok(()) => ok(),
+                Err(e) => err(&e),
+            },
+            ("getuser", [name]) => self.acl.get_user(name),
+            ("deluser", names) if !names.is_empty() => match self.acl.delete_users(names) {
+                Ok(deleted) => {
+                    self.kill_clients_of_removed_users();
+                    Resp::Integer(deleted as i64)
+                }
+                Err(e) => err(&e),
+            },
+            ("list", []) => bulk_array(self.acl.list()),
+            ("users", []) => bulk_array(self.acl.usernames()),
+            ("whoami", []) => Resp::BulkString(Some(client.user().into())),
+            ("cat", []) => bulk_array(CATEGORIES.iter().map(|c| c.to_string()).collect()),
+            ("cat", [category]) => {
+                let category = category.to_lowercase();
+                if CATEGORIES.contains(&category.as_str()) {
+                    bulk_array(self.acl.category_commands(&category))
+                } else {
+                    err(&format!("Unknown category '{}'", category))
+                }
+            }
+            ("log", []) => self.acl.log_reply(Some(ACL_LOG_DEFAULT_COUNT)),
+            ("log", [argument]) if argument.eq_ignore_ascii_case("reset") => {
+                self.acl.reset_log();
+                ok()
+            }
+            ("log", [count]) => match count.parse::<usize>() {
+                Ok(count) => self.acl.log_reply(Some(count)),
+                Err(_) => err("value is out of range, must be positive"),
+            },
+            ("save" | "load", []) => {
+                let aclfile = self.config.read().unwrap().aclfile.clone();
+                match (aclfile, subcommand.as_str()) {
+                    (None, _) => err(
+                        "This instance is not configured to use an ACL file. You may want to \
+                         specify users via the ACL SETUSER command and set aclfile to save them.",
+                    ),
+                    (Some(path), "save") => match self.acl.save(&path) {
+                        Ok(()) => ok(),
+                        Err(e) => err(&format!(
+                            "There was an error trying to save the ACLs: {}",
+                            e
+                        )),
+                    },
+                    (Some(path), _) => match self.acl.load(&path) {
+                        Ok(()) => {
+                            self.kill_clients_of_removed_users();
+                            ok()
+                        }
+                        Err(e) => err(&e),
+                    },
+                }
+            }
+            ("help", []) => help(&[
+                "ACL <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "CAT [<category>]",
+                "    List all commands that belong to <category>, or all command categories",
+                "    when no category is specified.",
+                "DELUSER <username> [<username> ...]",
+                "    Delete a list of users.",
+                "GETUSER <username>",
+                "    Get the user's details.",
+                "LIST",
+                "    Show users details in config file format.",
+                "LOAD",
+                "    Reload users from the ACL file.",
+                "LOG [<count> | RESET]",
+                "    Show the ACL log entries.",
+                "SAVE",
+                "    Save the current config to the ACL file.",
+                "SETUSER <username> <attribute> [<attribute> ...]",
+                "    Create or modify a user with the specified attributes.",
+                "USERS",
+                "    List all the registered usernames.",
+                "WHOAMI",
+                "    Return the current connection username.",
+            ]),
+            (
+                "setuser" | "getuser" | "deluser" | "list" | "users" | "whoami" | "cat" | "log"
+                | "save" | "load" | "help",
+                _,
+            ) => err(&format!(
+                "wrong number of arguments for 'acl|{}' command",
+                subcommand
+            )),
+            _ => err(&format!(
+                "unknown subcommand '{}'. Try ACL HELP.",
+                subcommand
+            )),
+        };
+        Ok(reply)
+    }
+
+    /// `COMMAND [COUNT | INFO [name ...] | DOCS [name ...] | LIST [FILTERBY filter value] |
+    /// GETKEYS command [argument ...]]`, served from the specs of the registered commands.
+    fn command(&self, arguments: &[Vec<u8>]) -> Resp {
+        let subcommand = arguments
+            .first()
+            .map(|s| String::from_utf8_lossy(s).to_lowercase())
+            .unwrap_or_default();
+        let names: Vec<String> = arguments
+            .iter()
+            .skip(1)
+            .map(|name| String::from_utf8_lossy(name).to_lowercase())
+            .collect();
+        let commands = self.commands.read().unwrap();
+        let all_commands = || commands.specs().map(|spec| (spec.name.to_string(), spec));
+
+        match (subcommand.as_str(), &names[..]) {
+            ("", _) | ("info", []) => Resp::Array(Some(
+                all_commands()
+                    .map(|(name, spec)| spec.info_reply(&name))
+                    .collect(),
+            )),
+            ("count", []) => Resp::Integer(commands.len() as i64),
+            ("info", names) => Resp::Array(Some(
+                names
+                    .iter()
+                    .map(|name| match commands.lookup(name) {
+                        Some(spec) => spec.info_reply(name),
+                        None => Resp::Array(None),
+                    })
+                    .collect(),
+            )),
+            ("docs", names) => {
+                let specs: Vec<_> = match names {
+                    [] => all_commands().collect(),
+                    names => names
+                        .iter()
+                        .filter_map(|name| Some((name.clone(), commands.lookup(name)?)))
+                        .collect(),
+                };
+                Resp::Map(
+                    specs
+                        .into_iter()
+                        .map(|(name, spec)| {
+                            let docs = spec.docs_reply(&name);
+                            (Resp::BulkString(Some(name.into())), docs)
+                        })
+                        .collect(),
+                )
+            }
+            ("list", filter) => {
+                let filter = match filter {
+                    [] => None,
+                    [filterby, kind, value]
+                        if filterby == "filterby"
+                            && ["module", "aclcat", "pattern"].contains(&kind.as_str()) =>
+                    {
+                        Some((kind.as_str(), value.as_str()))
+                    }
+                    _ => return err("syntax error"),
+                };
+                let module_commands = match filter {
+                    Some(("module", module)) => self.modules.commands_of(module),
+                    _ => vec![],
+                };
+                let matches = |name: &str, categories: &[&str]| match filter {
+                    None => true,
+                    Some(("module", _)) => name
+                        .split('|')
+                        .next()
+                        .is_some_and(|name| module_commands.iter().any(|command| command == name)),
+                    Some(("aclcat", category)) => categories.contains(&category),
+                    Some((_, pattern)) => glob_match_nocase(pattern.as_bytes(), name.as_bytes()),
+                };
+                let mut list = vec![];
+                for spec in commands.specs() {
+                    if matches(spec.name, spec.categories) {
+                        list.push(spec.name.to_string());
+                    }
+                    for subcommand in spec.subcommands {
+                        let name = format!("{}|{}", spec.name, subcommand.name);
+                        if matches(&name, subcommand.categories) {
+                            list.push(name);
+                        }
+                    }
+                }
+                Resp::Array(Some(
+                    list.into_iter()
+                        .map(|name| Resp::BulkString(Some(name.into())))
+                        .collect(),
+                ))
+            }
+            ("getkeys", [_, ..]) => {
+                let args = &arguments[1..];
+                let name = String::from_utf8_lossy(&args[0]);
+                let spec = match commands.spec(&name) {
+                    Some(spec) => args
+                        .get(1)
+                        .and_then(|subcommand| {
+                            spec.subcommand(&String::from_utf8_lossy(subcommand))
+                        })
+                        .unwrap_or(spec),
+                    None => return err("Invalid command specified"),
+                };
+                if !spec.accepts(args.len()) {
+                    return err("Invalid number of arguments specified for command");
+                }
+                match spec.keys(args) {
+                    keys if keys.is_empty() => err("The command has no key arguments"),
+                    keys => Resp::Array(Some(
+                        keys.into_iter()
+                            .map(|key| Resp::BulkString(Some(Bytes::copy_from_slice(key))))
+                            .collect(),
+                    )),
+                }
+            }
+            ("help", []) => help(&[
+                "COMMAND <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "(no subcommand)",
+                "    Return details about all commands.",
+                "COUNT",
+                "    Return the total number of commands in this server.",
+                "LIST",
+                "    Return a list of all commands in this server.",
+                "INFO [<command-name> ...]",
+                "    Return details about multiple commands.",
+                "    If no command names are given, documentation details for all",
+                "    commands are returned.",
+                "DOCS [<command-name> ...]",
+                "    Return documentation details about multiple commands.",
+                "    If no command names are given, documentation details for all",
+                "    commands are returned.",
+                "GETKEYS <full-command>",
+                "    Return the keys from a full command.",
+                "LIST [FILTERBY (MODULE <module-name>|ACLCAT <category>|PATTERN <pattern>)]",
+                "    Return a list of all commands in this server.",
+            ]),
+            ("count" | "getkeys" | "help", _) => err(&format!(
+                "wrong number of arguments for 'command|{}' command",
+                subcommand
+            )),
+            _ => err(&format!(
+                "unknown subcommand '{}'. Try COMMAND HELP.",
+                subcommand
+            )),
+        }
+    }
+
+    /// `MODULE LOAD|UNLOAD|LIST|HELP`. Modules are loaded by the name they were made
+    /// available with, see [`crate::modules::provide`].
+    fn module(&self, arguments: &[String]) -> Resp {
+        let subcommand = arguments
+            .first()
+            .map(|s| s.to_lowercase())
+            .unwrap_or_default();
+        match (subcommand.as_str(), &arguments[1.min(arguments.len())..]) {
+            ("load", [name, arguments @ ..]) => match self.load_module(name, arguments) {
+                Ok(()) => {
+                    eprintln!("Module '{}' loaded", name);
+                    ok()
+                }
+                Err(e) => {
+                    eprintln!("Module {} failed to load: {}", name, e);
+                    err("Error loading the extension. Please check the server logs.")
+                }
+            },
+            ("unload", [name]) => match self.unload_module(name) {
+                Ok(()) => {
+                    eprintln!("Module {} unloaded", name);
+                    ok()
+                }
+                Err(e) => err(&format!("Error unloading module: {}", e)),
+            },
+            ("list", []) => Resp::Array(Some(
+                self.modules
+                    .list()
+                    .into_iter()
+                    .map(|(name, version, arguments)| {
+                        let bulk = |value: String| Resp::BulkString(Some(value.into()));
+                        Resp::Map(vec![
+                            (bulk("name".to_string()), bulk(name.clone())),
+                            (bulk("ver".to_string()), Resp::Integer(version)),
+                            (bulk("path".to_string()), bulk(name)),
+                            (
+                                bulk("args".to_string()),
+                                Resp::Array(Some(arguments.into_iter().map(bulk).collect())),
+                            ),
+                        ])
+                    })
+                    .collect(),
+            )),
+            ("help", []) => help(&[
+                "MODULE <subcommand> [<arg> [value] [opt] ...]. Subcommands are:",
+                "LIST",
+                "    Return a list of loaded modules.",
+                "LOAD <name> [<arg> ...]",
+                "    Load a module made available by the program embedding the server,",
+                "    passing to it any additional arguments.",
+                "UNLOAD <name>",
+                "    Unload a module.",
+                "HELP",
+                "    Print this help.",
+            ]),
+            ("load" | "unload" | "list" | "help", _) => err(&format!(
+                "wrong number of arguments for 'module|{}' command",
+                subcommand
+            )),
+            _ => err(&format!(
+                "unknown subcommand '{}'. Try MODULE HELP.",
+                subcommand
+            )),
+        }
+    }
+
+    /// `SHUTDOWN` only requests the shutdown, [`ServerState::shut_down`] carries it out.
+    /// Nothing is replied on success, the connection gets closed like all the others.
+    fn shutdown_command(&self, client: &Client, arguments: &[String]) -> SerirResult<Resp> {
+        match ShutdownCommand::parse(arguments) {
+            Ok(ShutdownCommand::Shutdown(options)) => {
+                eprintln!("User requested shutdown...");
+                self.shutdown.request(options);
+                // the connection is closed without a reply, like all the others
+                client.set_reply_mode(ReplyMode::Off);
+                Ok(ok())
+            }
+            // shutdowns never wait for replicas, so there is never one to abort
+            Ok(ShutdownCommand::Abort) => Ok(err("No shutdown in progress.")),
+            Err(e) => Ok(err(&e)),
+        }
+    }
+
+    /// Stops the server gracefully once a shutdown was requested, by `SHUTDOWN` or a
+    /// signal: the listeners stop accepting, every connection finishes the request it is
+    /// running and gets its reply before being closed, then the dataset is saved if asked
+    /// to. Connections get `shutdown-timeout` seconds to finish, none with `NOW`.
+    pub async fn shut_down(&self, options: ShutdownOptions) {
+        self.shutdown.request(options);
+        self.modules.stop_threads();
+        // paused requests couldn't finish otherwise
+        self.clients.unpause();
+        for client in self.clients.list() {
+            client.kill();
+        }
+        let timeout = Duration::from_secs(self.config.read().unwrap().shutdown_timeout);
+        if !options.now {
+            let drained = tokio::time::timeout(timeout, async {
+                while !self.clients.is_empty() {
+                    tokio::time::sleep(Duration::from_millis(10)).await;
+                }
+            })
+            .await;
+            if drained.is_err() {
+                eprintln!(
+                    "{} connections still busy after {}s, closing them",
+                    self.clients.len(),
+                    timeout.as_secs()
+                );
+            }
+        }
+        let config = self.config.read().unwrap();
+        if options
+            .save
+            .unwrap_or(!config.save.is_empty() || config.appendonly)
+        {
+            eprintln!("serir doesn't persist the dataset yet, there is nothing to save");
+        }
+    }
+
+    /// Disconnects clients authenticated as users that were deleted.
+    fn kill_clients_of_removed_users(&self) {
+        for client in self.clients.list() {
+            if client.is_authenticated() && !self.acl.exists(&client.user()) {
+                client.kill();
+            }
+        }
+    }
+}
+
+fn help(lines: &[&str]) -> Resp {
+    Resp::Array(Some(
+        lines
+            .iter()
+            .map(|line| Resp::SimpleString(line.as_bytes().to_vec()))
+            .collect(),
+    ))
+}
+
+fn ok() -> Resp {
+    Resp::SimpleString(b"OK".to_vec())
+}
+
+fn err(message: &str) -> Resp {
+    Resp::Error(format!("ERR {}", message).into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands::COMMAND_TABLE;
+
+    fn request(state: &ServerState, client: &Client, args: &[&str]) -> Vec<u8> {
+        let request = Resp::Array(Some(
+            args.iter()
+                .map(|arg| Resp::BulkString(Some(Bytes::copy_from_slice(arg.as_bytes()))))
+                .collect(),
+        ));
+        let request = state.parse_request(client, request).unwrap();
+        state
+            .exec_request(client, request)
+            .unwrap()
+            .serialize_for(client.protocol())
+            .unwrap()
+    }
+
+    fn config(state: &ServerState, arguments: &[&str]) -> Vec<u8> {
+        let arguments = arguments.iter().map(|s| s.to_string()).collect();
+        state.exec(Command::Config(arguments)).unwrap()
+    }
+
+    #[test]
+    fn answers_config_get_like_redis_benchmark_expects() {
+        let state = ServerState::new(Config::default());
+        assert_eq!(
+            config(&state, &["GET", "save"]),
+            b"*2\r\n$4\r\nsave\r\n$23\r\n3600 1 300 100 60 10000\r\n".to_vec()
+        );
+        assert_eq!(
+            config(&state, &["GET", "appendonly"]),
+            b"*2\r\n$10\r\nappendonly\r\n$2\r\nno\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn sets_and_gets_config_at_runtime() {
+        let state = ServerState::new(Config::default());
+        assert_eq!(
+            config(&state, &["SET", "maxmemory", "1kb"]),
+            b"+OK\r\n".to_vec()
+        );
+        assert_eq!(
+            config(&state, &["GET", "maxmemory"]),
+            b"*2\r\n$9\r\nmaxmemory\r\n$4\r\n1024\r\n".to_vec()
+        );
+        assert!(config(&state, &["SET", "port", "1"]).starts_with(b"-ERR"));
+        assert!(config(&state, &["SET", "maxmemory"]).starts_with(b"-ERR"));
+    }
+
+    #[test]
+    fn logs_slow_commands_with_client_and_arguments() {
+        let state = ServerState::new(Config::default());
+        config(&state, &["SET", "slowlog-log-slower-than", "0"]);
+        let client = Client::new(1, "127.0.0.1:5555".to_string(), String::new());
+        request(&state, &client, &["GET", "key"]);
+
+        let entries = state.slowlog.get(None);
+        assert_eq!(entries.len(), 1);
+        assert_eq!(entries[0].args, vec![b"GET".to_vec(), b"key".to_vec()]);
+        assert_eq!(entries[0].client_addr, "127.0.0.1:5555");
+        assert_eq!(
+            state
+                .exec(Command::Slowlog(vec!["LEN".to_string()]))
+                .unwrap(),
+            b":1\r\n".to_vec()
+        );
+        assert!(state
+            .exec(Command::Slowlog(vec!["GET".to_string(), "-2".to_string()]))
+            .unwrap()
+            .starts_with(b"-ERR count should be greater than or equal to -1"));
+    }
+
+    #[test]
+    fn feeds_commands_to_monitors() {
+        let state = ServerState::new(Config::default());
+        let monitor = Client::new(1, String::new(), String::new());
+        request(&state, &monitor, &["MONITOR"]);
+        assert!(monitor.is_monitor());
+
+        let mut feed = state.monitors.subscribe();
+        let client = Client::new(2, "127.0.0.1:5555".to_string(), String::new());
+        request(&state, &client, &["GET", "key"]);
+
+        let line = feed.try_recv().unwrap();
+        assert!(line.ends_with(b" [0 127.0.0.1:5555] \"GET\" \"key\"\r\n"));
+        assert!(feed.try_recv().is_err());
+    }
+
+    #[test]
+    fn reports_latency_histogram_per_command() {
+        let state = ServerState::new(Config::default());
+        state.exec(Command::Get(b"key".to_vec())).unwrap();
+        let reply = state
+            .exec(Command::Latency(vec![
+                "HISTOGRAM".to_string(),
+                "get".to_string(),
+                "set".to_string(),
+            ]))
+            .unwrap();
+        assert!(reply.starts_with(b"*2\r\n$3\r\nget\r\n*4\r\n$5\r\ncalls\r\n:1\r\n"));
+        assert!(state
+            .exec(Command::Latency(vec!["LATEST".to_string()]))
+            .unwrap()
+            .starts_with(b"*0\r\n"));
+    }
+
+    #[test]
+    fn names_lists_and_kills_clients() {
+        let state = ServerState::new(Config::default());
+        let first = state
+            .clients
+            .register("127.0.0.1:1".to_string(), "127.0.0.1:6379".to_string());
+        let second = state
+            .clients
+            .register("127.0.0.1:2".to_string(), "127.0.0.1:6379".to_string());
+
+        assert_eq!(
+            request(&state, &first, &["CLIENT", "ID"]),
+            b":1\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &first, &["CLIENT", "GETNAME"]),
+            b"$-1\r\n".to_vec()
+        );
+        request(&state, &first, &["CLIENT", "SETNAME", "first"]);
+        assert_eq!(
+            request(&state, &first, &["CLIENT", "GETNAME"]),
+            b"$5\r\nfirst\r\n".to_vec()
+        );
+        let list = request(&state, &second, &["CLIENT", "LIST"]);
+        let list = String::from_utf8_lossy(&list);
+        assert!(list.contains("id=1 addr=127.0.0.1:1 laddr=127.0.0.1:6379 name=first "));
+        assert!(list.contains("id=2 addr=127.0.0.1:2 "));
+        assert!(list.contains(" cmd=client "));
+        let list = request(&state, &second, &["CLIENT", "LIST", "ID", "2"]);
+        assert!(!String::from_utf8_lossy(&list).contains("id=1 "));
+
+        assert_eq!(
+            request(&state, &second, &["CLIENT", "KILL", "ID", "2"]),
+            b":0\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &second, &["CLIENT", "KILL", "USER", "default"]),
+            b":1\r\n".to_vec()
+        );
+        assert!(first.is_killed());
+        assert!(!second.is_killed());
+        assert_eq!(
+            request(&state, &second, &["CLIENT", "KILL", "127.0.0.1:9"]),
+            b"-ERR No such client\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn pauses_and_unpauses_clients() {
+        let state = ServerState::new(Config::default());
+        let client = Client::new(1, String::new(), String::new());
+        request(&state, &client, &["CLIENT", "PAUSE", "100000", "WRITE"]);
+        assert!(state.clients.is_paused());
+        assert!(request(&state, &client, &["CLIENT", "PAUSE", "-1"]).starts_with(b"-ERR"));
+        request(&state, &client, &["CLIENT", "UNPAUSE"]);
+        assert!(!state.clients.is_paused());
+    }
+
+    #[test]
+    fn refuses_rewrite_without_config_file() {
+        let state = ServerState::new(Config::default());
+        assert!(config(&state, &["REWRITE"]).starts_with(b"-ERR"));
+    }
+
+    #[test]
+    fn requires_authentication_with_requirepass() {
+        let config = Config {
+            requirepass: Some("secret".to_string()),
+            ..Config::default()
+        };
+        let state = ServerState::new(config);
+        let client = Client::new(1, String::new(), String::new());
+
+        assert_eq!(
+            request(&state, &client, &["GET", "key"]),
+            b"-NOAUTH Authentication required.\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["AUTH", "wrong"]),
+            b"-WRONGPASS invalid username-password pair or user is disabled.\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["AUTH", "secret"]),
+            b"+OK\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["GET", "key"]),
+            b"$-1\r\n".to_vec()
+        );
+
+        // the failed attempt was logged, the password never shows up in the slow log
+        let log = request(&state, &client, &["ACL", "LOG"]);
+        assert!(String::from_utf8_lossy(&log).contains("\r\nauth\r\n"));
+        state.slowlog.configure(0, 128);
+        request(&state, &client, &["AUTH", "secret"]);
+        let entries = state.slowlog.get(Some(1));
+        assert_eq!(
+            entries[0].args,
+            vec![b"AUTH".to_vec(), b"(redacted)".to_vec()]
+        );
+    }
+
+    #[test]
+    fn compares_passwords_byte_for_byte() {
+        let state = ServerState::new(Config::default());
+        let client = Client::new(1, String::new(), String::new());
+        let request = |args: &[&[u8]]| {
+            let request = Resp::Array(Some(
+                args.iter()
+                    .map(|arg| Resp::BulkString(Some(Bytes::copy_from_slice(arg))))
+                    .collect(),
+            ));
+            match state.parse_request(&client, request) {
+                Ok(request) => state.exec_request(&client, request).unwrap(),
+                Err(SerirError::CommandError(message)) => Resp::Error(message.into_bytes()),
+                Err(e) => panic!("{}", e),
+            }
+        };
+        let wrongpass =
+            Resp::Error(b"WRONGPASS invalid username-password pair or user is disabled.".to_vec());
+
+        request(&[
+            b"ACL", b"SETUSER", b"alice", b"on", b">\xfe", b"+@all", b"~*",
+        ]);
+        assert_eq!(request(&[b"AUTH", b"alice", b"\xff"]), wrongpass);
+        assert_eq!(
+            request(&[b"HELLO", b"3", b"AUTH", b"alice", b"\xff"]),
+            wrongpass
+        );
+        assert_eq!(request(&[b"AUTH", b"alice", b"\xfe"]), ok());
+        assert!(matches!(
+            request(&[b"CONFIG", b"SET", b"requirepass", b"\xff"]),
+            Resp::Error(message) if message.ends_with(b"must be valid UTF-8")
+        ));
+    }
+
+    #[test]
+    fn negotiates_the_protocol_with_hello() {
+        let config = Config {
+            requirepass: Some("secret".to_string()),
+            ..Config::default()
+        };
+        let state = ServerState::new(config);
+        state.slowlog.configure(0, 128);
+        let client = Client::new(7, String::new(), String::new());
+
+        assert!(request(&state, &client, &["HELLO", "3"]).starts_with(b"-NOAUTH HELLO must"));
+        assert_eq!(
+            request(&state, &client, &["HELLO", "4"]),
+            b"-NOPROTO unsupported protocol version\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["HELLO", "three"]),
+            b"-ERR Protocol version is not an integer or out of range\r\n".to_vec()
+        );
+        assert_eq!(client.protocol(), Protocol::Resp2);
+
+        let reply = request(
+            &state,
+            &client,
+            &["HELLO", "3", "AUTH", "default", "secret", "SETNAME", "app"],
+        );
+        assert!(reply.starts_with(b"%7\r\n$6\r\nserver\r\n$5\r\nredis\r\n"));
+        assert!(String::from_utf8_lossy(&reply).contains("$5\r\nproto\r\n:3\r\n$2\r\nid\r\n:7\r\n"));
+        assert_eq!(client.protocol(), Protocol::Resp3);
+        assert_eq!(client.name(), "app");
+        assert_eq!(request(&state, &client, &["GET", "key"]), b"_\r\n".to_vec());
+        assert!(request(&state, &client, &["CONFIG", "GET", "port"]).starts_with(b"%1\r\n"));
+
+        request(&state, &client, &["HELLO", "2"]);
+        assert_eq!(
+            request(&state, &client, &["GET", "key"]),
+            b"$-1\r\n".to_vec()
+        );
+        // the password never shows up in the slow log
+        let entries = state.slowlog.get(None);
+        assert!(entries.iter().any(|entry| entry.args[0] == b"HELLO"));
+        assert!(entries
+            .iter()
+            .all(|entry| !entry.args.contains(&b"secret".to_vec())));
+    }
+
+    #[test]
+    fn invalidates_keys_tracking_clients_read() {
+        let state = ServerState::new(Config::default());
+        let reader = state.clients.register(String::new(), String::new());
+        let writer = state.clients.register(String::new(), String::new());
+        request(&state, &reader, &["HELLO", "3"]);
+        assert_eq!(
+            request(&state, &reader, &["CLIENT", "GETREDIR"]),
+            b":-1\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &reader, &["CLIENT", "TRACKING", "ON", "NOLOOP"]),
+            b"+OK\r\n".to_vec()
+        );
+        assert!(reader.info_line().contains(" flags=t "));
+
+        request(&state, &reader, &["MGET", "a", "b"]);
+        request(&state, &writer, &["MSET", "a", "1", "c", "1"]);
+        let invalidate = |keys: &[&str]| {
+            Resp::Push(vec![
+                Resp::BulkString(Some(Bytes::from_static(b"invalidate"))),
+                Resp::Array(Some(
+                    keys.iter()
+                        .map(|key| Resp::BulkString(Some(Bytes::copy_from_slice(key.as_bytes()))))
+                        .collect(),
+                )),
+            ])
+        };
+        assert_eq!(reader.take_pushes(), vec![invalidate(&["a"])]);
+        // keys are invalidated once until read again, and never for the reader's writes
+        request(&state, &writer, &["DEL", "a"]);
+        request(&state, &reader, &["SET", "b", "1"]);
+        assert!(reader.take_pushes().is_empty());
+
+        // RESP2 clients get the messages through the client they redirect to
+        let redirect = writer.id.to_string();
+        assert!(
+            request(&state, &reader, &["CLIENT", "TRACKING", "ON", "BCAST"])
+                .starts_with(b"-ERR You can't switch BCAST mode")
+        );
+        request(&state, &reader, &["CLIENT", "TRACKING", "OFF"]);
+        assert_eq!(
+            request(
+                &state,
+                &reader,
+                &["CLIENT", "TRACKING", "ON", "REDIRECT", &redirect, "BCAST", "PREFIX", "user:"]
+            ),
+            b"+OK\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &reader, &["CLIENT", "GETREDIR"]),
+            format!(":{}\r\n", redirect).into_bytes()
+        );
+        request(&state, &writer, &["SET", "user:1", "v"]);
+        request(&state, &writer, &["SET", "post:1", "v"]);
+        assert!(reader.take_pushes().is_empty());
+        let pushes = writer.take_pushes();
+        assert_eq!(
+            pushes[0].serialize().unwrap(),
+            b"*3\r\n$7\r\nmessage\r\n$20\r\n__redis__:invalidate\r\n*1\r\n$6\r\nuser:1\r\n"
+                .to_vec()
+        );
+        assert_eq!(pushes.len(), 1);
+
+        // the client redirected to went away
+        state.clients.unregister(writer.id);
+        request(&state, &reader, &["DEL", "user:1"]);
+        assert_eq!(
+            reader.take_pushes(),
+            vec![Resp::Push(vec![
+                Resp::BulkString(Some(Bytes::from_static(b"tracking-redir-broken"))),
+                Resp::Integer(writer.id as i64),
+            ])]
+        );
+        let info = request(&state, &reader, &["CLIENT", "TRACKINGINFO"]);
+        assert!(String::from_utf8_lossy(&info).contains("$15\r\nbroken_redirect\r\n"));
+        state.disable_tracking(&reader);
+        assert_eq!(state.tracking.num_clients(), 0);
+    }
+
+    #[test]
+    fn tracks_keys_only_as_told_in_optin_and_optout_modes() {
+        let state = ServerState::new(Config::default());
+        let client = state.clients.register(String::new(), String::new());
+        request(&state, &client, &["HELLO", "3"]);
+        assert!(request(&state, &client, &["CLIENT", "CACHING", "YES"])
+            .starts_with(b"-ERR CLIENT CACHING can be called only"));
+        request(&state, &client, &["CLIENT", "TRACKING", "ON", "OPTIN"]);
+        assert!(request(&state, &client, &["CLIENT", "CACHING", "NO"])
+            .starts_with(b"-ERR CLIENT CACHING NO is only valid"));
+
+        request(&state, &client, &["GET", "uncached"]);
+        request(&state, &client, &["CLIENT", "CACHING", "YES"]);
+        request(&state, &client, &["GET", "cached"]);
+        request(&state, &client, &["GET", "uncached-too"]);
+        assert_eq!(state.tracking.num_keys(), 1);
+
+        request(&state, &client, &["CLIENT", "TRACKING", "OFF"]);
+        request(&state, &client, &["CLIENT", "TRACKING", "ON", "OPTOUT"]);
+        request(&state, &client, &["CLIENT", "CACHING", "NO"]);
+        request(&state, &client, &["GET", "uncached"]);
+        request(&state, &client, &["GET", "cached-too"]);
+        assert_eq!(state.tracking.num_keys(), 2);
+    }
+
+    #[test]
+    fn describes_commands() {
+        let state = ServerState::new(Config::default());
+        let client = Client::new(1, String::new(), String::new());
+        assert_eq!(
+            request(&state, &client, &["COMMAND", "COUNT"]),
+            format!(":{}\r\n", COMMAND_TABLE.len()).into_bytes()
+        );
+        let info = request(
+            &state,
+            &client,
+            &["COMMAND", "INFO", "get", "nosuchcommand"],
+        );
+        assert!(info.starts_with(
+            b"*2\r\n*10\r\n$3\r\nget\r\n:2\r\n*2\r\n+readonly\r\n+fast\r\n:1\r\n:1\r\n:1\r\n"
+        ));
+        assert!(info.ends_with(b"*-1\r\n"));
+        let all = request(&state, &client, &["COMMAND"]);
+        assert!(all.starts_with(format!("*{}\r\n", COMMAND_TABLE.len()).as_bytes()));
+
+        request(&state, &client, &["HELLO", "3"]);
+        let docs = request(
+            &state,
+            &client,
+            &["COMMAND", "DOCS", "persist", "nosuchcommand"],
+        );
+        assert!(docs.starts_with(b"%1\r\n$7\r\npersist\r\n%4\r\n$7\r\nsummary\r\n"));
+        let docs = request(&state, &client, &["COMMAND", "DOCS", "config"]);
+        assert!(String::from_utf8_lossy(&docs).contains("$10\r\nconfig|get\r\n"));
+
+        assert_eq!(
+            request(
+                &state,
+                &client,
+                &["COMMAND", "LIST", "FILTERBY", "PATTERN", "*expire"]
+            ),
+            b"*2\r\n$6\r\nexpire\r\n$7\r\npexpire\r\n".to_vec()
+        );
+        let admin = request(
+            &state,
+            &client,
+            &["COMMAND", "LIST", "FILTERBY", "ACLCAT", "admin"],
+        );
+        assert!(String::from_utf8_lossy(&admin).contains("$11\r\nclient|kill\r\n"));
+        assert!(!String::from_utf8_lossy(&admin).contains("$9\r\nclient|id\r\n"));
+        assert_eq!(
+            request(
+                &state,
+                &client,
+                &["COMMAND", "LIST", "FILTERBY", "MODULE", "json"]
+            ),
+            b"*0\r\n".to_vec()
+        );
+
+        assert_eq!(
+            request(
+                &state,
+                &client,
+                &["COMMAND", "GETKEYS", "MSET", "a", "1", "b", "2"]
+            ),
+            b"*2\r\n$1\r\na\r\n$1\r\nb\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["COMMAND", "GETKEYS", "MSET", "a"]),
+            b"-ERR Invalid number of arguments specified for command\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["COMMAND", "GETKEYS", "CLIENT", "ID"]),
+            b"-ERR The command has no key arguments\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["COMMAND", "GETKEYS", "FOO"]),
+            b"-ERR Invalid command specified\r\n".to_vec()
+        );
+    }
+
+    #[test]
+    fn enforces_acl_permissions() {
+        let state = ServerState::new(Config::default());
+        let admin = Client::new(1, String::new(), String::new());
+        let client = state
+            .clients
+            .register("127.0.0.1:2".to_string(), "127.0.0.1:6379".to_string());
+        request(
+            &state,
+            &admin,
+            &[
+                "ACL", "SETUSER", "cache", "on", ">pw", "~cache:*", "+@read", "+set",
+            ],
+        );
+        request(&state, &client, &["AUTH", "cache", "pw"]);
+        assert_eq!(
+            request(&state, &client, &["ACL", "WHOAMI"]),
+            b"-NOPERM User cache has no permissions to run the 'acl|whoami' command\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["SET", "cache:1", "v"]),
+            b"+OK\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["GET", "other"]),
+            b"-NOPERM No permissions to access a key\r\n".to_vec()
+        );
+        assert_eq!(
+            request(&state, &client, &["DEL", "cache:1"]),
+            b"-NOPERM User cache has no permissions to run the 'del' command\r\n".to_vec()
+        );
+        assert!(client.info_line().contains(" user=cache "));
+
+        let users = request(&state, &admin, &["ACL", "USERS"]);
+        assert_eq!(users, b"*2\r\n$5\r\ncache\r\n$7\r\ndefault\r\n".to_vec());
+        assert_eq!(
+            request(&state, &admin, &["ACL", "DELUSER", "cache"]),
+            b":1\r\n".to_vec()
+        );
+        assert!(client.is_killed());
+        assert!(request(&state, &admin, &["ACL", "SAVE"]).starts_with(b"-ERR"));
+    }
+}
@@ -0,0 +1,341 @@
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+/// Number of samples averaged by instantaneous metrics.
+const INSTANTANEOUS_SAMPLES: usize = 16;
+/// Number of latency histogram buckets. Bucket `i` counts calls that took at most
+/// `2^i` microseconds, the last one also counts everything slower.
+pub const LATENCY_BUCKETS: usize = 24;
+
+/// Server-wide counters reported by `INFO`, reset by `CONFIG RESETSTAT`.
+#[derive(Debug)]
+pub struct Stats {
+    started: Instant,
+    connected_clients: AtomicUsize,
+    total_connections_received: AtomicU64,
+    total_commands_processed: AtomicU64,
+    total_net_input_bytes: AtomicU64,
+    total_net_output_bytes: AtomicU64,
+    rejected_connections: AtomicU64,
+    client_query_buffer_limit_disconnections: AtomicU64,
+    client_output_buffer_limit_disconnections: AtomicU64,
+    used_memory_peak: AtomicUsize,
+    commands_in_flight: AtomicUsize,
+    commands: RwLock<HashMap<&'static str, CommandStats>>,
+    instantaneous: Mutex<InstantaneousMetrics>,
+}
+
+/// Calls of a single command.
+#[derive(Debug, Default)]
+pub struct CommandStats {
+    calls: AtomicU64,
+    usec: AtomicU64,
+    latency: [AtomicU64; LATENCY_BUCKETS],
+}
+
+impl CommandStats {
+    fn record(&self, usec: u64) {
+        self.calls.fetch_add(1, Ordering::Relaxed);
+        self.usec.fetch_add(usec, Ordering::Relaxed);
+        let bucket = (u64::BITS - usec.saturating_sub(1).leading_zeros()) as usize;
+        self.latency[bucket.min(LATENCY_BUCKETS - 1)].fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// Number of calls per latency bucket, see [`LATENCY_BUCKETS`].
+    pub fn latency_buckets(&self) -> [u64; LATENCY_BUCKETS] {
+        let mut buckets = [0; LATENCY_BUCKETS];
+        for (bucket, count) in buckets.iter_mut().zip(&self.latency) {
+            *bucket = count.load(Ordering::Relaxed);
+        }
+        buckets
+    }
+
+    pub fn calls(&self) -> u64 {
+        self.calls.load(Ordering::Relaxed)
+    }
+
+    /// Total time spent executing the command, in microseconds.
+    pub fn usec(&self) -> u64 {
+        self.usec.load(Ordering::Relaxed)
+    }
+}
+
+/// Tracks how fast a counter grows by sampling it periodically and averaging the last
+/// few samples, like Redis does for `instantaneous_ops_per_sec`.
+#[derive(Debug, Default)]
+struct InstantaneousMetric {
+    samples: [f64; INSTANTANEOUS_SAMPLES],
+    next: usize,
+    last_value: u64,
+    last_sampled: Option<Instant>,
+}
+
+impl InstantaneousMetric {
+    fn sample(&mut self, value: u64, now: Instant) {
+        if let Some(last_sampled) = self.last_sampled {
+            let elapsed = now.duration_since(last_sampled).as_secs_f64();
+            if elapsed > 0.0 {
+                let rate = value.saturating_sub(self.last_value) as f64 / elapsed;
+                self.samples[self.next] = rate;
+                self.next = (self.next + 1) % INSTANTANEOUS_SAMPLES;
+            }
+        }
+        self.last_value = value;
+        self.last_sampled = Some(now);
+    }
+
+    fn per_sec(&self) -> f64 {
+        self.samples.iter().sum::<f64>() / INSTANTANEOUS_SAMPLES as f64
+    }
+}
+
+#[derive(Debug, Default)]
+struct InstantaneousMetrics {
+    ops: InstantaneousMetric,
+    net_input: InstantaneousMetric,
+    net_output: InstantaneousMetric,
+}
+
+impl Stats {
+    pub fn new() -> Self {
+        Self {
+            started: Instant::now(),
+            connected_clients: AtomicUsize::new(0),
+            total_connections_received: AtomicU64::new(0),
+            total_commands_processed: AtomicU64::new(0),
+            total_net_input_bytes: AtomicU64::new(0),
+            total_net_output_bytes: AtomicU64::new(0),
+            rejected_connections: AtomicU64::new(0),
+            client_query_buffer_limit_disconnections: AtomicU64::new(0),
+            client_output_buffer_limit_disconnections: AtomicU64::new(0),
+            used_memory_peak: AtomicUsize::new(0),
+            commands_in_flight: AtomicUsize::new(0),
+            commands: RwLock::new(HashMap::new()),
+            instantaneous: Mutex::new(InstantaneousMetrics::default()),
+        }
+    }
+
+    pub fn uptime(&self) -> Duration {
+        self.started.elapsed()
+    }
+
+    pub fn client_connected(&self) {
+        self.connected_clients.fetch_add(1, Ordering::Relaxed);
+        self.total_connections_received
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn client_disconnected(&self) {
+        self.connected_clients.fetch_sub(1, Ordering::Relaxed);
+    }
+
+    pub fn connection_rejected(&self) {
+        self.rejected_connections.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn query_buffer_limit_reached(&self) {
+        self.client_query_buffer_limit_disconnections
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn output_buffer_limit_reached(&self) {
+        self.client_output_buffer_limit_disconnections
+            .fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn net_input(&self, bytes: usize) {
+        self.total_net_input_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    pub fn net_output(&self, bytes: usize) {
+        self.total_net_output_bytes
+            .fetch_add(bytes as u64, Ordering::Relaxed);
+    }
+
+    /// Records that a command started executing, see [`Stats::commands_in_flight`].
+    pub fn command_started(&self) {
+        self.commands_in_flight.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn command_processed(&self, name: &'static str, duration: Duration) {
+        self.commands_in_flight.fetch_sub(1, Ordering::Relaxed);
+        self.total_commands_processed
+            .fetch_add(1, Ordering::Relaxed);
+
+        let usec = duration.as_micros() as u64;
+        if let Some(stats) = self.commands.read().unwrap().get(name) {
+            stats.record(usec);
+            return;
+        }
+        self.commands
+            .write()
+            .unwrap()
+            .entry(name)
+            .or_default()
+            .record(usec);
+    }
+
+    /// Records the current memory usage, to keep track of the peak.
+    pub fn memory_used(&self, used_memory: usize) {
+        self.used_memory_peak
+            .fetch_max(used_memory, Ordering::Relaxed);
+    }
+
+    /// Samples the counters behind the instantaneous metrics. Meant to be called
+    /// periodically.
+    pub fn sample(&self) {
+        let now = Instant::now();
+        let mut instantaneous = self.instantaneous.lock().unwrap();
+        instantaneous
+            .ops
+            .sample(self.total_commands_processed(), now);
+        instantaneous
+            .net_input
+            .sample(self.total_net_input_bytes(), now);
+        instantaneous
+            .net_output
+            .sample(self.total_net_output_bytes(), now);
+    }
+
+    pub fn reset(&self) {
+        self.total_connections_received.store(0, Ordering::Relaxed);
+        self.total_commands_processed.store(0, Ordering::Relaxed);
+        self.total_net_input_bytes.store(0, Ordering::Relaxed);
+        self.total_net_output_bytes.store(0, Ordering::Relaxed);
+        self.rejected_connections.store(0, Ordering::Relaxed);
+        self.client_query_buffer_limit_disconnections
+            .store(0, Ordering::Relaxed);
+        self.client_output_buffer_limit_disconnections
+            .store(0, Ordering::Relaxed);
+        self.used_memory_peak.store(0, Ordering::Relaxed);
+        self.commands.write().unwrap().clear();
+        *self.instantaneous.lock().unwrap() = InstantaneousMetrics::default();
+    }
+
+    pub fn connected_clients(&self) -> usize {
+        self.connected_clients.load(Ordering::Relaxed)
+    }
+
+    pub fn total_connections_received(&self) -> u64 {
+        self.total_connections_received.load(Ordering::Relaxed)
+    }
+
+    pub fn total_commands_processed(&self) -> u64 {
+        self.total_commands_processed.load(Ordering::Relaxed)
+    }
+
+    pub fn total_net_input_bytes(&self) -> u64 {
+        self.total_net_input_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn total_net_output_bytes(&self) -> u64 {
+        self.total_net_output_bytes.load(Ordering::Relaxed)
+    }
+
+    pub fn rejected_connections(&self) -> u64 {
+        self.rejected_connections.load(Ordering::Relaxed)
+    }
+
+    pub fn client_query_buffer_limit_disconnections(&self) -> u64 {
+        self.client_query_buffer_limit_disconnections
+            .load(Ordering::Relaxed)
+    }
+
+    pub fn client_output_buffer_limit_disconnections(&self) -> u64 {
+        self.client_output_buffer_limit_disconnections
+            .load(Ordering::Relaxed)
+    }
+
+    /// Number of commands currently executing or waiting for the shards they need.
+    pub fn commands_in_flight(&self) -> usize {
+        self.commands_in_flight.load(Ordering::Relaxed)
+    }
+
+    pub fn used_memory_peak(&self) -> usize {
+        self.used_memory_peak.load(Ordering::Relaxed)
+    }
+
+    pub fn instantaneous_ops_per_sec(&self) -> u64 {
+        self.instantaneous.lock().unwrap().ops.per_sec().round() as u64
+    }
+
+    pub fn instantaneous_input_kbps(&self) -> f64 {
+        self.instantaneous.lock().unwrap().net_input.per_sec() / 1024.0
+    }
+
+    pub fn instantaneous_output_kbps(&self) -> f64 {
+        self.instantaneous.lock().unwrap().net_output.per_sec() / 1024.0
+    }
+
+    /// Calls `f` with the name and stats of every command called so far, ordered by name.
+    pub fn for_each_command(&self, mut f: impl FnMut(&str, &CommandStats)) {
+        let commands = self.commands.read().unwrap();
+        let mut names: Vec<_> = commands.keys().collect();
+        names.sort();
+        for name in names {
+            f(name, &commands[name]);
+        }
+    }
+}
+
+impl Default for Stats {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn counts_commands_per_name() {
+        let stats = Stats::new();
+        (0..3).for_each(|_| stats.command_started());
+        stats.command_processed("get", Duration::from_micros(10));
+        stats.command_processed("get", Duration::from_micros(20));
+        stats.command_processed("set", Duration::from_micros(5));
+
+        let mut commands = vec![];
+        stats.for_each_command(|name, command| {
+            commands.push((name.to_string(), command.calls(), command.usec()))
+        });
+        assert_eq!(
+            commands,
+            vec![("get".to_string(), 2, 30), ("set".to_string(), 1, 5)]
+        );
+        assert_eq!(stats.total_commands_processed(), 3);
+        assert_eq!(stats.commands_in_flight(), 0);
+
+        stats.reset();
+        assert_eq!(stats.total_commands_processed(), 0);
+    }
+
+    #[test]
+    fn buckets_latencies_by_powers_of_two() {
+        let command = CommandStats::default();
+        for usec in [0, 1, 2, 3, 4, 5, 1000, u64::MAX] {
+            command.record(usec);
+        }
+
+        let buckets = command.latency_buckets();
+        assert_eq!(&buckets[..4], &[2, 1, 2, 1]);
+        // 1000us falls into the 1024us bucket
+        assert_eq!(buckets[10], 1);
+        assert_eq!(buckets[LATENCY_BUCKETS - 1], 1);
+    }
+
+    #[test]
+    fn averages_instantaneous_samples() {
+        let mut metric = InstantaneousMetric::default();
+        let start = Instant::now();
+        metric.sample(0, start);
+        for i in 1..=INSTANTANEOUS_SAMPLES as u64 {
+            metric.sample(i * 100, start + Duration::from_millis(i * 100));
+        }
+        assert!((metric.per_sec() - 1000.0).abs() < 1.0);
+    }
+}
@@ -1,54 +1,475 @@
-use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use bytes::Bytes;
+use indexmap::IndexMap;
+use rand::prelude::*;
 
 use crate::commands::Command;
 use crate::error::SerirResult;
+use crate::eviction::{lfu_decr, lfu_log_incr, EvictionPolicy, EvictionSettings, LFU_INIT_VAL};
 use crate::resp::Resp;
 
+/// Approximate memory used by the bookkeeping of a key on top of the key and value bytes.
+const ENTRY_OVERHEAD: usize = 64;
+/// Approximate memory used by the bookkeeping of a key's expire on top of the key bytes.
+const EXPIRE_OVERHEAD: usize = 24;
+/// Values at least this long are kept in the buffer they were received in. Shorter ones
+/// are copied, so that they don't keep alive the rest of a read buffer.
+const SHARED_VALUE_MIN_LEN: usize = 16 * 1024;
+
+/// Milliseconds since the unix epoch.
+pub fn now_ms() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as u64)
+        .unwrap_or_default()
+}
+
+/// Time to live of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Ttl {
+    /// The key doesn't exist.
+    Missing,
+    /// The key exists and has no expire.
+    Persistent,
+    /// The key expires after this long.
+    Expires(Duration),
+}
+
+impl Ttl {
+    /// The reply of `TTL`, in `unit` milliseconds, or `PTTL`: -2 for a missing key and
+    /// -1 for a key without expire. Like Redis, the time left is rounded to the nearest
+    /// unit, so a key with 999 milliseconds left has a `TTL` of 1.
+    pub fn as_integer(&self, unit: u64) -> i64 {
+        match self {
+            Ttl::Missing => -2,
+            Ttl::Persistent => -1,
+            Ttl::Expires(ttl) => ((ttl.as_millis() as u64 + unit / 2) / unit) as i64,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: Bytes,
+    /// Time of the last access in milliseconds, used by LRU eviction and `OBJECT IDLETIME`.
+    lru: u64,
+    /// Logarithmic access frequency counter, used by LFU eviction and `OBJECT FREQ`.
+    lfu: u8,
+    /// Time in minutes the LFU counter was last decremented.
+    lfu_decr_time: u64,
+}
+
+impl Entry {
+    fn new(value: Bytes, now: u64) -> Self {
+        Self {
+            value,
+            lru: now,
+            lfu: LFU_INIT_VAL,
+            lfu_decr_time: now / 60_000,
+        }
+    }
+
+    fn size(key: &[u8], value: &[u8]) -> usize {
+        key.len() + value.len() + ENTRY_OVERHEAD
+    }
+
+    /// LFU counter with the decay for the time elapsed since it was last decremented.
+    fn freq(&self, now: u64, settings: &EvictionSettings) -> u8 {
+        lfu_decr(
+            self.lfu,
+            self.lfu_decr_time,
+            now / 60_000,
+            settings.lfu_decay_time,
+        )
+    }
+
+    fn touch(&mut self, now: u64, settings: &EvictionSettings) {
+        self.lru = now;
+        self.lfu = lfu_log_incr(self.freq(now, settings), settings.lfu_log_factor);
+        self.lfu_decr_time = now / 60_000;
+    }
+}
+
 #[derive(Debug)]
 pub struct KeyValueStore {
-    store: HashMap<Vec<u8>, Vec<u8>>,
+    store: IndexMap<Vec<u8>, Entry>,
+    /// Expire times of volatile keys in milliseconds since the unix epoch.
+    expires: IndexMap<Vec<u8>, u64>,
+    used_memory: usize,
+    expired_keys: u64,
+    evicted_keys: u64,
+    keyspace_hits: u64,
+    keyspace_misses: u64,
+    settings: EvictionSettings,
+    /// Whether keys that change are recorded in `modified`, for `CLIENT TRACKING`.
+    track_modified: bool,
+    modified: Vec<Vec<u8>>,
 }
 
 impl KeyValueStore {
     pub fn new() -> Self {
         Self {
-            store: HashMap::new(),
+            store: IndexMap::new(),
+            expires: IndexMap::new(),
+            used_memory: 0,
+            expired_keys: 0,
+            evicted_keys: 0,
+            keyspace_hits: 0,
+            keyspace_misses: 0,
+            settings: EvictionSettings::default(),
+            track_modified: false,
+            modified: vec![],
         }
     }
 
-    pub fn exec(&mut self, command: Command) -> SerirResult<Vec<u8>> {
-        match command {
-            Command::Get(key) => self.get(&key),
-            Command::Set((key, value)) => self.set(&key, value),
-            // hardcoded only to be able to run redis-benchmark
-            Command::Command => Resp::BulkString(None).serialize(),
-            Command::Config(value) if value == *"save" => Ok(b"*2\r\n$4\r\nsave\r\n$23\r\n3600 1 300 100 60 10000\r\n".to_vec()),
-            Command::Config(value) if value == *"appendonly" => Ok(b"*2\r\n$10\r\nappendonly\r\n$2\r\nno\r\n".to_vec()),
-            Command::Config(_) => Resp::Error(b"Supporting only \"appendonly\" and \"save\"".to_vec()).serialize(),
+    pub fn configure(&mut self, settings: EvictionSettings) {
+        self.settings = settings;
+    }
+
+    /// Starts or stops recording the keys that change, written, deleted, expired or
+    /// evicted.
+    pub fn set_track_modified(&mut self, track_modified: bool) {
+        self.track_modified = track_modified;
+        if !track_modified {
+            self.modified.clear();
         }
     }
 
-    fn store_set(&mut self, key: &[u8], value: Vec<u8>) {
-        self.store.insert(key.to_owned(), value);
+    /// The keys that changed since the last call, while recording them.
+    pub fn take_modified(&mut self) -> Vec<Vec<u8>> {
+        std::mem::take(&mut self.modified)
     }
 
-    fn store_get(&self, key: &[u8]) -> Option<&Vec<u8>> {
-        self.store.get(&key.to_owned())
+    fn signal_modified(&mut self, key: &[u8]) {
+        if self.track_modified {
+            self.modified.push(key.to_vec());
+        }
     }
 
-    fn get(&self, key: &[u8]) -> SerirResult<Vec<u8>> {
-        let value = match self.store_get(key) {
-            Some(val) => Resp::BulkString(Some(val.clone())),
-            None => Resp::BulkString(None),
+    /// Executes a keyspace command and replies to it, on top of the typed methods.
+    pub fn exec(&mut self, command: Command) -> SerirResult<Resp> {
+        let reply = match command {
+            Command::Get(key) => Resp::BulkString(self.get(&key)),
+            Command::Set((key, value)) => {
+                self.set(&key, value);
+                Resp::SimpleString(b"OK".to_vec())
+            }
+            Command::MGet(keys) => Resp::Array(Some(
+                keys.iter()
+                    .map(|key| Resp::BulkString(self.get(key)))
+                    .collect(),
+            )),
+            Command::MSet(pairs) => {
+                for (key, value) in pairs {
+                    self.set(&key, value);
+                }
+                Resp::SimpleString(b"OK".to_vec())
+            }
+            Command::Del(keys) => {
+                Resp::Integer(keys.iter().filter(|key| self.del(key)).count() as i64)
+            }
+            Command::Exists(keys) => {
+                Resp::Integer(keys.iter().filter(|key| self.exists(key)).count() as i64)
+            }
+            Command::Expire((key, seconds)) => {
+                self.expire_in(&key, seconds.checked_mul(1000), "expire")
+            }
+            Command::PExpire((key, millis)) => self.expire_in(&key, Some(millis), "pexpire"),
+            Command::Ttl(key) => Resp::Integer(self.ttl(&key).as_integer(1000)),
+            Command::PTtl(key) => Resp::Integer(self.ttl(&key).as_integer(1)),
+            Command::Persist(key) => Resp::Integer(self.persist(&key) as i64),
+            Command::Object((subcommand, key)) => return self.object(&subcommand, &key),
+            command => Resp::Error(
+                format!("ERR '{}' is not a keyspace command", command.name()).into_bytes(),
+            ),
         };
+        Ok(reply)
+    }
+
+    /// Sets `key` to `value`, discarding its expire.
+    pub fn set(&mut self, key: &[u8], value: Bytes) {
+        self.signal_modified(key);
+        self.remove_expire(key);
+        let value = match value.len() < SHARED_VALUE_MIN_LEN {
+            true => Bytes::copy_from_slice(&value),
+            false => value,
+        };
+        self.used_memory += Entry::size(key, &value);
+        let entry = Entry::new(value, now_ms());
+        // overwriting doesn't need a copy of the key
+        match self.store.get_mut(key) {
+            Some(old) => {
+                let old = std::mem::replace(old, entry);
+                self.used_memory -= Entry::size(key, &old.value);
+            }
+            None => {
+                self.store.insert(key.to_owned(), entry);
+            }
+        }
+    }
+
+    /// The value of `key`, if it exists. The value shares its memory with the stored one.
+    pub fn get(&mut self, key: &[u8]) -> Option<Bytes> {
+        let entry = self.lookup(key)?;
+        Some(entry.value.clone())
+    }
+
+    /// Deletes `key`. Returns whether it existed.
+    pub fn del(&mut self, key: &[u8]) -> bool {
+        if self.expire_if_needed(key) {
+            return false;
+        }
+        self.remove(key)
+    }
+
+    pub fn exists(&mut self, key: &[u8]) -> bool {
+        self.lookup(key).is_some()
+    }
+
+    /// Sets the expire of `key` to `at` milliseconds since the unix epoch. Returns whether
+    /// the key exists.
+    pub fn expire_at(&mut self, key: &[u8], at: u64) -> bool {
+        if self.expire_if_needed(key) || !self.store.contains_key(key) {
+            return false;
+        }
+        // like Redis, a deadline in the past deletes the key rather than expiring it
+        if at <= now_ms() {
+            self.remove(key);
+            return true;
+        }
+        self.signal_modified(key);
+        if self.expires.insert(key.to_owned(), at).is_none() {
+            self.used_memory += key.len() + EXPIRE_OVERHEAD;
+        }
+        true
+    }
+
+    /// How long `key` has left to live.
+    pub fn ttl(&mut self, key: &[u8]) -> Ttl {
+        if self.expire_if_needed(key) || !self.store.contains_key(key) {
+            return Ttl::Missing;
+        }
+        match self.expires.get(key) {
+            Some(&at) => Ttl::Expires(Duration::from_millis(at.saturating_sub(now_ms()))),
+            None => Ttl::Persistent,
+        }
+    }
+
+    /// Removes the expire of `key`. Returns whether it had one.
+    pub fn persist(&mut self, key: &[u8]) -> bool {
+        let persisted = !self.expire_if_needed(key) && self.remove_expire(key);
+        if persisted {
+            self.signal_modified(key);
+        }
+        persisted
+    }
+
+    pub fn len(&self) -> usize {
+        self.store.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.store.is_empty()
+    }
+
+    /// Number of keys with an expire set.
+    pub fn volatile_len(&self) -> usize {
+        self.expires.len()
+    }
 
-        value.serialize()
+    /// Approximate memory used by keys, values and their metadata, in bytes.
+    pub fn used_memory(&self) -> usize {
+        self.used_memory
     }
 
-    fn set(&mut self, key: &[u8], value: Vec<u8>) -> SerirResult<Vec<u8>> {
-        self.store_set(key, value);
+    pub fn expired_keys(&self) -> u64 {
+        self.expired_keys
+    }
+
+    pub fn evicted_keys(&self) -> u64 {
+        self.evicted_keys
+    }
+
+    pub fn keyspace_hits(&self) -> u64 {
+        self.keyspace_hits
+    }
 
-        Resp::SimpleString(b"OK".to_vec()).serialize()
+    pub fn keyspace_misses(&self) -> u64 {
+        self.keyspace_misses
+    }
+
+    pub fn reset_stats(&mut self) {
+        self.expired_keys = 0;
+        self.evicted_keys = 0;
+        self.keyspace_hits = 0;
+        self.keyspace_misses = 0;
+    }
+
+    /// Looks `key` up on behalf of a client, updating its access metadata.
+    fn lookup(&mut self, key: &[u8]) -> Option<&mut Entry> {
+        self.expire_if_needed(key);
+        let settings = self.settings;
+        match self.store.get_mut(key) {
+            Some(entry) => {
+                self.keyspace_hits += 1;
+                entry.touch(now_ms(), &settings);
+                Some(entry)
+            }
+            None => {
+                self.keyspace_misses += 1;
+                None
+            }
+        }
+    }
+
+    /// Deletes `key` if its expire time has passed. Returns whether it was deleted.
+    fn expire_if_needed(&mut self, key: &[u8]) -> bool {
+        match self.expires.get(key) {
+            Some(&at) if at <= now_ms() => {
+                self.remove(key);
+                self.expired_keys += 1;
+                true
+            }
+            _ => false,
+        }
+    }
+
+    fn remove(&mut self, key: &[u8]) -> bool {
+        self.remove_expire(key);
+        match self.store.swap_remove(key) {
+            Some(entry) => {
+                self.used_memory -= Entry::size(key, &entry.value);
+                self.signal_modified(key);
+                true
+            }
+            None => false,
+        }
+    }
+
+    fn remove_expire(&mut self, key: &[u8]) -> bool {
+        if self.expires.swap_remove(key).is_some() {
+            self.used_memory -= key.len() + EXPIRE_OVERHEAD;
+            true
+        } else {
+            false
+        }
+    }
+
+    /// Samples keys according to the eviction policy and returns the best candidate for
+    /// eviction together with its score. The higher the score, the better the candidate.
+    pub(crate) fn eviction_candidate(&self) -> Option<(u64, Vec<u8>)> {
+        let policy = self.settings.policy;
+        let pool_len = if policy.is_volatile() {
+            self.expires.len()
+        } else {
+            self.store.len()
+        };
+        if policy == EvictionPolicy::NoEviction || pool_len == 0 {
+            return None;
+        }
+
+        let now = now_ms();
+        let mut rng = thread_rng();
+        (0..self.settings.samples.max(1))
+            .filter_map(|_| {
+                let index = rng.gen_range(0..pool_len);
+                let (key, entry) = if policy.is_volatile() {
+                    let (key, _) = self.expires.get_index(index)?;
+                    (key, self.store.get(key)?)
+                } else {
+                    self.store.get_index(index)?
+                };
+                let score = match policy {
+                    EvictionPolicy::AllKeysLru | EvictionPolicy::VolatileLru => {
+                        now.saturating_sub(entry.lru)
+                    }
+                    EvictionPolicy::AllKeysLfu | EvictionPolicy::VolatileLfu => {
+                        (u8::MAX - entry.freq(now, &self.settings)) as u64
+                    }
+                    EvictionPolicy::VolatileTtl => u64::MAX - self.expires.get(key)?,
+                    EvictionPolicy::AllKeysRandom
+                    | EvictionPolicy::VolatileRandom
+                    | EvictionPolicy::NoEviction => 0,
+                };
+                Some((score, key))
+            })
+            .max_by_key(|(score, _)| *score)
+            .map(|(score, key)| (score, key.clone()))
+    }
+
+    /// Evicts `key` to free memory. Returns whether it existed.
+    pub(crate) fn evict(&mut self, key: &[u8]) -> bool {
+        let evicted = self.remove(key);
+        if evicted {
+            self.evicted_keys += 1;
+        }
+        evicted
+    }
+
+    /// Samples up to `samples` volatile keys and deletes the expired ones. Returns the
+    /// number of sampled and expired keys.
+    pub(crate) fn active_expire(&mut self, samples: usize) -> (usize, usize) {
+        let now = now_ms();
+        let mut rng = thread_rng();
+        let mut sampled = 0;
+        let mut expired = 0;
+        while sampled < samples && !self.expires.is_empty() {
+            sampled += 1;
+            let index = rng.gen_range(0..self.expires.len());
+            let (key, &at) = self.expires.get_index(index).unwrap();
+            if at <= now {
+                let key = key.clone();
+                self.remove(&key);
+                self.expired_keys += 1;
+                expired += 1;
+            }
+        }
+        (sampled, expired)
+    }
+
+    /// Replies to `EXPIRE` or `PEXPIRE`, expiring `key` after `millis` milliseconds.
+    /// Deadlines that don't fit in an `i64`, `None` when converting them to milliseconds
+    /// overflowed already, are refused without touching the key.
+    fn expire_in(&mut self, key: &[u8], millis: Option<i64>, command: &str) -> Resp {
+        match millis.and_then(|millis| (now_ms() as i64).checked_add(millis)) {
+            Some(at) => Resp::Integer(self.expire_at(key, at.max(0) as u64) as i64),
+            None => Resp::Error(
+                format!("ERR invalid expire time in '{}' command", command).into_bytes(),
+            ),
+        }
+    }
+
+    fn object(&mut self, subcommand: &str, key: &[u8]) -> SerirResult<Resp> {
+        let is_lfu = self.settings.policy.is_lfu();
+        let settings = self.settings;
+        let entry = if self.expire_if_needed(key) {
+            None
+        } else {
+            self.store.get(key)
+        };
+
+        let reply = match (subcommand.to_lowercase().as_str(), entry) {
+            ("idletime" | "freq", None) => Resp::BulkString(None),
+            ("idletime", Some(_)) if is_lfu => Resp::Error(
+                b"ERR An LFU maxmemory policy is selected, idle time not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.".to_vec(),
+            ),
+            ("idletime", Some(entry)) => {
+                Resp::Integer((now_ms().saturating_sub(entry.lru) / 1000) as i64)
+            }
+            ("freq", Some(_)) if !is_lfu => Resp::Error(
+                b"ERR An LFU maxmemory policy is not selected, access frequency not tracked. Please note that when switching between policies at runtime LRU and LFU data will take some time to adjust.".to_vec(),
+            ),
+            ("freq", Some(entry)) => Resp::Integer(entry.freq(now_ms(), &settings) as i64),
+            _ => Resp::Error(
+                format!(
+                    "ERR unknown subcommand '{}'. Try OBJECT IDLETIME or OBJECT FREQ.",
+                    subcommand
+                )
+                .into_bytes(),
+            ),
+        };
+        Ok(reply)
     }
 }
 
@@ -61,7 +482,6 @@ impl Default for KeyValueStore {
 #[cfg(test)]
 mod tests {
     use super::*;
-    use rand::prelude::*;
 
     #[test]
     fn sets_and_gets_values() {
@@ -73,12 +493,195 @@ mod tests {
         let value_len = thread_rng().gen_range(2..=100);
         let value: Vec<u8> = (0..value_len).map(|_| thread_rng().gen::<u8>()).collect();
 
-        store.store_set(&key, value.clone());
+        store.set(&key, value.clone().into());
 
-        if let Some(get_value) = store.store_get(&key) {
+        if let Some(get_value) = store.get(&key) {
             assert_eq!(value, *get_value);
         } else {
             panic!("No value in store");
         }
     }
+
+    #[test]
+    fn accounts_memory_of_keys_values_and_expires() {
+        let mut store = KeyValueStore::new();
+
+        store.set(b"key", Bytes::from_static(b"value"));
+        assert_eq!(store.used_memory(), 3 + 5 + ENTRY_OVERHEAD);
+
+        store.set(b"key", Bytes::from_static(b"longer value"));
+        assert_eq!(store.used_memory(), 3 + 12 + ENTRY_OVERHEAD);
+
+        store.expire_at(b"key", now_ms() + 10_000);
+        assert_eq!(
+            store.used_memory(),
+            3 + 12 + ENTRY_OVERHEAD + 3 + EXPIRE_OVERHEAD
+        );
+
+        store.del(b"key");
+        assert_eq!(store.used_memory(), 0);
+    }
+
+    #[test]
+    fn expires_keys_lazily() {
+        let mut store = KeyValueStore::new();
+        store.set(b"key", Bytes::from_static(b"value"));
+        store.expire_at(b"key", now_ms() + 10_000);
+        assert!(store.exists(b"key"));
+
+        store.expires.insert(b"key".to_vec(), now_ms() - 1);
+        assert!(store.get(b"key").is_none());
+        assert!(store.is_empty());
+        assert_eq!(store.expired_keys(), 1);
+    }
+
+    #[test]
+    fn reports_time_to_live() {
+        let mut store = KeyValueStore::new();
+        assert_eq!(store.ttl(b"key"), Ttl::Missing);
+        store.set(b"key", Bytes::from_static(b"value"));
+        assert_eq!(store.ttl(b"key"), Ttl::Persistent);
+        assert_eq!(store.ttl(b"key").as_integer(1), -1);
+
+        store.expire_at(b"key", now_ms() + 10_900);
+        let Ttl::Expires(ttl) = store.ttl(b"key") else {
+            panic!("key without expire");
+        };
+        assert!(ttl > Duration::from_secs(10));
+        assert_eq!(store.ttl(b"key").as_integer(1000), 11);
+        assert_eq!(Ttl::Expires(Duration::from_millis(999)).as_integer(1000), 1);
+        assert_eq!(Ttl::Expires(Duration::from_millis(499)).as_integer(1000), 0);
+        assert_eq!(Ttl::Expires(Duration::from_millis(999)).as_integer(1), 999);
+        assert!(store.persist(b"key"));
+        assert_eq!(store.ttl(b"key"), Ttl::Persistent);
+    }
+
+    #[test]
+    fn deletes_keys_given_a_past_expire_time_without_counting_them_expired() {
+        let mut store = KeyValueStore::new();
+        store.set(b"key", Bytes::from_static(b"value"));
+        assert!(store.expire_at(b"key", now_ms() - 1));
+        assert!(store.is_empty());
+        assert_eq!(store.expired_keys(), 0);
+    }
+
+    #[test]
+    fn refuses_expire_times_that_overflow() {
+        let mut store = KeyValueStore::new();
+        store.set(b"key", Bytes::from_static(b"value"));
+
+        assert_eq!(
+            store
+                .exec(Command::Expire((b"key".to_vec(), i64::MAX)))
+                .unwrap(),
+            Resp::Error(b"ERR invalid expire time in 'expire' command".to_vec())
+        );
+        assert_eq!(
+            store
+                .exec(Command::PExpire((b"key".to_vec(), i64::MAX)))
+                .unwrap(),
+            Resp::Error(b"ERR invalid expire time in 'pexpire' command".to_vec())
+        );
+        assert_eq!(store.ttl(b"key"), Ttl::Persistent);
+        assert_eq!(
+            store.exec(Command::Get(b"key".to_vec())).unwrap(),
+            Resp::BulkString(Some(Bytes::from_static(b"value")))
+        );
+    }
+
+    #[test]
+    fn set_clears_expire() {
+        let mut store = KeyValueStore::new();
+        store.set(b"key", Bytes::from_static(b"value"));
+        store.expire_at(b"key", now_ms() + 10_000);
+        store.set(b"key", Bytes::from_static(b"value"));
+        assert_eq!(store.volatile_len(), 0);
+    }
+
+    #[test]
+    fn picks_least_recently_used_candidate() {
+        let mut store = KeyValueStore::new();
+        store.configure(EvictionSettings {
+            policy: EvictionPolicy::AllKeysLru,
+            samples: 100,
+            ..EvictionSettings::default()
+        });
+        store.set(b"old", Bytes::from_static(b"value"));
+        store.set(b"new", Bytes::from_static(b"value"));
+        store.store.get_mut(&b"old"[..]).unwrap().lru -= 60_000;
+
+        let (_, key) = store.eviction_candidate().unwrap();
+        assert_eq!(key, b"old".to_vec());
+    }
+
+    #[test]
+    fn picks_only_volatile_candidates_for_volatile_policies() {
+        let mut store = KeyValueStore::new();
+        store.configure(EvictionSettings {
+            policy: EvictionPolicy::VolatileTtl,
+            samples: 100,
+            ..EvictionSettings::default()
+        });
+        store.set(b"persistent", Bytes::from_static(b"value"));
+        assert!(store.eviction_candidate().is_none());
+
+        store.set(b"late", Bytes::from_static(b"value"));
+        store.set(b"soon", Bytes::from_static(b"value"));
+        store.expire_at(b"late", now_ms() + 100_000);
+        store.expire_at(b"soon", now_ms() + 10_000);
+        let (_, key) = store.eviction_candidate().unwrap();
+        assert_eq!(key, b"soon".to_vec());
+    }
+
+    #[test]
+    fn reports_idle_time_and_frequency_depending_on_policy() {
+        let mut store = KeyValueStore::new();
+        store.set(b"key", Bytes::from_static(b"value"));
+        store.store.get_mut(&b"key"[..]).unwrap().lru -= 5_000;
+
+        assert_eq!(store.object("IDLETIME", b"key").unwrap(), Resp::Integer(5));
+        assert!(matches!(
+            store.object("FREQ", b"key").unwrap(),
+            Resp::Error(_)
+        ));
+        assert_eq!(
+            store.object("IDLETIME", b"missing").unwrap(),
+            Resp::BulkString(None)
+        );
+
+        store.configure(EvictionSettings {
+            policy: EvictionPolicy::AllKeysLfu,
+            ..EvictionSettings::default()
+        });
+        assert_eq!(store.object("FREQ", b"key").unwrap(), Resp::Integer(5));
+        assert!(matches!(
+            store.object("IDLETIME", b"key").unwrap(),
+            Resp::Error(_)
+        ));
+    }
+
+    #[test]
+    fn records_modified_keys_only_when_asked_to() {
+        let mut store = KeyValueStore::new();
+        store.set(b"untracked", Bytes::from_static(b"value"));
+        assert!(store.take_modified().is_empty());
+
+        store.set_track_modified(true);
+        store
+            .exec(Command::Set((b"a".to_vec(), Bytes::from_static(b"1"))))
+            .unwrap();
+        store.exec(Command::Get(b"a".to_vec())).unwrap();
+        store.exec(Command::Persist(b"a".to_vec())).unwrap();
+        store
+            .exec(Command::Expire((b"untracked".to_vec(), 100)))
+            .unwrap();
+        store
+            .exec(Command::Del(vec![b"a".to_vec(), b"missing".to_vec()]))
+            .unwrap();
+        assert_eq!(
+            store.take_modified(),
+            vec![b"a".to_vec(), b"untracked".to_vec(), b"a".to_vec()]
+        );
+        assert!(store.take_modified().is_empty());
+    }
 }
@@ -0,0 +1,162 @@
+//! Servers and clients for integration tests. Every [`TestServer`] listens on its own free
+//! port and shuts down when dropped, so tests using them can run in parallel.
+
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use bytes::{Bytes, BytesMut};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+use crate::builder::{ServerBuilder, ServerHandle};
+use crate::resp::{Parser, Resp};
+use crate::state::ServerState;
+
+/// A server started for one test.
+#[derive(Debug)]
+pub struct TestServer {
+    handle: Option<ServerHandle>,
+}
+
+impl TestServer {
+    /// Starts a server with the default configuration on a free port of 127.0.0.1.
+    pub async fn start() -> Self {
+        Self::with(ServerBuilder::new()).await
+    }
+
+    /// Starts the server `builder` describes, panicking if it can't.
+    pub async fn with(builder: ServerBuilder) -> Self {
+        let handle = builder.start().await.expect("failed to start test server");
+        Self {
+            handle: Some(handle),
+        }
+    }
+
+    pub fn addr(&self) -> SocketAddr {
+        self.handle().addr()
+    }
+
+    pub fn state(&self) -> &Arc<ServerState> {
+        self.handle().state()
+    }
+
+    /// Opens a connection to the server.
+    pub async fn connect(&self) -> TestClient {
+        TestClient::connect(self.addr()).await
+    }
+
+    /// Shuts the server down and waits until it's done. Dropping it only requests the
+    /// shutdown.
+    pub async fn stop(mut self) {
+        let handle = self.handle.take().unwrap();
+        handle.shutdown();
+        handle.join().await;
+    }
+
+    fn handle(&self) -> &ServerHandle {
+        self.handle.as_ref().unwrap()
+    }
+}
+
+impl Drop for TestServer {
+    fn drop(&mut self) {
+        if let Some(handle) = &self.handle {
+            handle.shutdown();
+        }
+    }
+}
+
+/// A connection to a server, sending commands and reading replies one at a time.
+#[derive(Debug)]
+pub struct TestClient {
+    stream: TcpStream,
+    buffer: BytesMut,
+}
+
+impl TestClient {
+    pub async fn connect(addr: SocketAddr) -> Self {
+        let stream = TcpStream::connect(addr)
+            .await
+            .expect("failed to connect to test server");
+        Self {
+            stream,
+            buffer: BytesMut::new(),
+        }
+    }
+
+    /// Sends a command and waits for its reply.
+    pub async fn request(&mut self, args: &[&str]) -> Resp {
+        self.send(args).await;
+        self.read().await.expect("connection closed before a reply")
+    }
+
+    /// Sends a command without waiting for its reply.
+    pub async fn send(&mut self, args: &[&str]) {
+        let request = Resp::Array(Some(
+            args.iter()
+                .map(|arg| Resp::BulkString(Some(Bytes::copy_from_slice(arg.as_bytes()))))
+                .collect(),
+        ));
+        self.stream
+            .write_all(&request.serialize().unwrap())
+            .await
+            .expect("failed to send request");
+    }
+
+    /// Reads the next reply or push message, `None` once the server closed the
+    /// connection.
+    pub async fn read(&mut self) -> Option<Resp> {
+        loop {
+            let mut parser = Parser::new(self.buffer.clone().freeze());
+            if let Some(resp) = parser.parse_single_resp_object().expect("invalid reply") {
+                let _ = self.buffer.split_to(parser.position());
+                return Some(resp);
+            }
+            let read = self
+                .stream
+                .read_buf(&mut self.buffer)
+                .await
+                .expect("failed to read reply");
+            if read == 0 {
+                return None;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn bulk(value: &'static str) -> Resp {
+        Resp::BulkString(Some(Bytes::from_static(value.as_bytes())))
+    }
+
+    #[tokio::test]
+    async fn runs_servers_side_by_side() {
+        let first = TestServer::start().await;
+        let second = TestServer::with(ServerBuilder::new().data("key", "second")).await;
+        assert_ne!(first.addr(), second.addr());
+
+        let mut client = first.connect().await;
+        assert_eq!(
+            client.request(&["SET", "key", "first"]).await,
+            Resp::SimpleString(b"OK".to_vec())
+        );
+        assert_eq!(client.request(&["GET", "key"]).await, bulk("first"));
+        let mut client = second.connect().await;
+        assert_eq!(client.request(&["GET", "key"]).await, bulk("second"));
+        assert_eq!(first.state().store.get("key"), Some(Bytes::from("first")));
+    }
+
+    #[tokio::test]
+    async fn disconnects_clients_when_stopped() {
+        let server = TestServer::start().await;
+        let mut client = server.connect().await;
+        client.send(&["EXISTS", "key"]).await;
+        assert_eq!(client.read().await, Some(Resp::Integer(0)));
+
+        server.stop().await;
+        assert_eq!(client.read().await, None);
+    }
+}
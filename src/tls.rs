@@ -0,0 +1,347 @@
+use std::fmt::{self, Debug, Display};
+use std::path::Path;
+use std::str::FromStr;
+use std::sync::{Arc, RwLock};
+
+use rustls::pki_types::pem::PemObject;
+use rustls::pki_types::{CertificateDer, PrivateKeyDer};
+use rustls::server::WebPkiClientVerifier;
+use rustls::{RootCertStore, ServerConfig};
+use tokio_rustls::TlsAcceptor;
+
+use crate::config::Config;
+
+/// Whether TLS clients must present a certificate signed by `tls-ca-cert-file`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TlsAuthClients {
+    Yes,
+    No,
+    /// Clients may go without a certificate, but one they present must be valid.
+    Optional,
+}
+
+impl TlsAuthClients {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TlsAuthClients::Yes => "yes",
+            TlsAuthClients::No => "no",
+            TlsAuthClients::Optional => "optional",
+        }
+    }
+}
+
+impl Display for TlsAuthClients {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for TlsAuthClients {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        [
+            TlsAuthClients::Yes,
+            TlsAuthClients::No,
+            TlsAuthClients::Optional,
+        ]
+        .into_iter()
+        .find(|auth| auth.as_str().eq_ignore_ascii_case(s))
+        .ok_or_else(|| "argument must be 'yes', 'no' or 'optional'".to_string())
+    }
+}
+
+/// Accepts TLS connections with the configured certificates. Reloading swaps the
+/// certificates used by new connections, established ones are left alone.
+#[derive(Default)]
+pub struct Tls {
+    acceptor: RwLock<Option<TlsAcceptor>>,
+}
+
+impl Debug for Tls {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Tls")
+            .field("enabled", &self.acceptor.read().unwrap().is_some())
+            .finish()
+    }
+}
+
+impl Tls {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// (Re)loads the certificates and keys named by `config`. TLS stays disabled while
+    /// `tls-port` is 0. On error the previous certificates are kept.
+    pub fn reload(&self, config: &Config) -> Result<(), String> {
+        let acceptor = match config.tls_port {
+            0 => None,
+            _ => Some(TlsAcceptor::from(Arc::new(server_config(config)?))),
+        };
+        *self.acceptor.write().unwrap() = acceptor;
+        Ok(())
+    }
+
+    pub fn acceptor(&self) -> Option<TlsAcceptor> {
+        self.acceptor.read().unwrap().clone()
+    }
+}
+
+fn server_config(config: &Config) -> Result<ServerConfig, String> {
+    let cert_file = config
+        .tls_cert_file
+        .as_deref()
+        .ok_or("tls-cert-file must be specified")?;
+    let key_file = config
+        .tls_key_file
+        .as_deref()
+        .ok_or("tls-key-file must be specified")?;
+    let certs = load_certs(cert_file)?;
+    let key = PrivateKeyDer::from_pem_file(key_file)
+        .map_err(|e| format!("failed to load private key {}: {}", key_file.display(), e))?;
+
+    let builder = ServerConfig::builder();
+    let builder = match (config.tls_auth_clients, &config.tls_ca_cert_file) {
+        (TlsAuthClients::No, _) => builder.with_no_client_auth(),
+        (_, None) => {
+            return Err(
+                "tls-ca-cert-file must be specified when tls-auth-clients is enabled".to_string(),
+            )
+        }
+        (auth_clients, Some(ca_cert_file)) => {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(ca_cert_file)? {
+                roots.add(cert).map_err(|e| {
+                    format!(
+                        "invalid CA certificate in {}: {}",
+                        ca_cert_file.display(),
+                        e
+                    )
+                })?;
+            }
+            let verifier = WebPkiClientVerifier::builder(Arc::new(roots));
+            let verifier = match auth_clients {
+                TlsAuthClients::Optional => verifier.allow_unauthenticated(),
+                _ => verifier,
+            };
+            builder.with_client_cert_verifier(verifier.build().map_err(|e| e.to_string())?)
+        }
+    };
+    builder
+        .with_single_cert(certs, key)
+        .map_err(|e| format!("invalid certificate or key: {}", e))
+}
+
+fn load_certs(path: &Path) -> Result<Vec<CertificateDer<'static>>, String> {
+    let error = |e: &dyn Display| format!("failed to load {}: {}", path.display(), e);
+    let certs = CertificateDer::pem_file_iter(path)
+        .map_err(|e| error(&e))?
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| error(&e))?;
+    if certs.is_empty() {
+        return Err(error(&"no certificate found"));
+    }
+    Ok(certs)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::path::PathBuf;
+    use std::time::Duration;
+
+    use rcgen::{BasicConstraints, CertificateParams, IsCa, KeyPair};
+    use rustls::pki_types::ServerName;
+    use rustls::ClientConfig;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::{TcpListener, TcpStream};
+    use tokio_rustls::TlsConnector;
+
+    use crate::commands::Command;
+    use crate::server::Server;
+    use crate::state::ServerState;
+
+    /// A CA, a server certificate for `localhost` and a client certificate, both signed
+    /// by the CA, written as PEM files to a fresh directory.
+    struct Certs {
+        dir: PathBuf,
+        client_cert: String,
+        client_key: String,
+    }
+
+    impl Certs {
+        fn generate(name: &str) -> Self {
+            let dir = env::temp_dir().join(format!("serir-tls-{}-{}", name, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+
+            let ca_key = KeyPair::generate().unwrap();
+            let mut ca_params = CertificateParams::new(vec![]).unwrap();
+            ca_params.is_ca = IsCa::Ca(BasicConstraints::Unconstrained);
+            let ca = ca_params.self_signed(&ca_key).unwrap();
+            let sign = |names: Vec<String>| {
+                let key = KeyPair::generate().unwrap();
+                let cert = CertificateParams::new(names)
+                    .unwrap()
+                    .signed_by(&key, &ca, &ca_key)
+                    .unwrap();
+                (cert.pem(), key.serialize_pem())
+            };
+            let (server_cert, server_key) = sign(vec!["localhost".to_string()]);
+            let (client_cert, client_key) = sign(vec!["client".to_string()]);
+
+            fs::write(dir.join("ca.crt"), ca.pem()).unwrap();
+            fs::write(dir.join("server.crt"), server_cert).unwrap();
+            fs::write(dir.join("server.key"), server_key).unwrap();
+            Self {
+                dir,
+                client_cert,
+                client_key,
+            }
+        }
+
+        fn config(&self, auth_clients: TlsAuthClients) -> Config {
+            Config {
+                tls_port: 6380,
+                tls_cert_file: Some(self.dir.join("server.crt")),
+                tls_key_file: Some(self.dir.join("server.key")),
+                tls_ca_cert_file: Some(self.dir.join("ca.crt")),
+                tls_auth_clients: auth_clients,
+                ..Config::default()
+            }
+        }
+
+        fn connector(&self, with_client_cert: bool) -> TlsConnector {
+            let mut roots = RootCertStore::empty();
+            for cert in load_certs(&self.dir.join("ca.crt")).unwrap() {
+                roots.add(cert).unwrap();
+            }
+            let builder = ClientConfig::builder().with_root_certificates(roots);
+            let config = if with_client_cert {
+                let certs = CertificateDer::pem_slice_iter(self.client_cert.as_bytes())
+                    .collect::<Result<Vec<_>, _>>()
+                    .unwrap();
+                let key = PrivateKeyDer::from_pem_slice(self.client_key.as_bytes()).unwrap();
+                builder.with_client_auth_cert(certs, key).unwrap()
+            } else {
+                builder.with_no_client_auth()
+            };
+            TlsConnector::from(Arc::new(config))
+        }
+    }
+
+    impl Drop for Certs {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    async fn start(config: Config) -> (Arc<ServerState>, u16) {
+        let state = Arc::new(ServerState::new(config));
+        state.load_tls().unwrap();
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let server = Server::with_tls(state.clone(), listener);
+        tokio::spawn(async move { server.run().await });
+        (state, port)
+    }
+
+    /// Sends `SET key value` over TLS, returning the reply or `None` if the server
+    /// refused the connection.
+    async fn set(connector: &TlsConnector, port: u16) -> Option<Vec<u8>> {
+        let socket = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let name = ServerName::try_from("localhost").unwrap();
+        let mut stream = connector.connect(name, socket).await.ok()?;
+        stream
+            .write_all(b"*3\r\n$3\r\nSET\r\n$3\r\nkey\r\n$5\r\nvalue\r\n")
+            .await
+            .ok()?;
+        let mut reply = vec![0; 64];
+        match stream.read(&mut reply).await {
+            Ok(0) | Err(_) => None,
+            Ok(n) => Some(reply[..n].to_vec()),
+        }
+    }
+
+    #[tokio::test]
+    async fn serves_clients_over_mutual_tls() {
+        let certs = Certs::generate("mutual");
+        let (_state, port) = start(certs.config(TlsAuthClients::Yes)).await;
+
+        assert_eq!(
+            set(&certs.connector(true), port).await,
+            Some(b"+OK\r\n".to_vec())
+        );
+        assert_eq!(set(&certs.connector(false), port).await, None);
+    }
+
+    #[tokio::test]
+    async fn drops_clients_stalling_the_handshake() {
+        let certs = Certs::generate("stalling");
+        let config = Config {
+            timeout: 1,
+            ..certs.config(TlsAuthClients::No)
+        };
+        let (_state, port) = start(config).await;
+
+        let mut socket = TcpStream::connect(("127.0.0.1", port)).await.unwrap();
+        let mut buffer = [0; 16];
+        let closed = tokio::time::timeout(Duration::from_secs(5), socket.read(&mut buffer)).await;
+        assert!(matches!(closed, Ok(Ok(0) | Err(_))));
+    }
+
+    #[tokio::test]
+    async fn client_certificates_can_be_optional() {
+        let certs = Certs::generate("optional");
+        let (_state, port) = start(certs.config(TlsAuthClients::Optional)).await;
+        assert_eq!(
+            set(&certs.connector(false), port).await,
+            Some(b"+OK\r\n".to_vec())
+        );
+    }
+
+    #[tokio::test]
+    async fn reloads_certificates_on_config_set() {
+        let certs = Certs::generate("reload");
+        let (state, port) = start(certs.config(TlsAuthClients::No)).await;
+
+        let missing = certs.dir.join("missing.crt").display().to_string();
+        let reply = state
+            .exec(Command::Config(vec![
+                "SET".to_string(),
+                "tls-cert-file".to_string(),
+                missing,
+            ]))
+            .unwrap();
+        assert!(reply.starts_with(b"-ERR"));
+        assert_eq!(
+            state.config.read().unwrap().tls_cert_file,
+            Some(certs.dir.join("server.crt"))
+        );
+
+        let reply = state
+            .exec(Command::Config(vec![
+                "SET".to_string(),
+                "tls-auth-clients".to_string(),
+                "yes".to_string(),
+            ]))
+            .unwrap();
+        assert_eq!(reply, b"+OK\r\n".to_vec());
+        assert_eq!(set(&certs.connector(false), port).await, None);
+        assert_eq!(
+            set(&certs.connector(true), port).await,
+            Some(b"+OK\r\n".to_vec())
+        );
+    }
+
+    #[test]
+    fn requires_a_ca_to_authenticate_clients() {
+        let certs = Certs::generate("no-ca");
+        let config = Config {
+            tls_ca_cert_file: None,
+            ..certs.config(TlsAuthClients::Yes)
+        };
+        assert!(Tls::new().reload(&config).is_err());
+    }
+}
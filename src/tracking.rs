@@ -0,0 +1,360 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+use std::sync::Mutex;
+
+use crate::sharded::ShardedStore;
+
+/// Options of `CLIENT TRACKING ON`, as applied to a client.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TrackingOptions {
+    /// Client receiving the invalidation messages instead of the tracking one.
+    pub redirect: Option<u64>,
+    /// Broadcasting mode: every key starting with one of `prefixes` is invalidated,
+    /// whether the client read it or not.
+    pub bcast: bool,
+    pub prefixes: Vec<Vec<u8>>,
+    /// Only the keys read right after `CLIENT CACHING YES` are tracked.
+    pub optin: bool,
+    /// The keys read right after `CLIENT CACHING NO` aren't tracked.
+    pub optout: bool,
+    /// Keys modified by the client itself aren't invalidated.
+    pub noloop: bool,
+}
+
+/// What `CLIENT TRACKING` asked for.
+#[derive(Debug, Clone, PartialEq)]
+pub enum TrackingCommand {
+    On(TrackingOptions),
+    Off,
+}
+
+impl TrackingCommand {
+    /// Parses the arguments of `CLIENT TRACKING ON|OFF [REDIRECT id] [PREFIX prefix ...]
+    /// [BCAST] [OPTIN] [OPTOUT] [NOLOOP]`, options in any order and case. Whether the
+    /// options make sense together is checked by [`TrackingOptions::apply_to`].
+    pub fn parse(arguments: &[String]) -> Result<Self, String> {
+        let (switch, arguments) = arguments.split_first().ok_or("syntax error")?;
+        let mut options = TrackingOptions::default();
+        let mut arguments = arguments.iter();
+        while let Some(argument) = arguments.next() {
+            match argument.to_lowercase().as_str() {
+                "redirect" => {
+                    let id = arguments.next().ok_or("syntax error")?;
+                    if options.redirect.is_some() {
+                        return Err("A client can only redirect to a single other client".into());
+                    }
+                    let id = id
+                        .parse()
+                        .map_err(|_| "value is not an integer or out of range")?;
+                    options.redirect = Some(id);
+                }
+                "prefix" => {
+                    let prefix = arguments.next().ok_or("syntax error")?;
+                    options.prefixes.push(prefix.as_bytes().to_vec());
+                }
+                "bcast" => options.bcast = true,
+                "optin" => options.optin = true,
+                "optout" => options.optout = true,
+                "noloop" => options.noloop = true,
+                _ => return Err("syntax error".to_string()),
+            }
+        }
+        match switch.to_lowercase().as_str() {
+            "on" => Ok(TrackingCommand::On(options)),
+            "off" => Ok(TrackingCommand::Off),
+            _ => Err("syntax error".to_string()),
+        }
+    }
+}
+
+impl TrackingOptions {
+    /// Checks that the options can be applied to a client tracking with `current`
+    /// options, if any, and returns the options the client ends up with. Like Redis, the
+    /// mode can't change while tracking is on, while prefixes add up.
+    pub fn apply_to(self, current: Option<&TrackingOptions>) -> Result<Self, String> {
+        if !self.bcast && !self.prefixes.is_empty() {
+            return Err("PREFIX option requires BCAST mode to be enabled".to_string());
+        }
+        if current.is_some_and(|current| current.bcast != self.bcast) {
+            return Err(
+                "You can't switch BCAST mode on/off before disabling tracking for \
+                 this client, and then re-enabling it with a different mode."
+                    .to_string(),
+            );
+        }
+        if self.bcast && (self.optin || self.optout) {
+            return Err("OPTIN and OPTOUT are not compatible with BCAST".to_string());
+        }
+        if self.optin && self.optout {
+            return Err("You can't use both OPTIN and OPTOUT".to_string());
+        }
+        if current
+            .is_some_and(|current| (self.optin && current.optout) || (self.optout && current.optin))
+        {
+            return Err(
+                "You can't switch OPTIN/OPTOUT mode before disabling tracking for \
+                 this client, and then re-enabling it with a different mode."
+                    .to_string(),
+            );
+        }
+
+        let mut options = self;
+        if !options.bcast {
+            return Ok(options);
+        }
+        let existing = current
+            .map(|current| &current.prefixes[..])
+            .unwrap_or_default();
+        for (i, prefix) in options.prefixes.iter().enumerate() {
+            if let Some(other) = existing.iter().find(|other| overlap(prefix, other)) {
+                return Err(format!(
+                    "Prefix '{}' overlaps with an existing prefix '{}'. Prefixes for a \
+                     single client must not overlap.",
+                    String::from_utf8_lossy(prefix),
+                    String::from_utf8_lossy(other)
+                ));
+            }
+            if let Some(other) = options.prefixes[i + 1..]
+                .iter()
+                .find(|other| overlap(prefix, other))
+            {
+                return Err(format!(
+                    "Prefix '{}' overlaps with another provided prefix '{}'. Prefixes for a \
+                     single client must not overlap.",
+                    String::from_utf8_lossy(prefix),
+                    String::from_utf8_lossy(other)
+                ));
+            }
+        }
+        // without prefixes, every key is broadcast
+        if options.prefixes.is_empty() && existing.is_empty() {
+            options.prefixes.push(vec![]);
+        }
+        options.prefixes.splice(0..0, existing.iter().cloned());
+        Ok(options)
+    }
+}
+
+/// Whether a key could start with both prefixes.
+fn overlap(prefix: &[u8], other: &[u8]) -> bool {
+    prefix.starts_with(other) || other.starts_with(prefix)
+}
+
+#[derive(Debug, Default)]
+struct Table {
+    /// Clients that may have cached each key, in default mode.
+    keys: HashMap<Vec<u8>, BTreeSet<u64>>,
+    /// Clients in broadcasting mode by prefix, the empty prefix matches every key.
+    prefixes: BTreeMap<Vec<u8>, BTreeSet<u64>>,
+    /// Number of clients with tracking on.
+    clients: usize,
+}
+
+/// Which clients must be told when keys change, for client side caching.
+#[derive(Debug)]
+pub struct Tracking {
+    table: Mutex<Table>,
+}
+
+impl Tracking {
+    pub fn new() -> Self {
+        Self {
+            table: Mutex::new(Table::default()),
+        }
+    }
+
+    /// Turns tracking on for a client that tracked with `previous` options, if any, and
+    /// now tracks with `options`. The store only records the keys it modifies while
+    /// some client is tracking.
+    pub fn enable(
+        &self,
+        store: &ShardedStore,
+        client_id: u64,
+        previous: Option<&TrackingOptions>,
+        options: &TrackingOptions,
+    ) {
+        let mut table = self.table.lock().unwrap();
+        if previous.is_none() {
+            table.clients += 1;
+            if table.clients == 1 {
+                store.track_modified();
+            }
+        }
+        for prefix in options.prefixes.iter().filter(|_| options.bcast) {
+            table
+                .prefixes
+                .entry(prefix.clone())
+                .or_default()
+                .insert(client_id);
+        }
+    }
+
+    /// Turns tracking off for a client that tracked with `options`. Keys it read are
+    /// forgotten lazily, once they get invalidated.
+    pub fn disable(&self, store: &ShardedStore, client_id: u64, options: &TrackingOptions) {
+        let mut table = self.table.lock().unwrap();
+        table.clients -= 1;
+        for prefix in options.prefixes.iter().filter(|_| options.bcast) {
+            if let Some(clients) = table.prefixes.get_mut(prefix) {
+                clients.remove(&client_id);
+                if clients.is_empty() {
+                    table.prefixes.remove(prefix);
+                }
+            }
+        }
+        if table.clients == 0 {
+            store.untrack_modified();
+        }
+    }
+
+    /// Remembers that a client in default mode read `keys`.
+    pub fn remember(&self, client_id: u64, keys: Vec<Vec<u8>>) {
+        let mut table = self.table.lock().unwrap();
+        for key in keys {
+            table.keys.entry(key).or_default().insert(client_id);
+        }
+    }
+
+    /// Returns which of the modified `keys` every client must be told about. Keys read
+    /// by clients in default mode are forgotten, until they read them again.
+    pub fn invalidated(&self, keys: &[Vec<u8>]) -> BTreeMap<u64, Vec<Vec<u8>>> {
+        let mut table = self.table.lock().unwrap();
+        let mut invalidated: BTreeMap<u64, Vec<Vec<u8>>> = BTreeMap::new();
+        for key in keys {
+            let readers = table.keys.remove(key).unwrap_or_default();
+            let subscribers = table
+                .prefixes
+                .iter()
+                .filter(|(prefix, _)| key.starts_with(prefix))
+                .flat_map(|(_, clients)| clients);
+            let clients: BTreeSet<u64> = readers.iter().chain(subscribers).copied().collect();
+            for client_id in clients {
+                invalidated.entry(client_id).or_default().push(key.clone());
+            }
+        }
+        invalidated
+    }
+
+    /// Number of clients with tracking on.
+    pub fn num_clients(&self) -> usize {
+        self.table.lock().unwrap().clients
+    }
+
+    /// Number of keys read by clients in default mode.
+    pub fn num_keys(&self) -> usize {
+        self.table.lock().unwrap().keys.len()
+    }
+
+    /// Number of prefixes clients in broadcasting mode subscribed to.
+    pub fn num_prefixes(&self) -> usize {
+        self.table.lock().unwrap().prefixes.len()
+    }
+}
+
+impl Default for Tracking {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(arguments: &[&str]) -> Result<TrackingCommand, String> {
+        let arguments: Vec<String> = arguments.iter().map(|s| s.to_string()).collect();
+        TrackingCommand::parse(&arguments)
+    }
+
+    fn on(arguments: &[&str]) -> TrackingOptions {
+        match parse(arguments).unwrap() {
+            TrackingCommand::On(options) => options,
+            TrackingCommand::Off => panic!("expected tracking on"),
+        }
+    }
+
+    #[test]
+    fn parses_tracking_options() {
+        assert_eq!(parse(&["off"]), Ok(TrackingCommand::Off));
+        assert_eq!(
+            on(&["ON", "redirect", "7", "BCAST", "PREFIX", "a:", "prefix", "b:", "NOLOOP"]),
+            TrackingOptions {
+                redirect: Some(7),
+                bcast: true,
+                prefixes: vec![b"a:".to_vec(), b"b:".to_vec()],
+                noloop: true,
+                ..TrackingOptions::default()
+            }
+        );
+        assert_eq!(parse(&[]), Err("syntax error".to_string()));
+        assert_eq!(parse(&["maybe"]), Err("syntax error".to_string()));
+        assert_eq!(parse(&["on", "prefix"]), Err("syntax error".to_string()));
+        assert!(parse(&["on", "redirect", "x"]).is_err());
+        assert!(parse(&["on", "redirect", "1", "redirect", "2"]).is_err());
+    }
+
+    #[test]
+    fn refuses_incompatible_options() {
+        let apply =
+            |arguments: &[&str], current: Option<&TrackingOptions>| on(arguments).apply_to(current);
+        assert!(apply(&["on", "prefix", "a"], None)
+            .unwrap_err()
+            .starts_with("PREFIX option requires BCAST"));
+        assert!(apply(&["on", "optin", "optout"], None).is_err());
+        assert!(apply(&["on", "bcast", "optin"], None).is_err());
+
+        let optin = apply(&["on", "optin"], None).unwrap();
+        assert!(apply(&["on", "optout"], Some(&optin)).is_err());
+        assert!(apply(&["on", "bcast"], Some(&optin)).is_err());
+        assert_eq!(apply(&["on", "optin"], Some(&optin)), Ok(optin.clone()));
+
+        let bcast = apply(&["on", "bcast", "prefix", "user:"], None).unwrap();
+        assert!(apply(&["on", "bcast", "prefix", "user:1"], Some(&bcast))
+            .unwrap_err()
+            .contains("overlaps with an existing prefix 'user:'"));
+        assert!(apply(&["on", "bcast", "prefix", "a", "prefix", "ab"], None)
+            .unwrap_err()
+            .contains("overlaps with another provided prefix 'ab'"));
+        assert_eq!(
+            apply(&["on", "bcast", "prefix", "post:"], Some(&bcast))
+                .unwrap()
+                .prefixes,
+            vec![b"user:".to_vec(), b"post:".to_vec()]
+        );
+        assert_eq!(
+            apply(&["on", "bcast"], None).unwrap().prefixes,
+            vec![vec![]]
+        );
+    }
+
+    #[test]
+    fn invalidates_keys_read_once_and_prefixes_always() {
+        let store = ShardedStore::new(1);
+        let tracking = Tracking::new();
+        let default = TrackingOptions::default();
+        let bcast = on(&["on", "bcast", "prefix", "user:"])
+            .apply_to(None)
+            .unwrap();
+        tracking.enable(&store, 1, None, &default);
+        tracking.enable(&store, 2, None, &bcast);
+        assert_eq!(tracking.num_clients(), 2);
+
+        tracking.remember(1, vec![b"user:1".to_vec(), b"post:1".to_vec()]);
+        let keys = vec![b"user:1".to_vec(), b"post:1".to_vec(), b"user:2".to_vec()];
+        let invalidated = tracking.invalidated(&keys);
+        assert_eq!(
+            invalidated.get(&1),
+            Some(&vec![b"user:1".to_vec(), b"post:1".to_vec()])
+        );
+        assert_eq!(
+            invalidated.get(&2),
+            Some(&vec![b"user:1".to_vec(), b"user:2".to_vec()])
+        );
+
+        // until read again, keys are only invalidated once
+        let invalidated = tracking.invalidated(&keys);
+        assert!(!invalidated.contains_key(&1));
+        tracking.disable(&store, 2, &bcast);
+        assert!(tracking.invalidated(&keys).is_empty());
+        assert_eq!((tracking.num_clients(), tracking.num_prefixes()), (1, 0));
+    }
+}